@@ -0,0 +1,76 @@
+use std::hint::black_box;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+use tpk::{Element, Entry, Reader, SliceReader, Writer};
+
+const ENTRY_COUNT: usize = 100_000;
+const ITERATIONS: u32 = 20;
+
+fn sample_document() -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut writer = Writer::new(&mut output);
+    for i in 0..ENTRY_COUNT {
+        writer
+            .write_entry(&Entry {
+                name: format!("entry_{}", i),
+                elements: vec![
+                    Element::UInteger32(i as u32),
+                    Element::String(String::from("some string value")),
+                ],
+            })
+            .unwrap();
+    }
+    output
+}
+
+// Names of 64 bytes or more have a length prefix of more than one byte.
+fn long_marker_document() -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut writer = Writer::new(&mut output);
+    for i in 0..ENTRY_COUNT {
+        writer
+            .write_entry(&Entry {
+                name: format!("{:a>200}", i),
+                elements: vec![Element::UInteger32(i as u32)],
+            })
+            .unwrap();
+    }
+    output
+}
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<36} {:>10.3?}/iter", name, total / ITERATIONS);
+}
+
+fn main() {
+    let input = sample_document();
+
+    bench("Reader::read_entry", || {
+        let mut reader = Reader::new(Cursor::new(input.as_slice()));
+        while let Some(entry) = reader.read_entry().unwrap() {
+            black_box(entry);
+        }
+    });
+
+    bench("SliceReader::read_entry_ref", || {
+        let mut reader = SliceReader::new(input.as_slice());
+        while let Some(entry) = reader.read_entry_ref().unwrap() {
+            black_box(entry);
+        }
+    });
+
+    let input = long_marker_document();
+
+    bench("Reader::read_entry (long markers)", || {
+        let mut reader = Reader::new(Cursor::new(input.as_slice()));
+        while let Some(entry) = reader.read_entry().unwrap() {
+            black_box(entry);
+        }
+    });
+}
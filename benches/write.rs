@@ -0,0 +1,41 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tpk::{Element, Writer};
+
+const ELEMENT_COUNT: usize = 1_000_000;
+const ITERATIONS: u32 = 10;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<40} {:>10.3?}/iter", name, total / ITERATIONS);
+}
+
+fn main() {
+    let elements: Vec<Element> = (0..ELEMENT_COUNT)
+        .map(|i| Element::String(format!("value {}", i)))
+        .collect();
+    let mut output = Vec::with_capacity(ELEMENT_COUNT * 16);
+
+    bench("Writer::write_element", || {
+        output.clear();
+        let mut writer = Writer::new(&mut output);
+        for element in &elements {
+            writer.write_element(element).unwrap();
+        }
+        black_box(&output);
+    });
+
+    bench("Writer::write_element (scratch buffer)", || {
+        output.clear();
+        let mut writer = Writer::new(&mut output).scratch_buffer(true);
+        for element in &elements {
+            writer.write_element(element).unwrap();
+        }
+        black_box(&output);
+    });
+}
@@ -0,0 +1,632 @@
+//! Deserialization of TPK data into Rust data structures, using serde.
+//!
+//! TPK data is deserialized following the rules of [Value::from_document]: entry names become
+//! map keys (or struct field names), folders become nested maps, and collections become
+//! sequences. Data read from a source is deserialized as it is read, by a [Deserializer], while
+//! [documents][Document] are turned into a [Value] tree first.
+
+use crate::read;
+use crate::value::Value;
+use crate::{Document, Element, Reader};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::fmt::Display;
+use std::io;
+use thiserror::Error;
+
+/// Representation of a TPK deserialization error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The TPK data could not be read.
+    #[error(transparent)]
+    Read(#[from] read::Error),
+
+    /// The TPK data does not match the structure of the deserialized type.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// Representation of a TPK deserialization result.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Deserialize an instance of type `T` from the TPK data of the given source.
+///
+/// The data is deserialized as it is read, by a [Deserializer].
+pub fn from_reader<T, R>(read: R) -> Result<T>
+where
+    T: DeserializeOwned,
+    R: io::Read,
+{
+    T::deserialize(&mut Deserializer::new(Reader::new(read)))
+}
+
+/// Deserialize an instance of type `T` from a [Document].
+pub fn from_document<T>(document: &Document) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(Value::from_document(document))
+}
+
+/// A deserializer of TPK data, driven by a [Reader].
+///
+/// The entries of the reader are deserialized as they are read, following the rules of
+/// [Value::from_document], so that only the element being deserialized is held in memory. The
+/// data is deserialized as a map, whose values are the elements of each entry. Folders are
+/// nested at most as deep as the [maximum nesting depth][Reader::max_depth] of the reader.
+pub struct Deserializer<T> {
+    reader: Reader<T>,
+    depth: usize,
+    closing: usize,
+}
+
+impl<T> Deserializer<T>
+where
+    T: io::Read,
+{
+    /// Create a new [Deserializer] reading from the given [Reader].
+    pub fn new(reader: Reader<T>) -> Deserializer<T> {
+        Deserializer {
+            reader,
+            depth: 0,
+            closing: 0,
+        }
+    }
+
+    /// Whether the entry being read has no more elements.
+    fn at_entry_end(&mut self) -> Result<bool> {
+        Ok(match self.reader.peek_type_byte()? {
+            Some(type_byte) => type_byte & 0b10000000 != 0,
+            None => true,
+        })
+    }
+
+    /// Read the next element of the entry being read, if any.
+    fn next_entry_element(&mut self) -> Result<Option<Element>> {
+        if self.at_entry_end()? {
+            return Ok(None);
+        }
+        Ok(self.reader.read_element()?)
+    }
+}
+
+impl<'de, T> de::Deserializer<'de> for &mut Deserializer<T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut map = EntryMap::root(self);
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        seq unit unit_struct tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Map access over the entries of a [Deserializer], up to the end of the folder they are in.
+struct EntryMap<'a, T> {
+    de: &'a mut Deserializer<T>,
+    nested: bool,
+    ended: bool,
+}
+
+impl<'a, T> EntryMap<'a, T>
+where
+    T: io::Read,
+{
+    fn root(de: &'a mut Deserializer<T>) -> EntryMap<'a, T> {
+        EntryMap {
+            de,
+            nested: false,
+            ended: false,
+        }
+    }
+
+    fn nested(de: &'a mut Deserializer<T>) -> Result<EntryMap<'a, T>> {
+        de.reader.check_depth(de.depth)?;
+        de.depth += 1;
+        Ok(EntryMap {
+            de,
+            nested: true,
+            ended: false,
+        })
+    }
+
+    fn close<K>(&mut self) -> Result<Option<K>> {
+        if !self.ended && self.nested {
+            self.de.depth -= 1;
+        }
+        self.ended = true;
+        Ok(None)
+    }
+
+    /// Make sure that every entry of the map has been visited.
+    fn end(&self) -> Result<()> {
+        match self.ended {
+            true => Ok(()),
+            false => Err(de::Error::custom("map holds more entries than expected")),
+        }
+    }
+}
+
+impl<'de, T> de::MapAccess<'de> for EntryMap<'_, T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.ended {
+            return Ok(None);
+        }
+        loop {
+            if self.de.closing > 0 && self.nested {
+                self.de.closing -= 1;
+                return self.close();
+            }
+            let name = match self.de.reader.peek_element()? {
+                None => return self.close(),
+                Some(Element::Marker(name)) => {
+                    let name = name.clone();
+                    self.de.reader.read_element()?;
+                    name
+                }
+                // Elements preceding the first marker form an entry of their own.
+                Some(_) => self.de.reader.current_name().to_owned(),
+            };
+            if self.de.at_entry_end()? {
+                match name.as_str() {
+                    ".." if self.nested => return self.close(),
+                    ".." => continue,
+                    "/" => {
+                        self.de.closing = self.de.depth;
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+            return seed.deserialize(name.into_deserializer()).map(Some);
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value> {
+        seed.deserialize(EntryDeserializer::new(self.de)?)
+    }
+}
+
+/// The shape of the elements of an entry, which decides how they are deserialized.
+enum Shape {
+    /// The entry has no elements.
+    Empty,
+    /// The entry holds a single element.
+    Single(Value),
+    /// The entry holds several elements, or a collection, starting with the given element if it
+    /// has already been read.
+    Seq(Option<Element>),
+    /// The entry holds a single folder.
+    Folder,
+}
+
+/// Deserializer for the elements of an entry, read from a [Deserializer].
+struct EntryDeserializer<'a, T> {
+    de: &'a mut Deserializer<T>,
+    shape: Shape,
+}
+
+impl<'a, T> EntryDeserializer<'a, T>
+where
+    T: io::Read,
+{
+    fn new(de: &'a mut Deserializer<T>) -> Result<EntryDeserializer<'a, T>> {
+        let shape = match de.next_entry_element()? {
+            None => Shape::Empty,
+            Some(Element::Collection | Element::TypedCollection { .. }) => Shape::Seq(None),
+            Some(first) => match (first, de.at_entry_end()?) {
+                (Element::Folder, true) => Shape::Folder,
+                (element, true) => Shape::Single(element.into()),
+                (element, false) => Shape::Seq(Some(element)),
+            },
+        };
+        Ok(EntryDeserializer { de, shape })
+    }
+}
+
+impl<'de, T> de::Deserializer<'de> for EntryDeserializer<'_, T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.shape {
+            // Entries without any element are empty lists.
+            Shape::Empty => Value::List(Vec::new()).deserialize_any(visitor),
+            Shape::Single(value) => value.deserialize_any(visitor),
+            Shape::Seq(first) => {
+                let mut seq = EntrySeq { de: self.de, first };
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Shape::Folder => {
+                let mut map = EntryMap::nested(self.de)?;
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.shape {
+            Shape::Single(value) => value.deserialize_seq(visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.shape {
+            Shape::Empty => visitor.visit_none(),
+            // Empty collections are empty lists as well.
+            Shape::Seq(None) if self.de.at_entry_end()? => visitor.visit_none(),
+            Shape::Single(value) => value.deserialize_option(visitor),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.shape {
+            Shape::Empty => visitor.visit_unit(),
+            Shape::Seq(None) if self.de.at_entry_end()? => visitor.visit_unit(),
+            Shape::Single(value) => value.deserialize_unit(visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.shape {
+            Shape::Single(value) => value.deserialize_enum(name, variants, visitor),
+            Shape::Folder => visitor.visit_enum(FolderEnum {
+                map: EntryMap::nested(self.de)?,
+            }),
+            Shape::Empty | Shape::Seq(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Seq,
+                &"a string or a map with a single entry",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Sequence access over the elements of an entry.
+struct EntrySeq<'a, T> {
+    de: &'a mut Deserializer<T>,
+    first: Option<Element>,
+}
+
+impl<T> EntrySeq<'_, T>
+where
+    T: io::Read,
+{
+    /// Make sure that every element of the entry has been visited.
+    fn end(&mut self) -> Result<()> {
+        match self.first.is_none() && self.de.at_entry_end()? {
+            true => Ok(()),
+            false => Err(de::Error::custom("entry holds more elements than expected")),
+        }
+    }
+}
+
+impl<'de, T> de::SeqAccess<'de> for EntrySeq<'_, T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>> {
+        let element = match self.first.take() {
+            Some(element) => element,
+            None => match self.de.next_entry_element()? {
+                Some(element) => element,
+                None => return Ok(None),
+            },
+        };
+        seed.deserialize(Value::from(element)).map(Some)
+    }
+}
+
+/// Enum access over a folder holding a single entry, named after the variant.
+struct FolderEnum<'a, T> {
+    map: EntryMap<'a, T>,
+}
+
+impl<T> FolderEnum<'_, T>
+where
+    T: io::Read,
+{
+    /// Make sure that the folder holds no other entry than the variant.
+    fn end(mut self) -> Result<()> {
+        match de::MapAccess::next_key::<de::IgnoredAny>(&mut self.map)? {
+            None => Ok(()),
+            Some(_) => Err(de::Error::invalid_length(2, &"a map with a single entry")),
+        }
+    }
+}
+
+impl<'de, T> de::EnumAccess<'de> for FolderEnum<'_, T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(mut self, seed: S) -> Result<(S::Value, Self)> {
+        match de::MapAccess::next_key_seed(&mut self.map, seed)? {
+            Some(variant) => Ok((variant, self)),
+            None => Err(de::Error::invalid_length(0, &"a map with a single entry")),
+        }
+    }
+}
+
+impl<'de, T> de::VariantAccess<'de> for FolderEnum<'_, T>
+where
+    T: io::Read,
+{
+    type Error = Error;
+
+    fn unit_variant(mut self) -> Result<()> {
+        de::MapAccess::next_value::<()>(&mut self.map)?;
+        self.end()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(mut self, seed: S) -> Result<S::Value> {
+        let value = de::MapAccess::next_value_seed(&mut self.map, seed)?;
+        self.end()?;
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        let value =
+            de::Deserializer::deserialize_seq(EntryDeserializer::new(self.map.de)?, visitor)?;
+        self.end()?;
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let value =
+            de::Deserializer::deserialize_map(EntryDeserializer::new(self.map.de)?, visitor)?;
+        self.end()?;
+        Ok(value)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Value::Int(val) => visitor.visit_i64(val),
+            Value::UInt(val) => visitor.visit_u64(val),
+            Value::Float(val) => visitor.visit_f64(val),
+            Value::Bool(val) => visitor.visit_bool(val),
+            Value::Str(val) => visitor.visit_string(val),
+            Value::Bytes(val) => visitor.visit_byte_buf(val),
+            Value::List(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(entries) => {
+                let mut map = MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            // Blobs are also sequences of bytes, e.g. for `Vec<u8>`.
+            Value::Bytes(val) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(val.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            // Entries without any element are empty lists.
+            Value::List(ref items) if items.is_empty() => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Value::List(ref items) if items.is_empty() => visitor.visit_unit(),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self {
+            Value::Str(variant) => visitor.visit_enum(EnumDeserializer {
+                variant,
+                value: None,
+            }),
+            Value::Map(mut entries) if entries.len() == 1 => {
+                let (variant, value) = entries.remove(0);
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            value => Err(de::Error::invalid_type(
+                unexpected(&value),
+                &"a string or a map with a single entry",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, VariantDeserializer)>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<Value>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            None => Ok(()),
+            Some(value) => de::Deserialize::deserialize(value),
+        }
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"a newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_seq(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_map(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"a struct variant",
+            )),
+        }
+    }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match *value {
+        Value::Int(val) => de::Unexpected::Signed(val),
+        Value::UInt(val) => de::Unexpected::Unsigned(val),
+        Value::Float(val) => de::Unexpected::Float(val),
+        Value::Bool(val) => de::Unexpected::Bool(val),
+        Value::Str(ref val) => de::Unexpected::Str(val),
+        Value::Bytes(ref val) => de::Unexpected::Bytes(val),
+        Value::List(_) => de::Unexpected::Seq,
+        Value::Map(_) => de::Unexpected::Map,
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{Document, Element, Entry};
+use std::collections::HashMap;
+
+/// Representation of a difference between two [documents][Document].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// An entry only exists in the new document.
+    Added(String),
+    /// An entry only exists in the old document.
+    Removed(String),
+    /// An entry exists in both documents, but its elements hold different values.
+    Modified {
+        name: String,
+        old: Vec<Element>,
+        new: Vec<Element>,
+    },
+}
+
+/// Compute the differences between two [documents][Document].
+///
+/// Entries are matched by name. When a name appears several times in a document, its entries
+/// are matched in order of appearance, i.e. the second entry named `foo` in the old document is
+/// compared to the second entry named `foo` in the new document.
+///
+/// Elements are compared with [Element::value_eq], meaning that a change of width of an integer
+/// holding the same value is not considered a modification.
+///
+/// Removed and modified entries are returned first, in the order of the old document, followed
+/// by the added entries, in the order of the new document.
+pub fn diff(old: &Document, new: &Document) -> Vec<Change> {
+    let old_entries = index_entries(old);
+    let new_entries = index_entries(new);
+    let old_lookup: HashMap<_, _> = old_entries.iter().copied().collect();
+    let new_lookup: HashMap<_, _> = new_entries.iter().copied().collect();
+
+    let mut changes = Vec::new();
+    for (key, old_entry) in &old_entries {
+        match new_lookup.get(key) {
+            None => changes.push(Change::Removed(old_entry.name.clone())),
+            Some(new_entry) if !old_entry.value_eq(new_entry) => changes.push(Change::Modified {
+                name: old_entry.name.clone(),
+                old: old_entry.elements.clone(),
+                new: new_entry.elements.clone(),
+            }),
+            _ => (),
+        }
+    }
+    for (key, new_entry) in &new_entries {
+        if !old_lookup.contains_key(key) {
+            changes.push(Change::Added(new_entry.name.clone()));
+        }
+    }
+    changes
+}
+
+fn index_entries(document: &Document) -> Vec<((&str, usize), &Entry)> {
+    let mut occurrences = HashMap::new();
+    document
+        .entries
+        .iter()
+        .map(|entry| {
+            let occurrence = occurrences.entry(entry.name.as_str()).or_insert(0usize);
+            let key = (entry.name.as_str(), *occurrence);
+            *occurrence += 1;
+            (key, entry)
+        })
+        .collect()
+}
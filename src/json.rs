@@ -0,0 +1,93 @@
+use crate::read;
+use crate::value::{entry_value, Value};
+use crate::Reader;
+use serde_json::{Map, Number};
+use std::io;
+
+/// A reader converting TPK data into [JSON lines](https://jsonlines.org/).
+///
+/// This structure wraps a TPK [reader][Reader], and implements [io::Read] by decoding one
+/// [entry][crate::Entry] at a time and producing its JSON serialization, followed by a newline.
+/// Each line is an object holding a single key, the name of the entry, mapped to the
+/// [value][Value] of the entry. This makes it possible to [copy][io::copy] TPK data into any
+/// line-oriented JSON consumer.
+///
+/// Blobs are serialized as arrays of bytes, and non-finite floating-point numbers, which JSON
+/// cannot represent, as `null`. Read errors are reported as [io::ErrorKind::InvalidData] errors
+/// wrapping the [TPK error][read::Error], except for I/O errors of the source which are reported
+/// as-is.
+pub struct JsonLinesReader<T> {
+    reader: Reader<T>,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl<T> JsonLinesReader<T>
+where
+    T: io::Read,
+{
+    /// Create a new [JSON lines reader][JsonLinesReader] from the given TPK reader.
+    pub fn new(reader: Reader<T>) -> JsonLinesReader<T> {
+        JsonLinesReader {
+            reader,
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<bool> {
+        let entry = match self.reader.read_entry() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return Ok(false),
+            Err(read::Error::Io { source }) => return Err(source),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let mut object = Map::new();
+        object.insert(entry.name.clone(), entry_value(&entry).into());
+
+        self.line.clear();
+        self.pos = 0;
+        serde_json::to_writer(&mut self.line, &object)?;
+        self.line.push(b'\n');
+        Ok(true)
+    }
+}
+
+impl<T> io::Read for JsonLinesReader<T>
+where
+    T: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() && !self.next_line()? {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.line.len() - self.pos);
+        buf[..len].copy_from_slice(&self.line[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> serde_json::Value {
+        match value {
+            Value::Int(val) => serde_json::Value::Number(val.into()),
+            Value::UInt(val) => serde_json::Value::Number(val.into()),
+            Value::Float(val) => Number::from_f64(val).map_or(serde_json::Value::Null, Into::into),
+            Value::Bool(val) => serde_json::Value::Bool(val),
+            Value::Str(val) => serde_json::Value::String(val),
+            Value::Bytes(val) => {
+                serde_json::Value::Array(val.into_iter().map(Into::into).collect())
+            }
+            Value::List(values) => {
+                serde_json::Value::Array(values.into_iter().map(Into::into).collect())
+            }
+            Value::Map(pairs) => serde_json::Value::Object(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
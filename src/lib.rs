@@ -1,7 +1,31 @@
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod diff;
+#[cfg(feature = "json")]
+pub mod json;
 mod model;
 pub mod read;
+pub mod schema;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod stats;
+pub mod stream;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod text;
+pub mod value;
+pub mod visit;
 pub mod write;
 
-pub use model::{Element, Entry};
-pub use read::Reader;
-pub use write::Writer;
+#[cfg(feature = "bytes")]
+pub use model::ElementBytes;
+pub use model::{
+    type_byte_version, Document, Element, ElementCow, ElementKind, ElementRef, Entry, EntryRef,
+    RawElement, Scalar, BLOB_REF_EXTENSION_ID, COMPRESSED_BLOB_EXTENSION_ID,
+    DELTA_U64_EXTENSION_ID, DURATION_EXTENSION_ID, ENDIANNESS_MAGIC, FLAGGED_EXTENSION_ID,
+    LATEST_VERSION, MAP_EXTENSION_ID, STRING_REF_EXTENSION_ID, TYPED_COLLECTION_EXTENSION_ID,
+};
+pub use read::{
+    decode_entry, FramedReader, LogReader, ReadState, Reader, ResumableReader, SliceReader,
+};
+pub use write::{LogWriter, Writer};
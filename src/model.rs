@@ -1,8 +1,13 @@
+use crate::schema::{EntrySchema, SchemaError};
+use crate::write;
+use std::borrow::Cow;
+use std::mem;
+
 /// Representation of a TPK element.
 ///
 /// TPK elements are the building block of Tiwind Packages: they contain a single piece of data or
 /// metadata but, when put together, can describe complex and structured data.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Element {
     /// Represents a TPK marker.
     Marker(String),
@@ -18,6 +23,8 @@ pub enum Element {
     Integer32(i32),
     /// Represents a signed 64-bit TPK integer.
     Integer64(i64),
+    /// Represents a signed 128-bit TPK integer.
+    Integer128(i128),
     /// Represents a unsigned 8-bit TPK integer.
     UInteger8(u8),
     /// Represents a unsigned 16-bit TPK integer.
@@ -26,6 +33,8 @@ pub enum Element {
     UInteger32(u32),
     /// Represents a unsigned 64-bit TPK integer.
     UInteger64(u64),
+    /// Represents a unsigned 128-bit TPK integer.
+    UInteger128(u128),
     /// Represents a signed 32-bit TPK single precision floating-point number.
     Float32(f32),
     /// Represents a signed 64-bit TPK double precision floating-point number.
@@ -36,17 +45,507 @@ pub enum Element {
     String(String),
     /// Represents a TPK binary blob.
     Blob(Vec<u8>),
+    /// Represents a TPK NUL-terminated UTF-8 string.
+    ///
+    /// Unlike [strings][Element::String], NUL-terminated strings are not prefixed by their size,
+    /// and as such cannot contain any NUL character.
+    CString(String),
+    /// Represents a TPK extension element, holding an application-defined payload.
+    ///
+    /// Extension identifiers from 0 to 127 are reserved for extensions defined by this crate,
+    /// while identifiers from 128 to 255 are free to use by applications.
+    Extension { id: u8, data: Vec<u8> },
+    /// Represents a delta-encoded collection of unsigned 64-bit integers.
+    ///
+    /// This is written as an [extension element][Element::Extension] with the
+    /// [DELTA_U64_EXTENSION_ID] identifier, whose payload holds the first value followed by the
+    /// difference between each value and the previous one. This is much more compact than a
+    /// [collection][Element::Collection] for sequences of close values, such as timestamps.
+    DeltaU64(Vec<u64>),
+    /// Represents a reference to a previously written [blob][Element::Blob].
+    ///
+    /// Blobs are numbered from zero in the order they appear in the data. This is written as an
+    /// [extension element][Element::Extension] with the [BLOB_REF_EXTENSION_ID] identifier,
+    /// whose payload holds the index of the referenced blob as a little-endian `u32`. These
+    /// elements are produced by [writers deduplicating blobs][crate::Writer::dedup_blobs].
+    BlobRef(u32),
+    /// Represents an element carrying application-defined flags, such as "encrypted" or
+    /// "compressed".
+    ///
+    /// This is written as an [extension element][Element::Extension] with the
+    /// [FLAGGED_EXTENSION_ID] identifier, whose payload holds the flags byte followed by the
+    /// inner element. The inner element cannot be a [marker][Element::Marker].
+    Flagged { flags: u8, inner: Box<Element> },
+    /// Represents a signed duration, in nanoseconds.
+    ///
+    /// This is written as an [extension element][Element::Extension] with the
+    /// [DURATION_EXTENSION_ID] identifier, whose payload holds the number of nanoseconds as a
+    /// little-endian `i64`. Negative durations are allowed, e.g. to account for clock skew.
+    Duration(i64),
+    /// Represents the given number of reserved bytes, which do not hold any value.
+    ///
+    /// Padding is written as zero bytes, which can be overwritten in place later on, for example
+    /// to patch a fixed-layout file. Readers do not check the content of padding: it is read
+    /// back as a padding element of the same size, whatever bytes it holds.
+    Padding(usize),
+    /// Represents a [collection][Element::Collection] announcing the type and number of its
+    /// elements.
+    ///
+    /// This is written as an [extension element][Element::Extension] with the
+    /// [TYPED_COLLECTION_EXTENSION_ID] identifier, whose payload holds the type byte of the
+    /// elements followed by their number as a little-endian `u32`. The type byte must be the one
+    /// of a number element, such as `0b00100010` for [UInteger32][Element::UInteger32]. The
+    /// elements themselves are written after it, like for regular collections, which lets
+    /// readers validate them and allocate memory for them up front.
+    TypedCollection { type_byte: u8, count: u32 },
+    /// Represents an ordered map of string keys to elements.
+    ///
+    /// This is written as an [extension element][Element::Extension] with the [MAP_EXTENSION_ID]
+    /// identifier, whose payload holds the number of pairs as a little-endian `u32`, followed by
+    /// each key as a [string element][Element::String] and its value. Values cannot
+    /// be [markers][Element::Marker]. For small maps, this is more compact than a
+    /// [folder][Element::Folder] holding one entry per key.
+    Map(Vec<(String, Element)>),
+    /// Represents a reference to a previously written [string][Element::String].
+    ///
+    /// Strings are numbered from zero in the order they appear in the
+    /// [string tables][crate::Writer::write_string_table] of the data. This is written as
+    /// an [extension element][Element::Extension] with the [STRING_REF_EXTENSION_ID] identifier,
+    /// whose payload holds the index of the referenced string as a little-endian `u32`. These
+    /// elements are produced by [writers using a string table][crate::Writer::use_string_table].
+    StringRef(u32),
+}
+
+/// Kind of a TPK element, regardless of the data it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementKind {
+    /// The kind of [markers][Element::Marker].
+    Marker,
+    /// The kind of [folders][Element::Folder].
+    Folder,
+    /// The kind of [collections][Element::Collection].
+    Collection,
+    /// The kind of [signed 8-bit integers][Element::Integer8].
+    Integer8,
+    /// The kind of [signed 16-bit integers][Element::Integer16].
+    Integer16,
+    /// The kind of [signed 32-bit integers][Element::Integer32].
+    Integer32,
+    /// The kind of [signed 64-bit integers][Element::Integer64].
+    Integer64,
+    /// The kind of [signed 128-bit integers][Element::Integer128].
+    Integer128,
+    /// The kind of [unsigned 8-bit integers][Element::UInteger8].
+    UInteger8,
+    /// The kind of [unsigned 16-bit integers][Element::UInteger16].
+    UInteger16,
+    /// The kind of [unsigned 32-bit integers][Element::UInteger32].
+    UInteger32,
+    /// The kind of [unsigned 64-bit integers][Element::UInteger64].
+    UInteger64,
+    /// The kind of [unsigned 128-bit integers][Element::UInteger128].
+    UInteger128,
+    /// The kind of [32-bit floating-point numbers][Element::Float32].
+    Float32,
+    /// The kind of [64-bit floating-point numbers][Element::Float64].
+    Float64,
+    /// The kind of [booleans][Element::Boolean], whether true or false.
+    Boolean,
+    /// The kind of [strings][Element::String].
+    String,
+    /// The kind of [blobs][Element::Blob].
+    Blob,
+    /// The kind of [NUL-terminated strings][Element::CString].
+    CString,
+    /// The kind of [extension elements][Element::Extension], including every element written as
+    /// one, such as [durations][Element::Duration] or [maps][Element::Map].
+    Extension,
+    /// The kind of [padding][Element::Padding].
+    Padding,
 }
 
 /// Representation of a TPK entry.
 ///
 /// A TPK entry is composed of a name and zero, one or more associated elements.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Entry {
     pub name: String,
     pub elements: Vec<Element>,
 }
 
+/// Representation of a TPK document.
+///
+/// A TPK document is an ordered list of [entries][Entry], as they appear in a TPK payload.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub entries: Vec<Entry>,
+}
+
+/// Representation of a TPK element along with the raw bytes it was decoded from.
+///
+/// This is returned by [Reader::read_raw_element][crate::Reader::read_raw_element], and makes it
+/// possible to re-emit an element exactly as it was read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawElement {
+    /// The decoded element.
+    pub element: Element,
+    /// The raw bytes of the element, if [retained][crate::Reader::retain_raw].
+    pub raw: Option<Box<[u8]>>,
+}
+
+/// Borrowed representation of a TPK element.
+///
+/// This is the counterpart of [Element] returned by [SliceReader][crate::SliceReader]: markers,
+/// strings and blobs borrow their data from the source slice, while every other element is
+/// stored as an owned [Element], since those never require a heap allocation.
+#[derive(Debug)]
+pub enum ElementRef<'a> {
+    /// Represents a TPK marker.
+    Marker(&'a str),
+    /// Represents a TPK UTF-8 string.
+    String(&'a str),
+    /// Represents a TPK binary blob.
+    Blob(&'a [u8]),
+    /// Represents any other TPK element.
+    Other(Element),
+}
+
+/// Copy-on-write representation of a TPK element.
+///
+/// This is a middle ground between [Element] and [ElementRef]: markers, strings and blobs are
+/// borrowed when read by a [SliceReader][crate::SliceReader], and owned when read by a
+/// [Reader][crate::Reader], which makes it possible to handle both cases with the same code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementCow<'a> {
+    /// Represents a TPK marker.
+    Marker(Cow<'a, str>),
+    /// Represents a TPK UTF-8 string.
+    String(Cow<'a, str>),
+    /// Represents a TPK binary blob.
+    Blob(Cow<'a, [u8]>),
+    /// Represents any other TPK element.
+    Other(Element),
+}
+
+/// Representation of a TPK element sharing the buffer it was read from.
+///
+/// This is returned by [Reader::read_element_bytes][crate::Reader::read_element_bytes]: blobs
+/// are [slices][bytes::Bytes::slice] of the source buffer, which share its allocation instead of
+/// copying it, while every other element is stored as an owned [Element]. Elements can also be
+/// [cloned][Element::clone_shared] or converted into it, so that clones share their blob data.
+#[cfg(feature = "bytes")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementBytes {
+    /// Represents a TPK binary blob.
+    Blob(bytes::Bytes),
+    /// Represents any other TPK element.
+    Other(Element),
+}
+
+/// Borrowed representation of a TPK entry.
+///
+/// This is the counterpart of [Entry] returned by [SliceReader][crate::SliceReader], whose name
+/// and elements borrow their data from the source slice.
+pub struct EntryRef<'a> {
+    pub name: &'a str,
+    pub elements: Vec<ElementRef<'a>>,
+}
+
+impl<'a> ElementRef<'a> {
+    /// Convert this borrowed element into an owned [Element].
+    pub fn to_element(&self) -> Element {
+        match *self {
+            ElementRef::Marker(name) => Element::Marker(name.into()),
+            ElementRef::String(val) => Element::String(val.into()),
+            ElementRef::Blob(val) => Element::Blob(val.into()),
+            ElementRef::Other(ref element) => element.clone(),
+        }
+    }
+}
+
+impl<'a> ElementCow<'a> {
+    /// Convert this element into an owned [Element], copying its data only if it is borrowed.
+    pub fn into_element(self) -> Element {
+        match self {
+            ElementCow::Marker(name) => Element::Marker(name.into_owned()),
+            ElementCow::String(val) => Element::String(val.into_owned()),
+            ElementCow::Blob(val) => Element::Blob(val.into_owned()),
+            ElementCow::Other(element) => element,
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl ElementBytes {
+    /// Convert this element into an owned [Element].
+    ///
+    /// The data of blobs is copied, unless the buffer they share is not used anywhere else.
+    pub fn into_element(self) -> Element {
+        match self {
+            ElementBytes::Blob(val) => Element::Blob(val.into()),
+            ElementBytes::Other(element) => element,
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<Element> for ElementBytes {
+    /// Convert the given element into an [ElementBytes] whose blob data can be shared.
+    ///
+    /// The data of a blob is moved into a [bytes::Bytes] buffer without being copied, and every
+    /// clone of the returned element then shares that allocation. This makes it cheap to hand
+    /// the same large blob to several consumers.
+    fn from(element: Element) -> ElementBytes {
+        match element {
+            Element::Blob(val) => ElementBytes::Blob(val.into()),
+            element => ElementBytes::Other(element),
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<bytes::Bytes> for Element {
+    /// Convert the given buffer into a [blob element][Element::Blob].
+    ///
+    /// The data is copied, unless the buffer is not shared with any other [bytes::Bytes].
+    fn from(val: bytes::Bytes) -> Element {
+        Element::Blob(val.into())
+    }
+}
+
+impl<'a> From<ElementRef<'a>> for ElementCow<'a> {
+    fn from(element: ElementRef<'a>) -> ElementCow<'a> {
+        match element {
+            ElementRef::Marker(name) => ElementCow::Marker(Cow::Borrowed(name)),
+            ElementRef::String(val) => ElementCow::String(Cow::Borrowed(val)),
+            ElementRef::Blob(val) => ElementCow::Blob(Cow::Borrowed(val)),
+            ElementRef::Other(element) => ElementCow::Other(element),
+        }
+    }
+}
+
+impl From<Element> for ElementCow<'static> {
+    fn from(element: Element) -> ElementCow<'static> {
+        match element {
+            Element::Marker(name) => ElementCow::Marker(Cow::Owned(name)),
+            Element::String(val) => ElementCow::String(Cow::Owned(val)),
+            Element::Blob(val) => ElementCow::Blob(Cow::Owned(val)),
+            element => ElementCow::Other(element),
+        }
+    }
+}
+
+macro_rules! impl_from_primitive {
+    ($($primitive:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$primitive> for Element {
+                fn from(val: $primitive) -> Element {
+                    Element::$variant(val)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_primitive! {
+    i8 => Integer8,
+    i16 => Integer16,
+    i32 => Integer32,
+    i64 => Integer64,
+    i128 => Integer128,
+    u8 => UInteger8,
+    u16 => UInteger16,
+    u32 => UInteger32,
+    u64 => UInteger64,
+    u128 => UInteger128,
+    f32 => Float32,
+    f64 => Float64,
+    bool => Boolean,
+    String => String,
+    Vec<u8> => Blob,
+}
+
+/// A numeric type that can be [read in arrays][crate::Reader::read_scalar_array].
+///
+/// This trait is implemented for every primitive type matching a number [element][Element],
+/// and is sealed: it cannot be implemented outside of this crate.
+pub trait Scalar: sealed::Sealed + Copy + Into<Element> {
+    /// Type byte of the elements holding values of this type.
+    const TYPE_BYTE: u8;
+
+    /// Size of the values of this type, in bytes.
+    const SIZE: usize;
+
+    /// Byte array holding the encoded values of this type, [Self::SIZE] bytes long.
+    type Bytes: Default + AsRef<[u8]> + AsMut<[u8]>;
+
+    /// Decode a value from the given little-endian bytes, which are exactly [Self::SIZE] long.
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_scalar {
+    ($($primitive:ty => $type_byte:expr),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $primitive {}
+
+            impl Scalar for $primitive {
+                const TYPE_BYTE: u8 = $type_byte;
+                const SIZE: usize = mem::size_of::<$primitive>();
+                type Bytes = [u8; mem::size_of::<$primitive>()];
+
+                fn from_le_slice(bytes: &[u8]) -> $primitive {
+                    <$primitive>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar! {
+    u8 => 0b00100000,
+    u16 => 0b00100001,
+    u32 => 0b00100010,
+    u64 => 0b00100011,
+    i8 => 0b00100100,
+    i16 => 0b00100101,
+    i32 => 0b00100110,
+    i64 => 0b00100111,
+    u128 => 0b00101000,
+    i128 => 0b00101001,
+    f32 => 0b00101110,
+    f64 => 0b00101111,
+}
+
+impl TryFrom<std::time::Duration> for Element {
+    type Error = write::Error;
+
+    /// Convert the given duration into a [duration element][Element::Duration].
+    ///
+    /// This returns an [Error::DurationTooLong][crate::write::Error::DurationTooLong] if the
+    /// duration is longer than `i64::MAX` nanoseconds (about 292 years).
+    fn try_from(val: std::time::Duration) -> write::Result<Element> {
+        i64::try_from(val.as_nanos())
+            .map(Element::Duration)
+            .map_err(|_| write::Error::DurationTooLong)
+    }
+}
+
+impl From<&str> for Element {
+    fn from(val: &str) -> Element {
+        Element::String(val.into())
+    }
+}
+
+impl<'a> EntryRef<'a> {
+    /// Convert this borrowed entry into an owned [Entry].
+    pub fn to_entry(&self) -> Entry {
+        Entry {
+            name: self.name.into(),
+            elements: self.elements.iter().map(ElementRef::to_element).collect(),
+        }
+    }
+}
+
+impl ElementKind {
+    /// Get the kind of the elements with the given type byte, or `None` if that type byte is
+    /// unknown.
+    pub fn from_type_byte(type_byte: u8) -> Option<ElementKind> {
+        let kind = match type_byte {
+            0b10000000..=0b11111111 => ElementKind::Marker,
+            0b00000000 => ElementKind::Folder,
+            0b00000001 => ElementKind::Collection,
+            0b00100100 => ElementKind::Integer8,
+            0b00100101 => ElementKind::Integer16,
+            0b00100110 => ElementKind::Integer32,
+            0b00100111 => ElementKind::Integer64,
+            0b00101001 => ElementKind::Integer128,
+            0b00100000 => ElementKind::UInteger8,
+            0b00100001 => ElementKind::UInteger16,
+            0b00100010 => ElementKind::UInteger32,
+            0b00100011 => ElementKind::UInteger64,
+            0b00101000 => ElementKind::UInteger128,
+            0b00101110 => ElementKind::Float32,
+            0b00101111 => ElementKind::Float64,
+            0b00110000 | 0b00110001 => ElementKind::Boolean,
+            0b00010000..=0b00010011 => ElementKind::String,
+            0b00010100..=0b00010111 => ElementKind::Blob,
+            0b00011000 => ElementKind::CString,
+            0b00011100..=0b00011111 => ElementKind::Padding,
+            0b01110000..=0b01110011 => ElementKind::Extension,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
 impl Element {
+    /// Create a new [NUL-terminated string element][Element::CString], making sure that it can be
+    /// encoded.
+    ///
+    /// This returns an [Error::InteriorNul][crate::write::Error::InteriorNul] if the string
+    /// contains a NUL character.
+    pub fn c_string<S: Into<String>>(s: S) -> write::Result<Element> {
+        let s = s.into();
+        if let Some(pos) = s.bytes().position(|byte| byte == 0) {
+            return Err(write::Error::InteriorNul { pos });
+        }
+        Ok(Element::CString(s))
+    }
+
+    /// Create a new [extension element][Element::Extension] for an application-defined
+    /// extension.
+    ///
+    /// This returns an [Error::ReservedExtensionId][crate::write::Error::ReservedExtensionId] if
+    /// the identifier is reserved for the extensions defined by this crate, which readers would
+    /// decode as another element.
+    pub fn extension<B: Into<Vec<u8>>>(id: u8, data: B) -> write::Result<Element> {
+        if id < 128 {
+            return Err(write::Error::ReservedExtensionId { id });
+        }
+        Ok(Element::Extension {
+            id,
+            data: data.into(),
+        })
+    }
+
+    /// Get the [kind][ElementKind] of this [Element].
+    pub fn kind(&self) -> ElementKind {
+        match *self {
+            Element::Marker(_) => ElementKind::Marker,
+            Element::Folder => ElementKind::Folder,
+            Element::Collection => ElementKind::Collection,
+            Element::Integer8(_) => ElementKind::Integer8,
+            Element::Integer16(_) => ElementKind::Integer16,
+            Element::Integer32(_) => ElementKind::Integer32,
+            Element::Integer64(_) => ElementKind::Integer64,
+            Element::Integer128(_) => ElementKind::Integer128,
+            Element::UInteger8(_) => ElementKind::UInteger8,
+            Element::UInteger16(_) => ElementKind::UInteger16,
+            Element::UInteger32(_) => ElementKind::UInteger32,
+            Element::UInteger64(_) => ElementKind::UInteger64,
+            Element::UInteger128(_) => ElementKind::UInteger128,
+            Element::Float32(_) => ElementKind::Float32,
+            Element::Float64(_) => ElementKind::Float64,
+            Element::Boolean(_) => ElementKind::Boolean,
+            Element::String(_) => ElementKind::String,
+            Element::Blob(_) => ElementKind::Blob,
+            Element::CString(_) => ElementKind::CString,
+            Element::Extension { .. }
+            | Element::DeltaU64(_)
+            | Element::BlobRef(_)
+            | Element::Flagged { .. }
+            | Element::Duration(_)
+            | Element::TypedCollection { .. }
+            | Element::Map(_)
+            | Element::StringRef(_) => ElementKind::Extension,
+            Element::Padding(_) => ElementKind::Padding,
+        }
+    }
+
     /// Get the type byte for this [Element].
     pub fn get_type_byte(&self) -> u8 {
         match *self {
@@ -62,10 +561,12 @@ impl Element {
             Element::Integer16(_) => 0b00100101u8,
             Element::Integer32(_) => 0b00100110u8,
             Element::Integer64(_) => 0b00100111u8,
+            Element::Integer128(_) => 0b00101001u8,
             Element::UInteger8(_) => 0b00100000u8,
             Element::UInteger16(_) => 0b00100001u8,
             Element::UInteger32(_) => 0b00100010u8,
             Element::UInteger64(_) => 0b00100011u8,
+            Element::UInteger128(_) => 0b00101000u8,
             Element::Float32(_) => 0b00101110u8,
             Element::Float64(_) => 0b00101111u8,
             Element::Boolean(val) => {
@@ -77,16 +578,658 @@ impl Element {
             }
             Element::String(ref val) => 0b00010000u8 | size_byte(val.len()),
             Element::Blob(ref val) => 0b00010100u8 | size_byte(val.len()),
+            Element::CString(_) => 0b00011000u8,
+            Element::Padding(len) => 0b00011100u8 | size_byte(len),
+            Element::Extension { ref data, .. } => 0b01110000u8 | size_byte(data.len()),
+            Element::DeltaU64(ref values) => 0b01110000u8 | size_byte(delta_u64_len(values)),
+            Element::Flagged { ref inner, .. } => 0b01110000u8 | size_byte(1 + inner.encoded_len()),
+            Element::Map(ref pairs) => 0b01110000u8 | size_byte(map_len(pairs)),
+            Element::BlobRef(_)
+            | Element::Duration(_)
+            | Element::TypedCollection { .. }
+            | Element::StringRef(_) => 0b01110000u8,
+        }
+    }
+
+    /// Get the number of bytes this [Element] occupies once written.
+    ///
+    /// This includes the type byte, size bytes and data bytes (if any), as written by
+    /// [Writer::write_element][crate::Writer::write_element].
+    pub fn encoded_len(&self) -> usize {
+        1 + match *self {
+            Element::Marker(ref val) => marker_size_len(val.len()) + val.len(),
+            Element::Folder | Element::Collection | Element::Boolean(_) => 0,
+            Element::Integer8(_) | Element::UInteger8(_) => 1,
+            Element::Integer16(_) | Element::UInteger16(_) => 2,
+            Element::Integer32(_) | Element::UInteger32(_) | Element::Float32(_) => 4,
+            Element::Integer64(_) | Element::UInteger64(_) | Element::Float64(_) => 8,
+            Element::Integer128(_) | Element::UInteger128(_) => 16,
+            Element::String(ref val) => size_len(val.len()) + val.len(),
+            Element::Blob(ref val) => size_len(val.len()) + val.len(),
+            Element::CString(ref val) => val.len() + 1,
+            Element::Padding(len) => size_len(len) + len,
+            Element::Extension { ref data, .. } => 1 + size_len(data.len()) + data.len(),
+            Element::DeltaU64(ref values) => {
+                let len = delta_u64_len(values);
+                1 + size_len(len) + len
+            }
+            Element::BlobRef(_) | Element::StringRef(_) => 6,
+            Element::Duration(_) => 10,
+            Element::TypedCollection { .. } => 7,
+            Element::Map(ref pairs) => {
+                let len = map_len(pairs);
+                1 + size_len(len) + len
+            }
+            Element::Flagged { ref inner, .. } => {
+                let len = 1 + inner.encoded_len();
+                1 + size_len(len) + len
+            }
+        }
+    }
+
+    /// Get the bytes of the value held by this [Element], without its type byte and size bytes.
+    ///
+    /// Numbers are returned in little-endian order, strings as their UTF-8 bytes (without the
+    /// terminating NUL character of [NUL-terminated strings][Element::CString]), and blobs and
+    /// extensions as their payload. The flags of [flagged elements][Element::Flagged] are left out:
+    /// their inner element is used instead. Since booleans are encoded in their type byte, they are
+    /// returned as a single `0` or `1` byte. This returns `None` for markers, folders, collections
+    /// and padding, which do not hold any value, and for maps, which hold several.
+    pub fn value_bytes(&self) -> Option<Vec<u8>> {
+        let bytes = match *self {
+            Element::Marker(_)
+            | Element::Folder
+            | Element::Collection
+            | Element::Padding(_)
+            | Element::Map(_) => return None,
+            Element::Integer8(val) => val.to_le_bytes().to_vec(),
+            Element::Integer16(val) => val.to_le_bytes().to_vec(),
+            Element::Integer32(val) => val.to_le_bytes().to_vec(),
+            Element::Integer64(val) => val.to_le_bytes().to_vec(),
+            Element::Integer128(val) => val.to_le_bytes().to_vec(),
+            Element::UInteger8(val) => val.to_le_bytes().to_vec(),
+            Element::UInteger16(val) => val.to_le_bytes().to_vec(),
+            Element::UInteger32(val) => val.to_le_bytes().to_vec(),
+            Element::UInteger64(val) => val.to_le_bytes().to_vec(),
+            Element::UInteger128(val) => val.to_le_bytes().to_vec(),
+            Element::Float32(val) => val.to_le_bytes().to_vec(),
+            Element::Float64(val) => val.to_le_bytes().to_vec(),
+            Element::Boolean(val) => vec![val as u8],
+            Element::String(ref val) | Element::CString(ref val) => val.as_bytes().to_vec(),
+            Element::Blob(ref val) => val.clone(),
+            Element::Extension { ref data, .. } => data.clone(),
+            Element::DeltaU64(ref values) => {
+                let mut data = Vec::new();
+                encode_delta_u64(values, &mut data);
+                data
+            }
+            Element::BlobRef(index) | Element::StringRef(index) => index.to_le_bytes().to_vec(),
+            Element::Duration(val) => val.to_le_bytes().to_vec(),
+            Element::TypedCollection { type_byte, count } => {
+                let mut data = vec![type_byte];
+                data.extend_from_slice(&count.to_le_bytes());
+                data
+            }
+            Element::Flagged { ref inner, .. } => return inner.value_bytes(),
+        };
+        Some(bytes)
+    }
+
+    /// Get the first TPK format version in which this [Element] exists.
+    ///
+    /// See [type_byte_version] for the list of format versions.
+    pub fn version(&self) -> u8 {
+        type_byte_version(self.get_type_byte())
+    }
+
+    /// Check whether this [Element] holds the same value as another one.
+    ///
+    /// Unlike the derived [PartialEq] implementation, integers are compared by numeric value
+    /// regardless of their width and signedness, and floating-point numbers are compared
+    /// regardless of their precision. For example, `UInteger8(5)` and `Integer32(5)` hold the
+    /// same value. Every other element is compared structurally.
+    pub fn value_eq(&self, other: &Element) -> bool {
+        if let (Some(val), Some(other_val)) = (self.as_i128(), other.as_i128()) {
+            return val == other_val;
+        }
+        match (self, other) {
+            (
+                Element::Float32(_) | Element::Float64(_),
+                Element::Float32(_) | Element::Float64(_),
+            ) => self.as_f64() == other.as_f64(),
+            _ => self == other,
+        }
+    }
+
+    /// Check whether this [Element] is written as the same bytes as another one.
+    ///
+    /// Unlike [value_eq][Self::value_eq], integers of different widths are never equal, and
+    /// unlike the derived [PartialEq] implementation, floating-point numbers are compared by
+    /// their bits: `NaN` is equal to itself, while `0.0` and `-0.0` are different.
+    pub fn wire_eq(&self, other: &Element) -> bool {
+        if self.encoded_len() != other.encoded_len() {
+            return false;
         }
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        let mut other_bytes = Vec::with_capacity(other.encoded_len());
+        write::encode_element(self, &mut bytes);
+        write::encode_element(other, &mut other_bytes);
+        bytes == other_bytes
     }
+
+    fn narrow(&mut self) {
+        if let Some(narrowed) = self.narrowed() {
+            *self = narrowed;
+        }
+    }
+
+    /// Get the narrowest integer element holding the value of this [Element], or `None` if this
+    /// element is not an integer or is already as narrow as it can be.
+    pub(crate) fn narrowed(&self) -> Option<Element> {
+        let narrowed = match *self {
+            Element::Integer16(val) => narrow_signed(val as i64),
+            Element::Integer32(val) => narrow_signed(val as i64),
+            Element::Integer64(val) => narrow_signed(val),
+            Element::Integer128(val) => narrow_signed(i64::try_from(val).ok()?),
+            Element::UInteger16(val) => narrow_unsigned(val as u64),
+            Element::UInteger32(val) => narrow_unsigned(val as u64),
+            Element::UInteger64(val) => narrow_unsigned(val),
+            Element::UInteger128(val) => narrow_unsigned(u64::try_from(val).ok()?),
+            _ => return None,
+        };
+        // Narrowing must never truncate a value, or canonical writes would silently corrupt it.
+        debug_assert!(
+            narrowed.value_eq(self),
+            "{:?} narrowed to {:?}",
+            self,
+            narrowed
+        );
+        (narrowed.kind() != self.kind()).then_some(narrowed)
+    }
+
+    /// Get the duration held by this [Element], if it is a non-negative
+    /// [duration element][Element::Duration].
+    ///
+    /// This returns `None` for any other element, including negative durations, which cannot be
+    /// represented by [std::time::Duration].
+    pub fn as_duration(&self) -> Option<std::time::Duration> {
+        match *self {
+            Element::Duration(nanos) => u64::try_from(nanos)
+                .ok()
+                .map(std::time::Duration::from_nanos),
+            _ => None,
+        }
+    }
+
+    /// Clone this [Element] into an [ElementBytes] whose blob data can be shared.
+    ///
+    /// The data of a blob is copied once into a [bytes::Bytes] buffer, and every clone of the
+    /// returned element then shares that allocation instead of copying it again. This makes it
+    /// cheap to hand the same large blob to several consumers. An element that is no longer
+    /// needed can be [converted][ElementBytes::from] instead, which moves its blob data.
+    #[cfg(feature = "bytes")]
+    pub fn clone_shared(&self) -> ElementBytes {
+        match *self {
+            Element::Blob(ref val) => ElementBytes::Blob(bytes::Bytes::copy_from_slice(val)),
+            ref element => ElementBytes::Other(element.clone()),
+        }
+    }
+
+    fn as_i128(&self) -> Option<i128> {
+        match *self {
+            Element::Integer8(val) => Some(val as i128),
+            Element::Integer16(val) => Some(val as i128),
+            Element::Integer32(val) => Some(val as i128),
+            Element::Integer64(val) => Some(val as i128),
+            Element::Integer128(val) => Some(val),
+            Element::UInteger8(val) => Some(val as i128),
+            Element::UInteger16(val) => Some(val as i128),
+            Element::UInteger32(val) => Some(val as i128),
+            Element::UInteger64(val) => Some(val as i128),
+            // Values over `i128::MAX` are only equal to themselves, which is checked structurally.
+            Element::UInteger128(val) => i128::try_from(val).ok(),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Element::Float32(val) => Some(val as f64),
+            Element::Float64(val) => Some(val),
+            _ => None,
+        }
+    }
+}
+
+impl Entry {
+    /// Get the number of bytes this [Entry] occupies once written.
+    ///
+    /// This includes the marker element holding the name of the entry, as written by
+    /// [Writer::write_entry][crate::Writer::write_entry].
+    pub fn encoded_len(&self) -> usize {
+        1 + marker_size_len(self.name.len())
+            + self.name.len()
+            + self
+                .elements
+                .iter()
+                .map(Element::encoded_len)
+                .sum::<usize>()
+    }
+
+    /// Get the data of the single [blob element][Element::Blob] of this [Entry].
+    ///
+    /// This returns `None` unless this entry holds exactly one element, which is a blob. This is
+    /// meant for entries wrapping an opaque payload under a name.
+    pub fn as_blob(&self) -> Option<&[u8]> {
+        match self.elements.as_slice() {
+            [Element::Blob(data)] => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Take the data of the single [blob element][Element::Blob] of this [Entry].
+    ///
+    /// This behaves like [as_blob][Self::as_blob], without copying the data.
+    pub fn into_blob(mut self) -> Option<Vec<u8>> {
+        match self.elements.pop() {
+            Some(Element::Blob(data)) if self.elements.is_empty() => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Retain only the elements of this [Entry] for which the given predicate returns `true`.
+    ///
+    /// This behaves like [Vec::retain] on the elements of this entry.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Element) -> bool,
+    {
+        self.elements.retain(f);
+    }
+
+    /// Merge every run of consecutive [string elements][Element::String] of this [Entry] into a
+    /// single string element.
+    ///
+    /// This is meant for producers splitting long strings into several elements. Note that this
+    /// is lossy: the boundaries between the original string elements cannot be recovered.
+    pub fn coalesce_strings(&mut self) {
+        let mut elements: Vec<Element> = Vec::with_capacity(self.elements.len());
+        for element in self.elements.drain(..) {
+            match (elements.last_mut(), element) {
+                (Some(Element::String(previous)), Element::String(val)) => previous.push_str(&val),
+                (_, element) => elements.push(element),
+            }
+        }
+        self.elements = elements;
+    }
+
+    /// Check that the elements of this [Entry] conform to the given [schema][EntrySchema].
+    pub fn matches(&self, schema: &EntrySchema) -> Result<(), SchemaError> {
+        schema.validate(&self.elements)
+    }
+
+    /// Check whether this [Entry] holds the same values as another one.
+    ///
+    /// Both entries must have the same name and the same number of elements, and each element
+    /// must be [equal in value][Element::value_eq] to its counterpart.
+    pub fn value_eq(&self, other: &Entry) -> bool {
+        self.name == other.name
+            && self.elements.len() == other.elements.len()
+            && self
+                .elements
+                .iter()
+                .zip(other.elements.iter())
+                .all(|(element, other_element)| element.value_eq(other_element))
+    }
+}
+
+impl Document {
+    /// Get the number of bytes this [Document] occupies once written.
+    ///
+    /// This is the sum of the [encoded lengths][Entry::encoded_len] of every entry of this
+    /// document.
+    pub fn encoded_len(&self) -> usize {
+        self.entries.iter().map(Entry::encoded_len).sum()
+    }
+
+    /// Drop the trailing entries of this [Document] until its
+    /// [encoded length][Self::encoded_len] is at most `max_bytes`.
+    ///
+    /// Entries are kept whole: the document is always cut at an entry boundary, keeping as many
+    /// leading entries as fit in the budget. This returns the number of entries dropped.
+    pub fn truncate_to_bytes(&mut self, max_bytes: usize) -> usize {
+        let mut len = 0;
+        let kept = self
+            .entries
+            .iter()
+            .take_while(|entry| {
+                len += entry.encoded_len();
+                len <= max_bytes
+            })
+            .count();
+        let dropped = self.entries.len() - kept;
+        self.entries.truncate(kept);
+        dropped
+    }
+
+    /// Retain only the entries of this [Document] for which the given predicate returns `true`.
+    ///
+    /// This behaves like [Vec::retain] on the entries of this document.
+    pub fn retain_entries<F>(&mut self, f: F)
+    where
+        F: FnMut(&Entry) -> bool,
+    {
+        self.entries.retain(f);
+    }
+
+    /// Get an iterator over the entries of this [Document] that allows modifying them in place.
+    pub fn entries_mut(&mut self) -> std::slice::IterMut<'_, Entry> {
+        self.entries.iter_mut()
+    }
+
+    /// Rename every entry of this [Document] using the given function.
+    ///
+    /// The function is called with the current name of each entry, in order, and returns its
+    /// new name.
+    pub fn map_names<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str) -> String,
+    {
+        for entry in self.entries_mut() {
+            entry.name = f(&entry.name);
+        }
+    }
+
+    /// Prefix the name of every entry of this [Document] with the given namespace.
+    ///
+    /// Each name becomes `prefix/name`, which makes it possible to
+    /// [concatenate][Self::concat] documents coming from different sources without their entry
+    /// names colliding.
+    pub fn with_prefix(mut self, prefix: &str) -> Document {
+        self.map_names(|name| format!("{}/{}", prefix, name));
+        self
+    }
+
+    /// Concatenate the given documents into a single [Document].
+    ///
+    /// Entries are kept in order, starting with the entries of the first document.
+    pub fn concat(docs: Vec<Document>) -> Document {
+        Document {
+            entries: docs.into_iter().flat_map(|doc| doc.entries).collect(),
+        }
+    }
+
+    /// Get the number of bytes saved by [canonicalizing][Self::canonicalize] this [Document].
+    ///
+    /// This is the difference between the [encoded length][Self::encoded_len] of this document
+    /// and the encoded length of its canonical form, computed without canonicalizing or writing
+    /// it. This is useful to decide whether writing canonical data is worth it.
+    pub fn compact_savings(&self) -> usize {
+        self.entries
+            .iter()
+            .flat_map(|entry| &entry.elements)
+            .filter_map(|element| {
+                let narrowed = element.narrowed()?;
+                Some(element.encoded_len() - narrowed.encoded_len())
+            })
+            .sum()
+    }
+
+    /// Compute a SHA-256 hash of the content of this [Document].
+    ///
+    /// The hash is computed over the [canonical][Self::canonicalize] encoding of the document,
+    /// which means that two documents holding the same names and values hash identically, even
+    /// if they were written using different integer widths or size classes.
+    ///
+    /// This returns the [error][crate::write::Error] that writing the document would return if it
+    /// cannot be encoded, for example if it holds a NUL-terminated string containing a NUL
+    /// character.
+    #[cfg(feature = "sha2")]
+    pub fn content_hash(&self) -> write::Result<[u8; 32]> {
+        use sha2::{Digest, Sha256};
+
+        let mut canonical = self.clone();
+        canonical.canonicalize();
+        let mut hasher = Sha256::new();
+        crate::Writer::new(&mut hasher).write_document(&canonical)?;
+        Ok(hasher.finalize().into())
+    }
+
+    /// Rewrite this [Document] so that it is written using its canonical, minimal encoding.
+    ///
+    /// Two documents holding the same values are written as the exact same bytes once
+    /// canonicalized, which makes canonical documents suitable for hashing or content
+    /// addressing. The following transformations are applied:
+    ///
+    /// - integers are narrowed to the smallest width that can hold their value, while keeping
+    ///   their signedness: `UInteger32(5)` becomes `UInteger8(5)` and `Integer64(-300)` becomes
+    ///   `Integer16(-300)`. The items of [collections][Element::Collection] are left untouched,
+    ///   since narrowing them independently would make collections heterogeneous;
+    /// - every other element is left untouched. Note that strings, blobs and markers are always
+    ///   written with their minimal size class by [Writer][crate::Writer], and that
+    ///   floating-point numbers are never narrowed since doing so could lose precision.
+    ///
+    /// Canonicalizing a document is idempotent.
+    pub fn canonicalize(&mut self) {
+        for entry in &mut self.entries {
+            let mut in_collection = false;
+            for element in &mut entry.elements {
+                match *element {
+                    Element::Folder => in_collection = false,
+                    Element::Collection | Element::TypedCollection { .. } => in_collection = true,
+                    _ if !in_collection => element.narrow(),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+/// The latest TPK format version supported by this crate.
+pub const LATEST_VERSION: u8 = 3;
+
+/// Get the first TPK format version in which elements with the given type byte exist.
+///
+/// Version 1 contains the core elements: markers, folders, collections, numbers, booleans,
+/// strings and blobs. Version 2 introduces [extension elements][Element::Extension] and
+/// [NUL-terminated strings][Element::CString]. Version 3 introduces 128-bit
+/// [unsigned][Element::UInteger128] and [signed][Element::Integer128] integers, and
+/// [padding][Element::Padding].
+///
+/// Number type bytes from `0b00101010` to `0b00101101` are reserved for future numeric types,
+/// such as decimals. Like 128-bit integers, these will be introduced by a new format version.
+pub fn type_byte_version(type_byte: u8) -> u8 {
+    match type_byte {
+        0b00101000..=0b00101101 | 0b00011100..=0b00011111 => 3,
+        0b01110000..=0b01111111 | 0b00011000 => 2,
+        _ => 1,
+    }
+}
+
+/// Check whether the given type byte is the one of a number element, which the elements of a
+/// [typed collection][Element::TypedCollection] must hold.
+pub(crate) fn is_number_type_byte(type_byte: u8) -> bool {
+    matches!(type_byte, 0b00100000..=0b00101001 | 0b00101110 | 0b00101111)
+}
+
+/// The value of the magic optionally starting TPK data, used to
+/// [detect its byte order][crate::Reader::detect_endianness].
+///
+/// Written in little-endian order, as mandated by the format, its bytes spell `TPKE`. Neither
+/// order of these bytes starts with a valid type byte, so the magic cannot be mistaken for an
+/// element.
+pub const ENDIANNESS_MAGIC: u32 = 0x454B_5054;
+
+/// The identifier of the extension elements holding [delta-encoded integers][Element::DeltaU64].
+pub const DELTA_U64_EXTENSION_ID: u8 = 0;
+
+/// The identifier of the extension elements holding [blob references][Element::BlobRef].
+pub const BLOB_REF_EXTENSION_ID: u8 = 1;
+
+/// The identifier of the extension elements holding [flagged elements][Element::Flagged].
+pub const FLAGGED_EXTENSION_ID: u8 = 2;
+
+/// The identifier of the extension elements holding [durations][Element::Duration].
+pub const DURATION_EXTENSION_ID: u8 = 3;
+
+/// The identifier of the extension elements holding DEFLATE-compressed blobs.
+///
+/// These are written by writers compressing blobs, and read back as [blobs][Element::Blob] by
+/// readers decompressing blobs, both of which require the `flate2` feature. Other readers read
+/// them as regular [extension elements][Element::Extension].
+pub const COMPRESSED_BLOB_EXTENSION_ID: u8 = 4;
+
+/// The identifier of the extension elements holding [typed collections][Element::TypedCollection].
+pub const TYPED_COLLECTION_EXTENSION_ID: u8 = 5;
+
+/// The identifier of the extension elements holding [maps][Element::Map].
+pub const MAP_EXTENSION_ID: u8 = 6;
+
+/// The identifier of the extension elements holding [string references][Element::StringRef].
+pub const STRING_REF_EXTENSION_ID: u8 = 7;
+
+/// Encode the payload of a [delta-encoded integer collection][Element::DeltaU64].
+///
+/// The first value is written as an unsigned LEB128 varint, followed by the (wrapping)
+/// difference between each value and the previous one as a zig-zag encoded varint.
+pub(crate) fn encode_delta_u64(values: &[u64], out: &mut Vec<u8>) {
+    let mut previous = 0u64;
+    for (i, &val) in values.iter().enumerate() {
+        let encoded = if i == 0 {
+            val
+        } else {
+            zig_zag(val.wrapping_sub(previous) as i64)
+        };
+        push_varint(encoded, out);
+        previous = val;
+    }
+}
+
+/// Decode the payload of a [delta-encoded integer collection][Element::DeltaU64], or return
+/// `None` if it is malformed.
+pub(crate) fn decode_delta_u64(mut data: &[u8]) -> Option<Vec<u64>> {
+    let mut values = Vec::new();
+    let mut previous = 0u64;
+    while !data.is_empty() {
+        let (encoded, len) = read_varint(data)?;
+        data = &data[len..];
+        let val = if values.is_empty() {
+            encoded
+        } else {
+            previous.wrapping_add(un_zig_zag(encoded) as u64)
+        };
+        values.push(val);
+        previous = val;
+    }
+    Some(values)
+}
+
+fn delta_u64_len(values: &[u64]) -> usize {
+    let mut previous = 0u64;
+    let mut len = 0;
+    for (i, &val) in values.iter().enumerate() {
+        let encoded = if i == 0 {
+            val
+        } else {
+            zig_zag(val.wrapping_sub(previous) as i64)
+        };
+        len += varint_len(encoded);
+        previous = val;
+    }
+    len
+}
+
+/// Get the length of the payload of a [map][Element::Map] holding the given pairs.
+fn map_len(pairs: &[(String, Element)]) -> usize {
+    let pairs_len: usize = pairs
+        .iter()
+        .map(|(key, value)| 1 + size_len(key.len()) + key.len() + value.encoded_len())
+        .sum();
+    4 + pairs_len
+}
+
+fn zig_zag(val: i64) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+fn un_zig_zag(val: u64) -> i64 {
+    (val >> 1) as i64 ^ -((val & 1) as i64)
+}
+
+fn push_varint(mut val: u64, out: &mut Vec<u8>) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut val = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        let bits = (byte & 0x7F) as u64;
+        if i == 9 && bits > 1 {
+            return None;
+        }
+        val |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((val, i + 1));
+        }
+    }
+    None
+}
+
+fn varint_len(val: u64) -> usize {
+    (64 - val.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+pub(crate) fn narrow_signed(val: i64) -> Element {
+    if let Ok(val) = i8::try_from(val) {
+        Element::Integer8(val)
+    } else if let Ok(val) = i16::try_from(val) {
+        Element::Integer16(val)
+    } else if let Ok(val) = i32::try_from(val) {
+        Element::Integer32(val)
+    } else {
+        Element::Integer64(val)
+    }
+}
+
+pub(crate) fn narrow_unsigned(val: u64) -> Element {
+    if let Ok(val) = u8::try_from(val) {
+        Element::UInteger8(val)
+    } else if let Ok(val) = u16::try_from(val) {
+        Element::UInteger16(val)
+    } else if let Ok(val) = u32::try_from(val) {
+        Element::UInteger32(val)
+    } else {
+        Element::UInteger64(val)
+    }
+}
+
+#[inline(always)]
+fn marker_size_len(size: usize) -> usize {
+    let mut remaining_size = size >> 6;
+    let mut size_len = if size > 63 { 1 } else { 0 };
+    while remaining_size > 0x7F {
+        remaining_size >>= 7;
+        size_len += 1;
+    }
+    size_len
+}
+
+#[inline(always)]
+fn size_len(size: usize) -> usize {
+    1 << size_byte(size)
 }
 
 #[inline(always)]
-fn size_byte(size: usize) -> u8 {
-    match size {
-        0..=255 => 0b00u8,
-        256..=65535 => 0b01u8,
-        65536..=4294967295 => 0b10u8,
+pub(crate) fn size_byte(size: usize) -> u8 {
+    // Compare as `u64`, so that the branches mean the same on every pointer width.
+    match size as u64 {
+        0..=0xFF => 0b00u8,
+        0x100..=0xFFFF => 0b01u8,
+        0x1_0000..=0xFFFF_FFFF => 0b10u8,
         _ => 0b11u8,
     }
 }
@@ -1,8 +1,26 @@
-use crate::model::Entry;
+#[cfg(feature = "bytes")]
+use crate::model::ElementBytes;
+#[cfg(feature = "flate2")]
+use crate::model::COMPRESSED_BLOB_EXTENSION_ID;
+use crate::model::{
+    decode_delta_u64, is_number_type_byte, type_byte_version, BLOB_REF_EXTENSION_ID,
+    DELTA_U64_EXTENSION_ID, DURATION_EXTENSION_ID, ENDIANNESS_MAGIC, FLAGGED_EXTENSION_ID,
+    LATEST_VERSION, MAP_EXTENSION_ID, STRING_REF_EXTENSION_ID, TYPED_COLLECTION_EXTENSION_ID,
+};
+use crate::model::{
+    Document, ElementCow, ElementKind, ElementRef, Entry, EntryRef, RawElement, Scalar,
+};
 use crate::read::Error::{Syntax, UnknownType};
-use crate::Element;
+use crate::stats::Stats;
+use crate::write::{FOOTER_NAME, LOG_RECORD_MAGIC, STRING_TABLE_NAME};
+use crate::{Element, Writer};
 use byteorder::{ByteOrder, LE};
-use std::{io, string};
+use std::collections::{HashMap, HashSet};
+use std::mem::Discriminant;
+use std::ops::Range;
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::{io, mem, str, string};
 use thiserror::Error;
 
 /// Representation of a TPK read error.
@@ -65,24 +83,312 @@ pub enum Error {
     #[deprecated]
     #[error("Unsupported element type at byte {0}: {1}")]
     UnsupportedType(usize, &'static str),
+
+    /// A collection contains elements of different types.
+    ///
+    /// This error happens in [strict mode][Reader::strict] when an element following a
+    /// [collection][Element::Collection] is not of the same type as the previous elements of
+    /// that collection.
+    #[error("Heterogeneous collection element at byte {pos}")]
+    HeterogeneousCollection { pos: usize },
+
+    /// A typed collection does not hold what it announces.
+    ///
+    /// This error happens in [strict mode][Reader::strict] when the type byte of a
+    /// [typed collection][Element::TypedCollection] is not the one of a number element, or when
+    /// the elements following it, up to the next marker, container or end of file, are not
+    /// exactly `count` elements of that type.
+    #[error("Invalid typed collection element at byte {pos}")]
+    InvalidTypedCollection { pos: usize },
+
+    /// A size is not minimally encoded.
+    ///
+    /// This error happens in [strict mode][Reader::strict] when the size of a marker is followed
+    /// by redundant continuation bytes, which would make the same marker encodable in several
+    /// ways.
+    #[error("Non-canonical size at byte {pos}")]
+    NonCanonicalSize { pos: usize },
+
+    /// A marker name is too long.
+    ///
+    /// This error happens when the declared length of a marker name exceeds the
+    /// [maximum marker length][Reader::max_marker_len] configured for the reader. The name is
+    /// rejected before any memory is allocated for it.
+    #[error("Marker at byte {pos} is {len} bytes long, exceeding the limit of {limit} bytes")]
+    MarkerTooLong {
+        pos: usize,
+        len: usize,
+        limit: usize,
+    },
+
+    /// A frame is too large.
+    ///
+    /// This error happens when the length prefix of a [framed document][FramedReader] or of a
+    /// [log record][LogReader] exceeds the maximum frame length configured for the reader. The
+    /// frame is rejected before any of it is read.
+    #[error("Frame at byte {pos} is {len} bytes long, exceeding the limit of {limit} bytes")]
+    FrameTooLarge {
+        pos: usize,
+        len: usize,
+        limit: usize,
+    },
+
+    /// Elements are nested too deeply.
+    ///
+    /// This error happens when a [flagged element][Element::Flagged] or [map][Element::Map] holds
+    /// elements nested deeper than the [maximum nesting depth][Reader::max_depth] configured for
+    /// the reader. The nested elements are rejected before being decoded.
+    #[error("Element at byte {pos} is nested deeper than the limit of {limit} levels")]
+    TooDeep { pos: usize, limit: usize },
+
+    /// A compressed blob is too large once decompressed.
+    ///
+    /// This error happens when a compressed blob decompresses to more bytes than the limit
+    /// configured for a reader decompressing blobs, which requires the `flate2` feature.
+    /// Decompression stops as soon as the limit is exceeded.
+    #[error("Compressed blob at byte {pos} exceeds the limit of {limit} bytes once decompressed")]
+    BlobTooLarge { pos: usize, limit: usize },
+
+    /// An entry holds too many elements.
+    ///
+    /// This error happens when [reading an entry][Reader::read_entry] holding more elements than
+    /// the [maximum number of elements per entry][Reader::max_elements_per_entry] configured for
+    /// the reader.
+    #[error("Entry \"{name}\" holds more than {limit} elements")]
+    TooManyElements { name: String, limit: usize },
+
+    /// An expected marker was not found.
+    ///
+    /// This error happens when the end of file is reached while
+    /// [reading until a marker][Reader::read_until_marker], if the reader is configured to
+    /// [fail on missing markers][Reader::fail_on_missing_marker].
+    #[error("Marker \"{name}\" not found before end of file")]
+    MarkerNotFound { name: String },
+
+    /// Data has been found after the expected end of file.
+    ///
+    /// This error happens when [expecting the end of file][Reader::expect_eof] while the
+    /// reader still has data to read.
+    #[error("Trailing data at byte {pos}")]
+    TrailingData { pos: usize },
+
+    /// An element type does not exist in the format version of the reader.
+    ///
+    /// This error happens when an element type introduced after the
+    /// [format version][Reader::version] configured for the reader is encountered.
+    #[error("Element type {byte:#X} at byte {pos} does not exist in version {version}")]
+    TypeNotInVersion { pos: usize, byte: u8, version: u8 },
+
+    /// An entry holds more than one element.
+    ///
+    /// This error happens when [reading a map][Reader::read_map] out of a payload containing an
+    /// entry with several elements.
+    #[error("Entry \"{name}\" holds more than one element")]
+    MultiValueEntry { name: String },
+
+    /// An element is not of the expected type.
+    ///
+    /// This error happens when [reading a blob into a buffer][Reader::read_blob_into] while the
+    /// next element is not a blob, when [reading an array][Reader::read_scalar_array] while an
+    /// element is not of the requested type, or when
+    /// [reading a typed collection][Reader::read_typed_collection] while the next element is not
+    /// one. The element is not consumed.
+    #[error("Unexpected element type at byte {pos}: {byte:#X}")]
+    UnexpectedType { pos: usize, byte: u8 },
+
+    /// An element could not be decoded.
+    ///
+    /// This error wraps the [syntax errors][Error::Syntax] and
+    /// [invalid string errors][Error::InvalidString] happening while reading an element, if the
+    /// reader is configured to [keep the decoding context][Reader::decode_context]. It holds the
+    /// bytes of the element that were read before the error happened, starting with its type
+    /// byte.
+    #[error("{source} (after reading {bytes:02X?})")]
+    DecodeContext {
+        bytes: Vec<u8>,
+
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// A string is invalid in the encoding of the reader.
+    ///
+    /// This error happens when a marker name or string holds data that is invalid in the
+    /// string encoding configured for the reader, which requires the `encoding_rs` feature.
+    #[error("Invalid {encoding} string at byte {pos}")]
+    InvalidEncoding { pos: usize, encoding: &'static str },
+
+    /// A buffer is too small to hold an element.
+    ///
+    /// This error happens when [reading a blob into a buffer][Reader::read_blob_into] that is
+    /// smaller than the blob.
+    #[error("Buffer of {have} bytes is too small for {needed} bytes")]
+    BufferTooSmall { needed: usize, have: usize },
+
+    /// The source does not end with a footer.
+    ///
+    /// This error happens when [reading entries in reverse][Reader::entries_reversed] from a
+    /// source without a [footer][crate::Writer::write_footer]. Such a source can only be read
+    /// forward, by [reading its entries][Reader::read_entry] one after the other.
+    #[error("No footer found, entries must be read forward")]
+    MissingFooter,
+
+    /// An element could not be written.
+    ///
+    /// This error happens when an element read by this reader is written to a destination, for
+    /// example when [copying the remaining bytes][Reader::copy_remaining_to] of the reader.
+    #[error("Could not write TPK data: {source}")]
+    Write {
+        #[from]
+        source: crate::write::Error,
+    },
 }
 
 /// Representation of a TPK read result.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A decoder for [extension elements][Element::Extension].
+///
+/// Extension decoders are [registered][Reader::register_extension] for a given extension
+/// identifier, and turn the payload of the extension elements holding that identifier into a
+/// dedicated [element][Element].
+pub type ExtensionDecoder = Box<dyn Fn(&[u8]) -> Result<Element> + Send + Sync>;
+
+/// Decode the first [entry][Entry] of the given bytes.
+///
+/// This behaves like [reading an entry][Reader::read_entry] with a default reader over the given
+/// slice, and returns `None` if the slice is empty. The entry ends at the type byte of the next
+/// marker, which is not decoded: anything from that byte on is ignored, even if it is invalid.
+pub fn decode_entry(bytes: &[u8]) -> Result<Option<Entry>> {
+    let mut reader = Reader::new(bytes);
+    let mut elements = Vec::with_capacity(1); // Entries usually have one element.
+    let name = match reader.read_element()? {
+        Some(Element::Marker(name)) => name,
+        Some(element) => {
+            elements.push(element);
+            reader.current_name.clone()
+        }
+        None => return Ok(None),
+    };
+    while !reader.next_is_marker_or_eof()? {
+        elements.extend(reader.read_element()?);
+    }
+    Ok(Some(Entry { name, elements }))
+}
+
 /// A TPK reader structure.
 ///
 /// This structure holds the source from which TPK data should be read, as well as internal reader
 /// contextual data.
+///
+/// A reader is [Send] and [Sync] as long as its source is, which makes it possible to share it
+/// between threads, for example behind a mutex. [Extension decoders][ExtensionDecoder] are
+/// required to be thread-safe for that reason.
+///
+/// The reader does not buffer its source: type bytes, size bytes and the length prefix of
+/// markers are read a few bytes at a time, since their length is only known once they are
+/// decoded. Sources that are expensive to read from, such as files or sockets, should be
+/// wrapped in a [std::io::BufReader].
 pub struct Reader<T> {
     read: T,
     previous_bytes_read: usize,
     bytes_read: usize,
     current_name: String,
+    /// An element that has already been read from the source, but not returned to the caller.
+    ///
+    /// An element is retained in two cases only: when [read_entry][Self::read_entry] (or any
+    /// other entry-level read) stops at the marker starting the next entry, and when an element
+    /// is [peeked at][Self::peek_element]. At most one element is retained at a time: every read
+    /// takes the retained element (if any) before touching the source, and elements are only
+    /// retained right after having been read, so a retained element is never overwritten.
+    ///
+    /// The reader state attached to an element (current entry name, strict collection type,
+    /// byte counters) is updated when the element is read from the source, not when the retained
+    /// element is returned. Its raw bytes, if retained, are kept in `retained_raw`, and its
+    /// offset in the source in `retained_start`.
     retained_element: Option<Element>,
+    retained_raw: Option<Box<[u8]>>,
+    retained_start: usize,
+    retain_raw: bool,
+    /// Whether the raw bytes of the marker being read are recorded, even if
+    /// [raw bytes retention][Self::retain_raw] is disabled, so that a retained marker can
+    /// always be written back as it was read.
+    raw_marker: bool,
+    raw: Vec<u8>,
+    last_raw: Option<Box<[u8]>>,
+    last_start: usize,
+    max_marker_len: usize,
+    max_elements_per_entry: usize,
+    max_depth: usize,
+    depth: usize,
+    fail_on_missing_marker: bool,
+    extensions: HashMap<u8, ExtensionDecoder>,
+    strict: bool,
+    collection_type: Option<Option<Discriminant<Element>>>,
+    typed_collection: Option<(u8, u32)>,
+    peeked_type_byte: Option<u8>,
+    input_len: Option<usize>,
+    version: u8,
+    lossy_utf8: bool,
+    lossy_substitutions: usize,
+    coalesce_strings: bool,
+    manifest_name: String,
+    dedup_blobs: bool,
+    blobs: Vec<Vec<u8>>,
+    use_string_table: bool,
+    strings: Vec<String>,
+    containers_break_entries: bool,
+    #[cfg(feature = "encoding_rs")]
+    string_encoding: &'static encoding_rs::Encoding,
+    #[cfg(feature = "flate2")]
+    decompress_blobs_up_to: Option<usize>,
+    decode_context: bool,
+    big_endian: bool,
 }
 
+/// The default name of the [manifest entry][Reader::read_manifest].
+pub const DEFAULT_MANIFEST_NAME: &str = "__schema__";
+
+/// The default [maximum nesting depth][Reader::max_depth] of elements.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
 const UNEXPECTED_EOF: &str = "expected more, got EOF";
+const SIZE_EXCEEDS_INPUT: &str = "declared size exceeds remaining input";
+const SIZE_EXCEEDS_ADDRESS_SPACE: &str = "declared size exceeds the address space";
+const MARKER_SIZE_OVERFLOW: &str = "marker size exceeds the address space";
+
+/// Maximum number of bytes allocated upfront for values whose count is read from the source.
+const MAX_PREALLOCATED_LEN: usize = 64 * 1024;
+
+/// Representation of an element that has been skipped by a reader.
+///
+/// Markers are always fully read when skipped, since their name is needed to track entries.
+/// Both variants hold the number of bytes the skipped element occupies.
+enum Skipped {
+    Marker { name: String, len: usize },
+    Data { type_byte: u8, len: usize },
+}
+
+/// Decompress the given compressed blob data to the given destination, returning the length
+/// of the blob, or an [Error::BlobTooLarge] as soon as it exceeds the given limit.
+#[cfg(feature = "flate2")]
+fn decompress_blob<W: io::Write>(
+    pos: usize,
+    data: &[u8],
+    limit: usize,
+    out: &mut W,
+) -> Result<usize> {
+    use std::io::Read;
+
+    // One more byte than the limit is enough to tell that the blob is too large.
+    let mut decoder =
+        flate2::read::DeflateDecoder::new(data).take((limit as u64).saturating_add(1));
+    let len = io::copy(&mut decoder, out).map_err(|_| Syntax(pos, "invalid compressed blob"))?;
+    if len > limit as u64 {
+        return Err(Error::BlobTooLarge { pos, limit });
+    }
+    Ok(len as usize)
+}
 
 impl<T> Reader<T>
 where
@@ -96,44 +402,553 @@ where
             bytes_read: 0,
             current_name: String::from("/"),
             retained_element: None,
+            retained_raw: None,
+            retained_start: 0,
+            retain_raw: false,
+            raw_marker: false,
+            raw: Vec::new(),
+            last_raw: None,
+            last_start: 0,
+            max_marker_len: usize::MAX,
+            max_elements_per_entry: usize::MAX,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            fail_on_missing_marker: false,
+            extensions: HashMap::new(),
+            strict: false,
+            collection_type: None,
+            typed_collection: None,
+            peeked_type_byte: None,
+            input_len: None,
+            version: LATEST_VERSION,
+            lossy_utf8: false,
+            lossy_substitutions: 0,
+            coalesce_strings: false,
+            manifest_name: String::from(DEFAULT_MANIFEST_NAME),
+            dedup_blobs: false,
+            blobs: Vec::new(),
+            use_string_table: false,
+            strings: Vec::new(),
+            containers_break_entries: false,
+            #[cfg(feature = "encoding_rs")]
+            string_encoding: encoding_rs::UTF_8,
+            #[cfg(feature = "flate2")]
+            decompress_blobs_up_to: None,
+            decode_context: false,
+            big_endian: false,
         }
     }
 
+    /// Enable or disable strict mode for this reader.
+    ///
+    /// In strict mode, the reader validates semantic constraints that lenient (default) mode
+    /// lets through. Currently, this means that every element following a
+    /// [collection][Element::Collection], up to the next marker or folder, must be of the same
+    /// type, otherwise an [Error::HeterogeneousCollection] is returned, that
+    /// [typed collections][Element::TypedCollection] must be followed by exactly the elements
+    /// they announce, otherwise an [Error::InvalidTypedCollection] is returned, and that the
+    /// sizes of markers must be minimally encoded, otherwise an [Error::NonCanonicalSize] is
+    /// returned.
+    pub fn strict(mut self, strict: bool) -> Reader<T> {
+        self.strict = strict;
+        self
+    }
+
+    /// Enable or disable the retention of raw bytes for this reader.
+    ///
+    /// When enabled, the reader keeps a copy of the exact bytes every element has been decoded
+    /// from, which can then be obtained through [read_raw_element][Self::read_raw_element]. This
+    /// makes it possible to re-emit untouched elements verbatim, even if their original encoding
+    /// is not the one the [writer][crate::Writer] would have chosen.
+    ///
+    /// Note that this doubles the memory used to read strings and blobs.
+    pub fn retain_raw(mut self, retain_raw: bool) -> Reader<T> {
+        self.retain_raw = retain_raw;
+        self
+    }
+
+    /// Set the maximum length of marker names accepted by this reader.
+    ///
+    /// Marker names declared as longer than this limit are rejected with an
+    /// [Error::MarkerTooLong] before any memory is allocated for them, which protects against
+    /// payloads declaring giant names. By default, marker names are not limited.
+    ///
+    /// Regardless of this limit, the size of a marker must fit in a `usize`: marker names can be
+    /// at most 4 GiB long on 32-bit platforms, and 16 EiB long on 64-bit platforms, where their
+    /// size takes at most 10 bytes to encode. Larger sizes are rejected with an [Error::Syntax].
+    pub fn max_marker_len(mut self, max_marker_len: usize) -> Reader<T> {
+        self.max_marker_len = max_marker_len;
+        self
+    }
+
+    /// Set the maximum nesting depth of the elements accepted by this reader.
+    ///
+    /// [Flagged elements][Element::Flagged] and [maps][Element::Map] hold other elements, which
+    /// are decoded recursively. Elements nested deeper than this limit are rejected with an
+    /// [Error::TooDeep], which protects against payloads nesting elements until the stack
+    /// overflows. By default, elements can be nested [DEFAULT_MAX_DEPTH] levels deep.
+    pub fn max_depth(mut self, max_depth: usize) -> Reader<T> {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the maximum number of elements accepted in a single entry by this reader.
+    ///
+    /// [Reading an entry][Self::read_entry] holding more elements than this limit fails with an
+    /// [Error::TooManyElements] as soon as the limit is exceeded, which protects against payloads
+    /// made of a giant number of small elements. By default, entries are not limited.
+    pub fn max_elements_per_entry(mut self, max_elements_per_entry: usize) -> Reader<T> {
+        self.max_elements_per_entry = max_elements_per_entry;
+        self
+    }
+
+    /// Set whether [reading until a marker][Self::read_until_marker] fails if the end of file
+    /// is reached before the marker is found.
+    ///
+    /// By default, the elements collected before the end of file are returned. When enabled, an
+    /// [Error::MarkerNotFound] is returned instead.
+    pub fn fail_on_missing_marker(mut self, fail_on_missing_marker: bool) -> Reader<T> {
+        self.fail_on_missing_marker = fail_on_missing_marker;
+        self
+    }
+
+    /// Set the TPK format version of the data read by this reader.
+    ///
+    /// Element types introduced after that version are rejected with an
+    /// [Error::TypeNotInVersion], which makes sure that the data can be read by older consumers.
+    /// By default, the [latest version][LATEST_VERSION] is used.
+    pub fn version(mut self, version: u8) -> Reader<T> {
+        self.version = version;
+        self
+    }
+
+    /// Enable or disable lossy UTF-8 decoding for this reader.
+    ///
+    /// By default, marker names and strings holding invalid UTF-8 data are rejected with an
+    /// [Error::InvalidString]. When lossy decoding is enabled, invalid sequences are replaced
+    /// with the replacement character (`U+FFFD`) instead, as [String::from_utf8_lossy] does,
+    /// and the number of affected names and strings is recorded as
+    /// [lossy substitutions][Self::lossy_substitutions].
+    pub fn lossy_utf8(mut self, lossy_utf8: bool) -> Reader<T> {
+        self.lossy_utf8 = lossy_utf8;
+        self
+    }
+
+    /// Set the encoding of the marker names and strings read by this reader.
+    ///
+    /// TPK strings are UTF-8 by default, but some producers write them in a legacy encoding,
+    /// such as Latin-1 ([WINDOWS_1252][encoding_rs::WINDOWS_1252]). Names and strings are then
+    /// decoded from the given encoding, and invalid data is rejected with an
+    /// [Error::InvalidEncoding], unless [lossy decoding][Self::lossy_utf8] is enabled.
+    #[cfg(feature = "encoding_rs")]
+    pub fn string_encoding(mut self, encoding: &'static encoding_rs::Encoding) -> Reader<T> {
+        self.string_encoding = encoding;
+        self
+    }
+
+    /// Decompress the compressed blobs read by this reader, up to the given number of bytes each.
+    ///
+    /// Extension elements with the [COMPRESSED_BLOB_EXTENSION_ID] identifier, as written by
+    /// [writers compressing blobs][crate::Writer::compress_blobs_over], are then returned as
+    /// [blobs][Element::Blob]. Blobs that decompress to more bytes than the limit are rejected
+    /// with an [Error::BlobTooLarge]. By default, compressed blobs are returned as
+    /// [extension elements][Element::Extension], like readers built without the `flate2` feature
+    /// do.
+    #[cfg(feature = "flate2")]
+    pub fn decompress_blobs_up_to(mut self, limit: usize) -> Reader<T> {
+        self.decompress_blobs_up_to = Some(limit);
+        self
+    }
+
+    /// Enable or disable the decoding context of the errors of this reader.
+    ///
+    /// When enabled, [syntax errors][Error::Syntax] and
+    /// [invalid string errors][Error::InvalidString] happening while
+    /// [reading an element][Self::read_element] are wrapped in an [Error::DecodeContext] holding
+    /// the bytes of the element read so far, such as its type byte and size bytes. This makes
+    /// it easier to diagnose corrupted data.
+    ///
+    /// Note that, like [raw bytes retention][Self::retain_raw], this doubles the memory used to
+    /// read strings and blobs.
+    pub fn decode_context(mut self, decode_context: bool) -> Reader<T> {
+        self.decode_context = decode_context;
+        self
+    }
+
+    /// Enable or disable the coalescing of strings for this reader.
+    ///
+    /// When enabled, consecutive string elements of the [entries][Self::read_entry] read by this
+    /// reader are merged into a single string element, as [Entry::coalesce_strings] does. This is
+    /// lossy: the boundaries between the original string elements are lost.
+    pub fn coalesce_strings(mut self, coalesce_strings: bool) -> Reader<T> {
+        self.coalesce_strings = coalesce_strings;
+        self
+    }
+
+    /// Set the name of the [manifest entry][Self::read_manifest] of the data read by this reader.
+    ///
+    /// By default, the manifest entry is named [`__schema__`][DEFAULT_MANIFEST_NAME].
+    pub fn manifest_name<S: Into<String>>(mut self, manifest_name: S) -> Reader<T> {
+        self.manifest_name = manifest_name.into();
+        self
+    }
+
+    /// Enable or disable the resolution of blob references for this reader.
+    ///
+    /// When enabled, the reader remembers every [blob][Element::Blob] it reads, and returns
+    /// [blob references][Element::BlobRef] as the blobs they refer to, as written by a
+    /// [writer deduplicating blobs][crate::Writer::dedup_blobs]. References to unknown blobs are
+    /// rejected with an [Error::Syntax]. This mode should be enabled before reading anything, so
+    /// that blob indices match on both sides.
+    ///
+    /// Note that every blob is kept in memory for the whole lifetime of the reader.
+    pub fn dedup_blobs(mut self, dedup_blobs: bool) -> Reader<T> {
+        self.dedup_blobs = dedup_blobs;
+        self
+    }
+
+    /// Enable or disable the string table of this reader.
+    ///
+    /// When enabled, the reader numbers the [strings][Element::String] of the string table
+    /// entries it reads, named [`__strings__`][STRING_TABLE_NAME], and returns
+    /// [string references][Element::StringRef] as the strings they refer to, as written by a
+    /// [writer using a string table][crate::Writer::use_string_table]. References to unknown
+    /// strings are rejected with an [Error::Syntax]. String table entries are not returned by
+    /// [read_entry][Self::read_entry], nor counted by [nth_entry][Self::nth_entry]. This mode
+    /// should be enabled before reading anything, so that string indices match on both sides.
+    ///
+    /// Note that every string of the table is kept in memory for the whole lifetime of the
+    /// reader.
+    pub fn use_string_table(mut self, use_string_table: bool) -> Reader<T> {
+        self.use_string_table = use_string_table;
+        self
+    }
+
+    /// Set whether [folders][Element::Folder] and [collections][Element::Collection] end the
+    /// [entries][Self::read_entry] read by this reader.
+    ///
+    /// By default, folders and collections are part of the entry they appear in, like any other
+    /// element. When enabled, a folder or collection following other elements of an entry ends
+    /// that entry, as a marker would: it then starts a new entry with the same name. Folders and
+    /// collections directly following a marker still belong to its entry.
+    ///
+    /// When building a [Value][crate::value::Value] tree out of the document, this means that an
+    /// entry such as `a: 1, Folder` becomes the value `1` followed by a nested map named `a`,
+    /// instead of a list holding `1` and an empty map.
+    pub fn containers_break_entries(mut self, containers_break_entries: bool) -> Reader<T> {
+        self.containers_break_entries = containers_break_entries;
+        self
+    }
+
+    /// Get the number of marker names and strings that have been decoded lossily by this
+    /// reader.
+    ///
+    /// This is always zero unless [lossy UTF-8 decoding][Self::lossy_utf8] is enabled.
+    pub fn lossy_substitutions(&self) -> usize {
+        self.lossy_substitutions
+    }
+
+    /// Set the total length of the input of this reader, in bytes.
+    ///
+    /// When the input length is known, strings, blobs, markers and extensions declaring a size
+    /// larger than the remaining input are rejected with an [Error::Syntax] before any memory is
+    /// allocated for them, instead of failing once the end of file is reached. For seekable
+    /// sources, the input length can be [detected][Reader::detect_input_len] instead.
+    pub fn input_len(mut self, input_len: usize) -> Reader<T> {
+        self.input_len = Some(input_len);
+        self
+    }
+
+    /// Register a decoder for the [extension elements][Element::Extension] with the given
+    /// identifier.
+    ///
+    /// Once registered, the decoder is called with the payload of every extension element with
+    /// that identifier, and the element it returns is returned by the reader instead of the raw
+    /// extension element. Registering a decoder for an identifier that already has one replaces
+    /// it.
+    pub fn register_extension(mut self, id: u8, decoder: ExtensionDecoder) -> Reader<T> {
+        self.extensions.insert(id, decoder);
+        self
+    }
+
     /// Read an [element][Element] from this reader.
     ///
     /// This function will consume bytes from the source reader, and will attempt to parse them
     /// and construct a new [element][Element].
     pub fn read_element(&mut self) -> Result<Option<Element>> {
-        if let Some(retained_element) = self.retained_element.take() {
+        if let Some(retained_element) = self.take_retained() {
             return Ok(Some(retained_element));
         }
 
-        let mut type_byte_buf = [0u8; 1];
-        let bytes_read = self.read.read(&mut type_byte_buf)?;
-        if bytes_read == 0 {
-            return Ok(None);
+        self.raw.clear();
+        let element_start = self.bytes_read;
+        let type_byte = match self.read_type_byte()? {
+            Some(type_byte) => type_byte,
+            None => {
+                if self.strict && matches!(self.typed_collection, Some((_, 1..))) {
+                    return Err(Error::InvalidTypedCollection { pos: element_start });
+                }
+                return Ok(None);
+            }
+        };
+        let is_marker = type_byte & 0b10000000 != 0;
+        let element = if is_marker {
+            self.read_raw_marker(type_byte)
+        } else {
+            self.read_data_element(type_byte)
+        }
+        .map_err(|e| self.with_decode_context(e))?;
+
+        if self.strict {
+            self.check_collection(&element, element_start)?;
+        }
+        self.last_raw = (self.retain_raw || is_marker).then(|| self.raw.as_slice().into());
+        self.last_start = element_start;
+        Ok(Some(element))
+    }
+
+    /// Get the type byte of the next [element][Element] of this reader, without decoding it.
+    ///
+    /// The type byte is kept by this reader, and will be consumed by the next read as if it had
+    /// not been peeked at. If an element has been retained by this reader, its type byte is
+    /// returned instead. This function returns `None` if the end of file has been reached.
+    pub fn peek_type_byte(&mut self) -> Result<Option<u8>> {
+        if let Some(ref retained_element) = self.retained_element {
+            return Ok(Some(match self.retained_raw {
+                Some(ref raw) => raw[0],
+                None => retained_element.get_type_byte(),
+            }));
+        }
+
+        while self.peeked_type_byte.is_none() {
+            // The byte is not recorded as raw bytes, so `read_bytes` is not used.
+            let mut type_byte_buf = [0u8; 1];
+            match self.read.read(&mut type_byte_buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => self.peeked_type_byte = Some(type_byte_buf[0]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(self.peeked_type_byte)
+    }
+
+    /// Get the next [element][Element] of this reader, without consuming it.
+    ///
+    /// The element is decoded and retained by this reader: the next read returns it (or, for
+    /// [entries][Self::read_entry], starts with it) as if it had not been peeked at. Peeking
+    /// several times in a row returns the same element. This function returns `None` if the end
+    /// of file has been reached.
+    pub fn peek_element(&mut self) -> Result<Option<&Element>> {
+        if self.retained_element.is_none() {
+            match self.read_element()? {
+                Some(element) => self.retain(element),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.retained_element.as_ref())
+    }
+
+    /// Read the next [element][Element] of this reader, which must be a [blob][Element::Blob],
+    /// into the given buffer.
+    ///
+    /// The data of the blob is copied into the beginning of the buffer without allocating any
+    /// memory, and its length is returned. [Blob references][Element::BlobRef] and compressed
+    /// blobs are accepted too when this reader resolves them to blobs, that is when
+    /// [deduplicating blobs][Reader::dedup_blobs] or decompressing them. If the next element is
+    /// not a blob, an
+    /// [Error::UnexpectedType] is returned and the element is left for the next read. If the
+    /// blob is larger than the buffer, an [Error::BufferTooSmall] is returned and the blob is
+    /// skipped. If the end of file has been reached, an [Error::Eof] is returned.
+    pub fn read_blob_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if let Some(retained_element) = self.take_retained() {
+            let data = match retained_element {
+                Element::Blob(ref data) => data,
+                element => return Err(self.unexpected_retained(element)),
+            };
+            if data.len() > buf.len() {
+                return Err(Error::BufferTooSmall {
+                    needed: data.len(),
+                    have: buf.len(),
+                });
+            }
+            buf[..data.len()].copy_from_slice(data);
+            return Ok(data.len());
+        }
+
+        let type_byte = self.peek_type_byte()?.ok_or(Error::Eof)?;
+        if type_byte & 0xFC == 0b01110000 {
+            // Extensions are decoded first, so blob references and compressed blobs resolve to
+            // blobs when this reader is configured to.
+            if let Some(element) = self.read_element()? {
+                self.retain(element);
+            }
+            return self.read_blob_into(buf);
+        }
+        if type_byte & 0xFC != 0b00010100 {
+            return Err(Error::UnexpectedType {
+                pos: self.bytes_read,
+                byte: type_byte,
+            });
         }
+
+        self.raw.clear();
+        let element_start = self.bytes_read;
+        self.read_type_byte()?;
+        let size = self.read_bundled_size(type_byte)?;
+        if size > buf.len() {
+            if self.dedup_blobs {
+                // Later blob references may point to this blob, so it must still be remembered.
+                let data = self.expect_heap(size)?;
+                self.blobs.push(data);
+            } else {
+                self.skip(size)?;
+            }
+            return Err(Error::BufferTooSmall {
+                needed: size,
+                have: buf.len(),
+            });
+        }
+
+        let bytes_read = self.read_bytes(&mut buf[..size])?;
         self.previous_bytes_read = self.bytes_read;
         self.bytes_read += bytes_read;
-        let type_byte = type_byte_buf[0];
-        if type_byte & 0b10000000 != 0 {
-            let element = self.read_marker(type_byte)?;
-            return Ok(Some(element));
+        if bytes_read != size {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
         }
+        if self.strict {
+            self.check_collection(&Element::Blob(Vec::new()), element_start)?;
+        }
+        if self.dedup_blobs {
+            self.blobs.push(buf[..size].to_vec());
+        }
+        self.last_raw = self.retain_raw.then(|| self.raw.as_slice().into());
+        self.last_start = element_start;
+        Ok(size)
+    }
 
-        #[allow(deprecated)]
-        let element = match (type_byte & 0xF0) >> 4 {
-            0b0000 => self.read_folder(type_byte),
-            0b0010 => self.read_number(type_byte),
-            0b0011 => self.read_boolean(type_byte),
-            0b0001 => self.read_string_or_blob(type_byte),
-            0b0111 => Err(Error::UnsupportedType(
-                self.previous_bytes_read,
-                "extension",
-            )),
-            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
-        }?;
-        Ok(Some(element))
+    /// Read the header of a [typed collection][Element::TypedCollection] from this reader.
+    ///
+    /// This returns the type byte and the number of the elements of the collection, which are
+    /// left for the next reads: for numbers, they can be read at once using
+    /// [read_scalar_array][Self::read_scalar_array]. If the next element is not a typed
+    /// collection, an [Error::UnexpectedType] is returned and the element is left for the next
+    /// read. If the end of file has been reached, an [Error::Eof] is returned.
+    pub fn read_typed_collection(&mut self) -> Result<(u8, u32)> {
+        match self.read_element()?.ok_or(Error::Eof)? {
+            Element::TypedCollection { type_byte, count } => Ok((type_byte, count)),
+            element => Err(self.unexpected_retained(element)),
+        }
+    }
+
+    /// Read the given number of [number elements][Element] of this reader into a vector of
+    /// [scalars][Scalar].
+    ///
+    /// Every element must hold a value of the requested type, for example a
+    /// [UInteger32][Element::UInteger32] for `u32`. Elements are decoded directly into the
+    /// vector, which is faster than [reading them][Self::read_element] one at a time. If an
+    /// element is of another type, an [Error::UnexpectedType] is returned and the element is left
+    /// for the next read. If the end of file is reached before all elements have been read, an
+    /// [Error::Eof] is returned.
+    pub fn read_scalar_array<S: Scalar>(&mut self, count: usize) -> Result<Vec<S>> {
+        // The count usually comes from the source, so it cannot be trusted to allocate memory.
+        let mut values = Vec::with_capacity(count.min(MAX_PREALLOCATED_LEN / S::SIZE));
+        for _ in 0..count {
+            if let Some(retained_element) = self.take_retained() {
+                match retained_element.value_bytes() {
+                    Some(ref bytes) if retained_element.get_type_byte() == S::TYPE_BYTE => {
+                        values.push(S::from_le_slice(bytes));
+                        continue;
+                    }
+                    _ => return Err(self.unexpected_retained(retained_element)),
+                }
+            }
+
+            let type_byte = self.peek_type_byte()?.ok_or(Error::Eof)?;
+            if type_byte != S::TYPE_BYTE {
+                return Err(Error::UnexpectedType {
+                    pos: self.bytes_read,
+                    byte: type_byte,
+                });
+            }
+
+            self.raw.clear();
+            let element_start = self.bytes_read;
+            self.read_type_byte()?;
+            self.check_version(type_byte)?;
+            let mut bytes = S::Bytes::default();
+            let bytes_read = self.read_bytes(bytes.as_mut())?;
+            self.previous_bytes_read = self.bytes_read;
+            self.bytes_read += bytes_read;
+            if bytes_read != S::SIZE {
+                return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+            }
+            if self.big_endian {
+                bytes.as_mut().reverse();
+            }
+            let value = S::from_le_slice(bytes.as_ref());
+            if self.strict {
+                self.check_collection(&value.into(), element_start)?;
+            }
+            self.last_raw = self.retain_raw.then(|| self.raw.as_slice().into());
+            self.last_start = element_start;
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Read the next [element][Element] of this reader, only if it matches the given predicate.
+    ///
+    /// The next element is [peeked at][Self::peek_element] and passed to the predicate: if it
+    /// returns `true`, the element is consumed and returned, otherwise it is retained by this
+    /// reader and `None` is returned. This is useful to parse optional elements.
+    pub fn read_if<F>(&mut self, pred: F) -> Result<Option<Element>>
+    where
+        F: Fn(&Element) -> bool,
+    {
+        match self.peek_element()? {
+            Some(element) if pred(element) => self.read_element(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Read a [copy-on-write element][ElementCow] from this reader.
+    ///
+    /// This function behaves like [read_element][Self::read_element]: since this reader does
+    /// not borrow from its source, the returned element always owns its data.
+    pub fn read_element_cow(&mut self) -> Result<Option<ElementCow<'static>>> {
+        Ok(self.read_element()?.map(ElementCow::from))
+    }
+
+    /// Read an [element][Element] from this reader, along with the raw bytes it was decoded
+    /// from.
+    ///
+    /// This function behaves like [read_element][Self::read_element], except that the returned
+    /// [RawElement] also holds the raw bytes of the element if it is a marker, or if
+    /// [raw bytes retention][Self::retain_raw] is enabled.
+    pub fn read_raw_element(&mut self) -> Result<Option<RawElement>> {
+        let element = self.read_element()?;
+        Ok(element.map(|element| RawElement {
+            element,
+            raw: self.last_raw.take(),
+        }))
+    }
+
+    /// Read an [element][Element] from this reader, along with the range of bytes it occupied
+    /// in the source.
+    ///
+    /// This function behaves like [read_element][Self::read_element]. Ranges of successive
+    /// elements are contiguous, and cover the whole source when it is read to its end, including
+    /// elements retained by this reader (for example, because they have been
+    /// [peeked at][Self::peek_element]).
+    pub fn read_element_ranged(&mut self) -> Result<Option<(Element, Range<usize>)>> {
+        if let Some(retained_element) = self.take_retained() {
+            return Ok(Some((retained_element, self.last_start..self.bytes_read)));
+        }
+
+        let start = self.bytes_read;
+        let element = self.read_element()?;
+        Ok(element.map(|element| (element, start..self.bytes_read)))
     }
 
     /// Read an [entry][Entry] from this reader.
@@ -147,6 +962,16 @@ where
     /// element read, and if the entry does not begin with an marker element, the remembered
     /// marker will be used instead.
     pub fn read_entry(&mut self) -> Result<Option<Entry>> {
+        loop {
+            match self.read_any_entry()? {
+                Some(entry) if self.use_string_table && entry.name == STRING_TABLE_NAME => (),
+                entry => return Ok(entry),
+            }
+        }
+    }
+
+    /// Read the next [entry][Entry] from this reader, string table entries included.
+    fn read_any_entry(&mut self) -> Result<Option<Entry>> {
         let first_element = self.read_element()?;
         if first_element.is_none() {
             return Ok(None);
@@ -157,143 +982,2137 @@ where
             name
         } else {
             elements.push(first_element.unwrap());
+            self.check_entry_len(&self.current_name, elements.len())?;
             self.current_name.clone()
         };
 
         while let Some(element) = self.read_element()? {
             match element {
                 Element::Marker(name) => {
-                    self.retained_element = Some(Element::Marker(name));
+                    self.retain(Element::Marker(name));
+                    break;
+                }
+                Element::Folder | Element::Collection
+                    if self.containers_break_entries && !elements.is_empty() =>
+                {
+                    self.retain(element);
                     break;
                 }
                 _ => {
                     elements.push(element);
+                    self.check_entry_len(&name, elements.len())?;
                 }
             }
         }
 
-        Ok(Some(Entry { name, elements }))
+        let mut entry = Entry { name, elements };
+        if self.coalesce_strings {
+            entry.coalesce_strings();
+        }
+        Ok(Some(entry))
     }
 
-    fn read_marker(&mut self, type_byte: u8) -> Result<Element> {
-        let mut has_more = type_byte & 0b01000000 != 0;
-        let mut size = (type_byte & 0b111111) as usize;
-        let mut shift = 6;
-        while has_more {
-            let byte = self.expect::<1>()?[0];
-            has_more = byte & 0b10000000 != 0;
-            size |= ((byte & 0b01111111) as usize) << shift;
-            shift += 7;
+    /// Read the manifest [entry][Entry] of this reader, if there is one.
+    ///
+    /// The next entry is read only if its marker has the [manifest name][Self::manifest_name].
+    /// Otherwise, this function returns `None` and the element that has been read is retained
+    /// by this reader, so that the data can then be read normally. This is meant to be called
+    /// before anything else is read, to discover the structure of a payload.
+    pub fn read_manifest(&mut self) -> Result<Option<Entry>> {
+        self.peek_element()?;
+        match self.retained_element {
+            Some(Element::Marker(ref name)) if *name == self.manifest_name => self.read_entry(),
+            _ => Ok(None),
         }
+    }
 
-        let name = self.read_utf8_string(size)?;
-        self.current_name.clear();
-        self.current_name.push_str(name.as_str());
-        Ok(Element::Marker(name))
+    /// Read every [element][Element] from this reader, up to the marker with the given name.
+    ///
+    /// The marker with the given name is not included in the returned elements: it is retained
+    /// by this reader, and will be returned by the next read. Other markers are collected like
+    /// any other element.
+    ///
+    /// If the end of file is reached before the marker is found, the collected elements are
+    /// returned, unless the reader has been configured to
+    /// [fail on missing markers][Self::fail_on_missing_marker].
+    pub fn read_until_marker(&mut self, name: &str) -> Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        while let Some(element) = self.read_element()? {
+            match element {
+                Element::Marker(ref marker_name) if marker_name == name => {
+                    self.retain(element);
+                    return Ok(elements);
+                }
+                _ => elements.push(element),
+            }
+        }
+
+        if self.fail_on_missing_marker {
+            return Err(Error::MarkerNotFound { name: name.into() });
+        }
+        Ok(elements)
     }
 
-    fn read_folder(&mut self, type_byte: u8) -> Result<Element> {
-        match type_byte {
-            0 => Ok(Element::Folder),
-            1 => Ok(Element::Collection),
-            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
+    /// Make sure this reader has reached the end of file.
+    ///
+    /// This function returns an [Error::TrailingData] if there is anything left to read, including
+    /// an element retained by this reader. This is useful to reject payloads containing
+    /// unexpected data after their logical end.
+    ///
+    /// Note that this function consumes one byte from the source reader if it is not exhausted.
+    pub fn expect_eof(&mut self) -> Result<()> {
+        if self.retained_element.is_some() {
+            return Err(Error::TrailingData {
+                pos: self.retained_start,
+            });
+        }
+
+        match self.read_type_byte()? {
+            Some(_) => Err(Error::TrailingData {
+                pos: self.previous_bytes_read,
+            }),
+            None => Ok(()),
         }
     }
 
-    fn read_number(&mut self, type_byte: u8) -> Result<Element> {
-        match type_byte {
-            0b00100000 => Ok(Element::UInteger8(self.expect::<1>()?[0])),
-            0b00100001 => Ok(Element::UInteger16(LE::read_u16(
-                self.expect::<2>()?.as_slice(),
-            ))),
-            0b00100010 => Ok(Element::UInteger32(LE::read_u32(
-                self.expect::<4>()?.as_slice(),
-            ))),
-            0b00100011 => Ok(Element::UInteger64(LE::read_u64(
-                self.expect::<8>()?.as_slice(),
-            ))),
+    /// Read a whole [document][Document] from this reader.
+    ///
+    /// This function reads [entries][Self::read_entry] until the end of file is reached.
+    pub fn read_document(&mut self) -> Result<Document> {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.read_entry()? {
+            entries.push(entry);
+        }
+        Ok(Document { entries })
+    }
+
+    /// Read a whole [document][Document] from this reader, reporting progress along the way.
+    ///
+    /// This function behaves like [read_document][Self::read_document], and calls the given
+    /// callback with the number of bytes read so far after each entry. Since reading an entry
+    /// also reads the marker of the next one, that marker is included in the reported count.
+    pub fn read_document_with_progress<F>(&mut self, mut progress: F) -> Result<Document>
+    where
+        F: FnMut(usize),
+    {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.read_entry()? {
+            entries.push(entry);
+            progress(self.bytes_read);
+        }
+        Ok(Document { entries })
+    }
+
+    /// Read every remaining [entry][Entry] of this reader into a map of single elements.
+    ///
+    /// This is meant for payloads where every entry holds exactly one element, such as
+    /// configuration files. Each entry name is mapped to its element, and an
+    /// [Error::MultiValueEntry] is returned if an entry holds more than one element. Entries
+    /// without any element are ignored.
+    ///
+    /// Note that if several entries share the same name, the last one overwrites the others.
+    pub fn read_map(&mut self) -> Result<HashMap<String, Element>> {
+        let mut map = HashMap::new();
+        while let Some(mut entry) = self.read_entry()? {
+            match entry.elements.len() {
+                0 => (),
+                1 => {
+                    map.insert(entry.name, entry.elements.remove(0));
+                }
+                _ => return Err(Error::MultiValueEntry { name: entry.name }),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Read the name of the first [marker][Element::Marker] of this reader.
+    ///
+    /// Elements preceding the marker are skipped without being decoded, which makes this a cheap
+    /// way to identify a TPK payload. This function returns `None` if the end of file is reached
+    /// before any marker.
+    ///
+    /// Note that this function is relative to the current position of the reader, which is left
+    /// right after the marker: the next [entry][Self::read_entry] read is the rest of the entry
+    /// started by the marker.
+    pub fn first_marker_name(&mut self) -> Result<Option<String>> {
+        loop {
+            match self.skip_element()? {
+                Some(Skipped::Marker { name, .. }) => return Ok(Some(name)),
+                Some(Skipped::Data { .. }) => (),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Read the names of every remaining [marker][Element::Marker] of this reader, in order.
+    ///
+    /// Other elements are skipped without being decoded, like [nth_entry][Self::nth_entry] does,
+    /// which makes this fast even on payloads holding large blobs. The reader is left at the end
+    /// of file.
+    pub fn marker_names(&mut self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        while let Some(skipped) = self.skip_element()? {
+            if let Skipped::Marker { name, .. } = skipped {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Read every remaining [element][Element] of this reader, along with its nesting depth.
+    ///
+    /// Depths follow the rules used to build a [Value][crate::value::Value] tree out of a
+    /// document:
+    ///
+    /// - markers are at the depth of the folder they appear in, starting at 0, and the elements
+    ///   of their entry are one level deeper;
+    /// - an entry holding a single [folder][Element::Folder] opens a nested folder: the entries
+    ///   following it are one level deeper, until an entry named `..` without any element
+    ///   closes it, or an entry named `/` without any element closes all of them. These closing
+    ///   markers are at the depth of the folder they close;
+    /// - the elements following a [collection][Element::Collection], typed or not, starting an
+    ///   entry are one level deeper than the collection itself, until the next marker.
+    pub fn read_with_depth(&mut self) -> Result<Vec<(usize, Element)>> {
+        let mut elements = Vec::new();
+        let mut depth = 0usize;
+        let mut entry_len = 0;
+        let mut in_collection = false;
+        while let Some(element) = self.read_element()? {
+            match element {
+                Element::Marker(ref name) => {
+                    let next_depth = match name.as_str() {
+                        ".." if self.next_is_marker_or_eof()? => depth.saturating_sub(1),
+                        "/" if self.next_is_marker_or_eof()? => 0,
+                        _ => depth,
+                    };
+                    elements.push((depth, element));
+                    depth = next_depth;
+                    entry_len = 0;
+                    in_collection = false;
+                }
+                Element::Folder if entry_len == 0 && self.next_is_marker_or_eof()? => {
+                    depth += 1;
+                    elements.push((depth, element));
+                    entry_len += 1;
+                }
+                element => {
+                    let element_depth = depth + 1 + usize::from(in_collection);
+                    if entry_len == 0 {
+                        in_collection = matches!(
+                            element,
+                            Element::Collection | Element::TypedCollection { .. }
+                        );
+                    }
+                    elements.push((element_depth, element));
+                    entry_len += 1;
+                }
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Read every remaining value of this reader, regardless of the entry holding it.
+    ///
+    /// Every element is returned in stream order, except for markers, folders and collections
+    /// (typed or not), which only describe the structure of the data, and padding, which holds
+    /// no value. This is meant for quick dumps of the values of a payload.
+    pub fn read_all_scalars(&mut self) -> Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        while let Some(element) = self.read_element()? {
+            match element {
+                Element::Marker(_)
+                | Element::Folder
+                | Element::Collection
+                | Element::TypedCollection { .. }
+                | Element::Padding(_) => {}
+                element => elements.push(element),
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Read the `n`th [entry][Entry] (starting from zero) from this reader.
+    ///
+    /// The `n` first entries are skipped without being decoded: only their markers are read, and
+    /// the payloads of their other elements are discarded as they are read, which avoids
+    /// allocating memory for data that is not going to be used.
+    ///
+    /// Note that this function is relative to the current position of the reader, meaning that
+    /// `nth_entry(0)` is equivalent to [read_entry][Self::read_entry].
+    pub fn nth_entry(&mut self, n: usize) -> Result<Option<Entry>> {
+        for _ in 0..n {
+            if !self.skip_entry()? {
+                return Ok(None);
+            }
+        }
+        self.read_entry()
+    }
+
+    /// Read the [entries][Entry] whose name is in the given set from this reader.
+    ///
+    /// Only the markers of the other entries are decoded: the rest of these entries is skipped
+    /// like [nth_entry][Self::nth_entry] does. Entries that do not begin with a marker element
+    /// are named after the last marker read, like [read_entry][Self::read_entry] does. The
+    /// returned iterator ends at the end of file, or after the first error.
+    pub fn read_entries_named<'a>(
+        &'a mut self,
+        names: &'a HashSet<String>,
+    ) -> impl Iterator<Item = Result<Entry>> + 'a {
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let result = self.read_entry_named(names).transpose();
+            failed = matches!(result, Some(Err(_)));
+            result
+        })
+    }
+
+    fn read_entry_named(&mut self, names: &HashSet<String>) -> Result<Option<Entry>> {
+        loop {
+            match self.peek_type_byte()? {
+                None => return Ok(None),
+                // Peeking at a marker makes it the current name.
+                Some(type_byte) if type_byte & 0b10000000 != 0 => {
+                    self.peek_element()?;
+                }
+                Some(_) => {}
+            }
+            if names.contains(&self.current_name) {
+                return self.read_entry();
+            }
+            if !self.skip_entry()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Copy the remaining bytes of this reader into the given writer, without decoding them.
+    ///
+    /// This is useful to copy the untouched tail of a TPK payload, after having read and
+    /// transformed the elements that precede it. This function returns the total number of bytes
+    /// written into the writer.
+    ///
+    /// If an element has been retained by this reader (for example, the marker that ended the
+    /// last [entry][Self::read_entry] that was read), it is written first. The raw bytes of
+    /// markers are always kept for this purpose, but other elements (for example, an element
+    /// that has been [peeked at][Self::peek_element]) are re-encoded unless
+    /// [raw bytes retention][Self::retain_raw] is enabled. Their binary representation might
+    /// then differ from the original one, if the latter was not minimal, or if it held a string
+    /// that was decoded [lossily][Self::lossy_utf8] or from another encoding than UTF-8.
+    pub fn copy_remaining_to<W: io::Write>(&mut self, w: &mut W) -> Result<u64> {
+        let mut bytes_written = 0u64;
+        if let Some(retained_element) = self.take_retained() {
+            let retained_bytes = match self.last_raw.take() {
+                Some(raw) => raw.into_vec(),
+                None => {
+                    let mut retained_bytes = Vec::new();
+                    Writer::new(&mut retained_bytes).write_element(&retained_element)?;
+                    retained_bytes
+                }
+            };
+            w.write_all(&retained_bytes)?;
+            bytes_written += retained_bytes.len() as u64;
+        }
+        if let Some(type_byte) = self.peeked_type_byte.take() {
+            w.write_all(&[type_byte])?;
+            self.bytes_read += 1;
+            bytes_written += 1;
+        }
+
+        let bytes_copied = io::copy(&mut self.read, w)?;
+        self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += bytes_copied as usize;
+        Ok(bytes_written + bytes_copied)
+    }
+
+    /// Compute [statistics][Stats] about every remaining element of this reader.
+    ///
+    /// Elements are skipped rather than decoded, which means that the payloads of strings,
+    /// blobs and extensions are never loaded in memory. The byte count of each element includes
+    /// its type byte and size bytes.
+    pub fn statistics(&mut self) -> Result<Stats> {
+        let mut stats = Stats::default();
+        while let Some(skipped) = self.skip_element()? {
+            match skipped {
+                Skipped::Marker { len, .. } => stats.record(ElementKind::Marker, len),
+                Skipped::Data { type_byte, len } => {
+                    let kind = ElementKind::from_type_byte(type_byte)
+                        .ok_or(UnknownType(self.last_start, type_byte))?;
+                    stats.record(kind, len);
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Estimate the memory [reading the remaining document][Self::read_document] of this reader
+    /// would allocate, in bytes.
+    ///
+    /// Elements are skipped rather than decoded, like [statistics][Self::statistics] does: the
+    /// declared sizes of markers, strings, blobs and extensions are summed along with the size of
+    /// the [entries][Entry] and [elements][Element] holding them, while accounting for the
+    /// configuration of this reader (for example, [resolved blob references][Self::dedup_blobs]
+    /// are counted as copies of their blob). The returned size is an upper bound meant to reject
+    /// untrusted payloads before reading them, rather than an exact measure. Payloads of
+    /// [registered extensions][Self::register_extension] are assumed to be decoded into elements
+    /// allocating no more memory than their own size.
+    ///
+    /// The reader is left at the end of file.
+    pub fn estimate_parsed_size(&mut self) -> Result<usize> {
+        // Vectors grow by doubling, so their capacity is at most twice their length, past the
+        // first allocation: the document starts with room for 4 entries, and entries with room
+        // for one element. Coalescing strings copies the elements of each entry once more.
+        let element_size = match self.coalesce_strings {
+            true => 3 * mem::size_of::<Element>(),
+            false => 2 * mem::size_of::<Element>(),
+        };
+        let entry_size = 2 * mem::size_of::<Entry>() + mem::size_of::<Element>();
+        let string_factor = self.string_factor();
+        let mut size = 4 * mem::size_of::<Entry>();
+        let mut blob_sizes: Vec<usize> = self.blobs.iter().map(Vec::len).collect();
+        let mut string_sizes: Vec<usize> = self.strings.iter().map(String::len).collect();
+        let mut in_entry = false;
+
+        if let Some(retained_element) = self.take_retained() {
+            size += match retained_element {
+                Element::Marker(ref name) => entry_size + name.len(),
+                ref element => {
+                    entry_size
+                        + self.current_name.len()
+                        + element_size
+                        + element.encoded_len() * string_factor
+                }
+            };
+            in_entry = true;
+        }
+
+        while let Some(type_byte) = self.read_type_byte()? {
+            let element_size = if type_byte & 0b10000000 != 0 {
+                let len = if self.use_string_table {
+                    // String tables are told apart by their names, which must then be decoded.
+                    self.read_marker(type_byte)?;
+                    self.current_name.len()
+                } else {
+                    let len = self.read_marker_size(type_byte)?;
+                    self.skip(len)?;
+                    len
+                };
+                in_entry = true;
+                entry_size.saturating_add(len.saturating_mul(string_factor))
+            } else {
+                let payload_size =
+                    self.estimate_data_element(type_byte, &mut blob_sizes, &mut string_sizes)?;
+                // Elements preceding the first marker form an entry of their own, and so may
+                // containers when they break entries.
+                if self.containers_break_entries && type_byte & 0b11111110 == 0 {
+                    in_entry = false;
+                }
+                let implicit_entry_size = match in_entry {
+                    true => 0,
+                    false => entry_size + self.current_name.len(),
+                };
+                in_entry = true;
+                (element_size + implicit_entry_size).saturating_add(payload_size)
+            };
+            size = size.saturating_add(element_size);
+        }
+        Ok(size)
+    }
+
+    /// Get the byte offsets at which every remaining [entry][Entry] of this reader starts.
+    ///
+    /// This function skips through the whole source, like [statistics][Self::statistics] does.
+    /// The returned offsets are relative to the start of the source, and can be used to split
+    /// it into chunks that can each be read independently, for example by several threads.
+    ///
+    /// Note that if the remaining data starts with non-marker elements, these elements form an
+    /// entry of their own, as they would when [read][Self::read_entry].
+    pub fn entry_offsets(&mut self) -> Result<Vec<usize>> {
+        let mut offsets = Vec::new();
+        while let Some(skipped) = self.skip_element()? {
+            if matches!(skipped, Skipped::Marker { .. }) || offsets.is_empty() {
+                offsets.push(self.last_start);
+            }
+        }
+        Ok(offsets)
+    }
+
+    fn skip_entry(&mut self) -> Result<bool> {
+        loop {
+            match self.skip_any_entry()? {
+                Some(true) if self.use_string_table => (),
+                skipped => return Ok(skipped.is_some()),
+            }
+        }
+    }
+
+    /// Skip the next [entry][Entry] of this reader, string table entries included, returning
+    /// whether it was a string table.
+    fn skip_any_entry(&mut self) -> Result<Option<bool>> {
+        let (mut has_elements, is_string_table) = match self.skip_element()? {
+            Some(Skipped::Marker { name, .. }) => (false, name == STRING_TABLE_NAME),
+            Some(Skipped::Data { .. }) => (true, self.current_name == STRING_TABLE_NAME),
+            None => return Ok(None),
+        };
+
+        while let Some(skipped) = self.skip_element()? {
+            match skipped {
+                Skipped::Marker { name, .. } => {
+                    self.retain(Element::Marker(name));
+                    break;
+                }
+                Skipped::Data { type_byte, .. }
+                    if type_byte <= 1 && self.containers_break_entries && has_elements =>
+                {
+                    let container = self.read_folder(type_byte)?;
+                    self.retain(container);
+                    break;
+                }
+                Skipped::Data { .. } => has_elements = true,
+            }
+        }
+        Ok(Some(is_string_table))
+    }
+
+    fn skip_element(&mut self) -> Result<Option<Skipped>> {
+        if let Some(retained_element) = self.take_retained() {
+            let len = self.bytes_read - self.last_start;
+            return Ok(Some(match retained_element {
+                Element::Marker(name) => Skipped::Marker { name, len },
+                element => Skipped::Data {
+                    type_byte: element.get_type_byte(),
+                    len,
+                },
+            }));
+        }
+
+        self.raw.clear();
+        let element_start = self.bytes_read;
+        let type_byte = match self.read_type_byte()? {
+            Some(type_byte) => type_byte,
+            None => return Ok(None),
+        };
+        if type_byte & 0b10000000 != 0 {
+            let name = match self.read_raw_marker(type_byte)? {
+                Element::Marker(name) => name,
+                _ => return Err(Error::Unknown),
+            };
+            self.collection_type = None;
+            self.last_raw = Some(self.raw.as_slice().into());
+            self.last_start = element_start;
+            return Ok(Some(Skipped::Marker {
+                name,
+                len: self.bytes_read - element_start,
+            }));
+        }
+
+        self.skip_data_element(type_byte)?;
+        self.last_raw = None;
+        self.last_start = element_start;
+        Ok(Some(Skipped::Data {
+            type_byte,
+            len: self.bytes_read - element_start,
+        }))
+    }
+
+    fn next_is_marker_or_eof(&mut self) -> Result<bool> {
+        Ok(match self.peek_type_byte()? {
+            Some(type_byte) => type_byte & 0b10000000 != 0,
+            None => true,
+        })
+    }
+
+    fn with_decode_context(&self, error: Error) -> Error {
+        match error {
+            Syntax(..) | Error::InvalidString { .. } if self.decode_context => {
+                Error::DecodeContext {
+                    bytes: self.raw.clone(),
+                    source: Box::new(error),
+                }
+            }
+            error => error,
+        }
+    }
+
+    /// Get the name of the entry being read, as [read_entry][Self::read_entry] names entries that
+    /// do not begin with a marker element.
+    #[cfg(feature = "serde")]
+    pub(crate) fn current_name(&self) -> &str {
+        &self.current_name
+    }
+
+    /// Make sure that elements nested at the given depth are within the
+    /// [maximum nesting depth][Self::max_depth] of this reader.
+    #[cfg(feature = "serde")]
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth >= self.max_depth {
+            return Err(Error::TooDeep {
+                pos: self.previous_bytes_read,
+                limit: self.max_depth,
+            });
+        }
+        Ok(())
+    }
+
+    fn retain(&mut self, element: Element) {
+        self.retained_element = Some(element);
+        self.retained_raw = self.last_raw.take();
+        self.retained_start = self.last_start;
+    }
+
+    fn take_retained(&mut self) -> Option<Element> {
+        let retained_element = self.retained_element.take()?;
+        self.last_raw = self.retained_raw.take();
+        self.last_start = self.retained_start;
+        Some(retained_element)
+    }
+
+    fn skip_data_element(&mut self, type_byte: u8) -> Result<()> {
+        self.check_version(type_byte)?;
+        let size = match type_byte {
+            0b00000000 | 0b00000001 | 0b00110000 | 0b00110001 => 0,
+            0b00100000 | 0b00100100 => 1,
+            0b00100001 | 0b00100101 => 2,
+            0b00100010 | 0b00100110 | 0b00101110 => 4,
+            0b00100011 | 0b00100111 | 0b00101111 => 8,
+            0b00101000 | 0b00101001 => 16,
+            // Strings and blobs must be decoded to be remembered in their tables.
+            0b00010000..=0b00010011 if !self.in_string_table() => {
+                self.read_bundled_size(type_byte)?
+            }
+            0b00011100..=0b00011111 => self.read_bundled_size(type_byte)?,
+            0b00010100..=0b00010111 if !self.dedup_blobs => self.read_bundled_size(type_byte)?,
+            // Compressed blobs, flagged elements and maps must be decoded to remember the blobs
+            // they hold.
+            0b01110000..=0b01110011 if !self.dedup_blobs => {
+                self.expect::<1>()?;
+                self.read_bundled_size(type_byte)?
+            }
+            _ => {
+                // Elements without a dedicated skip path are simply decoded and dropped.
+                self.read_data_element(type_byte)?;
+                return Ok(());
+            }
+        };
+        self.skip(size)
+    }
+
+    /// Whether the strings being read belong to a string table, and must be remembered.
+    fn in_string_table(&self) -> bool {
+        self.use_string_table && self.current_name == STRING_TABLE_NAME
+    }
+
+    fn string_factor(&self) -> usize {
+        // Each invalid or non-UTF-8 byte decodes to at most 3 bytes, and coalescing strings may
+        // double the capacity of the merged string.
+        #[cfg(feature = "encoding_rs")]
+        let transcoded = self.string_encoding != encoding_rs::UTF_8;
+        #[cfg(not(feature = "encoding_rs"))]
+        let transcoded = false;
+        let factor = if self.lossy_utf8 || transcoded { 3 } else { 1 };
+        match self.coalesce_strings {
+            true => 2 * factor,
+            false => factor,
+        }
+    }
+
+    fn estimate_data_element(
+        &mut self,
+        type_byte: u8,
+        blob_sizes: &mut Vec<usize>,
+        string_sizes: &mut Vec<usize>,
+    ) -> Result<usize> {
+        self.check_version(type_byte)?;
+        match type_byte {
+            0b00010000..=0b00010011 => {
+                let len = self.read_bundled_size(type_byte)?;
+                self.skip(len)?;
+                let len = len.saturating_mul(self.string_factor());
+                if !self.in_string_table() {
+                    return Ok(len);
+                }
+                string_sizes.push(len);
+                Ok(len.saturating_mul(2))
+            }
+            0b00010100..=0b00010111 => {
+                let len = self.read_bundled_size(type_byte)?;
+                self.skip(len)?;
+                if !self.dedup_blobs {
+                    return Ok(len);
+                }
+                blob_sizes.push(len);
+                Ok(len.saturating_mul(2))
+            }
+            0b00011000 => {
+                // C strings are read byte by byte, so their buffer grows by doubling.
+                let mut len = 0usize;
+                while self.expect::<1>()?[0] != 0 {
+                    len += 1;
+                }
+                Ok(len.saturating_mul(2 * self.string_factor()))
+            }
+            0b01110000..=0b01110011 => {
+                let previous_bytes_read = self.previous_bytes_read;
+                let id = self.expect::<1>()?[0];
+                let len = self.read_bundled_size(type_byte)?;
+                match id {
+                    _ if self.extensions.contains_key(&id) => self.skip(len).map(|_| len),
+                    DELTA_U64_EXTENSION_ID => self.skip(len).map(|_| 16 * len.max(2)),
+                    BLOB_REF_EXTENSION_ID if self.dedup_blobs && len == 4 => {
+                        let index = u32::from_le_bytes(self.expect_ordered::<4>()?) as usize;
+                        blob_sizes
+                            .get(index)
+                            .copied()
+                            .ok_or(Syntax(previous_bytes_read, "reference to an unknown blob"))
+                    }
+                    STRING_REF_EXTENSION_ID if self.use_string_table && len == 4 => {
+                        let index = u32::from_le_bytes(self.expect_ordered::<4>()?) as usize;
+                        string_sizes.get(index).copied().ok_or(Syntax(
+                            previous_bytes_read,
+                            "reference to an unknown string",
+                        ))
+                    }
+                    #[cfg(feature = "flate2")]
+                    COMPRESSED_BLOB_EXTENSION_ID if self.decompress_blobs_up_to.is_some() => {
+                        let data = self.expect_heap(len)?;
+                        let limit = self.decompress_blobs_up_to.unwrap_or(usize::MAX);
+                        let blob_len =
+                            decompress_blob(previous_bytes_read, &data, limit, &mut io::sink())?;
+                        if self.dedup_blobs {
+                            blob_sizes.push(blob_len);
+                            return Ok(blob_len.saturating_mul(3));
+                        }
+                        Ok(blob_len.saturating_mul(2))
+                    }
+                    FLAGGED_EXTENSION_ID => self.read_nested(previous_bytes_read, len, |reader| {
+                        reader.expect::<1>()?;
+                        let inner_type_byte = reader.expect::<1>()?[0];
+                        reader.estimate_data_element(inner_type_byte, blob_sizes, string_sizes)
+                    }),
+                    MAP_EXTENSION_ID => self.read_nested(previous_bytes_read, len, |reader| {
+                        let count = u32::from_le_bytes(reader.expect_ordered::<4>()?) as usize;
+                        // Pairs are pushed one by one, so their vector may be twice as large.
+                        let mut size =
+                            count.saturating_mul(2 * mem::size_of::<(String, Element)>());
+                        for _ in 0..count.saturating_mul(2) {
+                            let type_byte = reader.expect::<1>()?[0];
+                            let element_size = reader.estimate_data_element(
+                                type_byte,
+                                blob_sizes,
+                                string_sizes,
+                            )?;
+                            size = size.saturating_add(element_size);
+                        }
+                        Ok(size)
+                    }),
+                    _ => self.skip(len).map(|_| len),
+                }
+            }
+            _ => self.skip_data_element(type_byte).map(|_| 0),
+        }
+    }
+
+    fn check_entry_len(&self, name: &str, len: usize) -> Result<()> {
+        if len > self.max_elements_per_entry {
+            return Err(Error::TooManyElements {
+                name: name.into(),
+                limit: self.max_elements_per_entry,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_collection(&mut self, element: &Element, pos: usize) -> Result<()> {
+        if let Some((type_byte, remaining)) = self.typed_collection {
+            let is_container = matches!(
+                element,
+                Element::Marker(_)
+                    | Element::Folder
+                    | Element::Collection
+                    | Element::TypedCollection { .. }
+            );
+            match remaining {
+                0 if is_container => (),
+                _ if remaining == 0 || element.get_type_byte() != type_byte => {
+                    return Err(Error::InvalidTypedCollection { pos });
+                }
+                _ => self.typed_collection = Some((type_byte, remaining - 1)),
+            }
+        }
+        match *element {
+            Element::Marker(_) | Element::Folder => {
+                self.collection_type = None;
+                self.typed_collection = None;
+            }
+            Element::Collection => {
+                self.collection_type = Some(None);
+                self.typed_collection = None;
+            }
+            Element::TypedCollection { type_byte, count } => {
+                if !is_number_type_byte(type_byte) {
+                    return Err(Error::InvalidTypedCollection { pos });
+                }
+                self.collection_type = Some(None);
+                self.typed_collection = Some((type_byte, count));
+            }
+            _ => match self.collection_type {
+                Some(Some(collection_type)) if collection_type != mem::discriminant(element) => {
+                    return Err(Error::HeterogeneousCollection { pos });
+                }
+                Some(None) => self.collection_type = Some(Some(mem::discriminant(element))),
+                _ => (),
+            },
+        }
+        Ok(())
+    }
+
+    fn unexpected_retained(&mut self, element: Element) -> Error {
+        let byte = match self.last_raw {
+            Some(ref raw) => raw[0],
+            None => element.get_type_byte(),
+        };
+        let pos = self.last_start;
+        self.retain(element);
+        Error::UnexpectedType { pos, byte }
+    }
+
+    fn check_version(&self, type_byte: u8) -> Result<()> {
+        if type_byte_version(type_byte) > self.version {
+            return Err(Error::TypeNotInVersion {
+                pos: self.previous_bytes_read,
+                byte: type_byte,
+                version: self.version,
+            });
+        }
+        Ok(())
+    }
+
+    fn read_type_byte(&mut self) -> Result<Option<u8>> {
+        if let Some(type_byte) = self.peeked_type_byte.take() {
+            if self.records_raw() {
+                self.raw.push(type_byte);
+            }
+            self.previous_bytes_read = self.bytes_read;
+            self.bytes_read += 1;
+            return Ok(Some(type_byte));
+        }
+
+        let mut type_byte_buf = [0u8; 1];
+        let bytes_read = self.read_bytes(&mut type_byte_buf)?;
+        if bytes_read == 0 {
+            return Ok(None);
+        }
+        self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += bytes_read;
+        Ok(Some(type_byte_buf[0]))
+    }
+
+    fn read_data_element(&mut self, type_byte: u8) -> Result<Element> {
+        self.check_version(type_byte)?;
+        let element = match (type_byte & 0xF0) >> 4 {
+            0b0000 => self.read_folder(type_byte),
+            0b0010 => self.read_number(type_byte),
+            0b0011 => self.read_boolean(type_byte),
+            0b0001 => self.read_string_or_blob(type_byte),
+            0b0111 => self.read_extension(type_byte),
+            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
+        }?;
+        // Resolved blob references are blobs too, but must not be remembered again.
+        if let (true, 0b00010100..=0b00010111, Element::Blob(ref data)) =
+            (self.dedup_blobs, type_byte, &element)
+        {
+            self.blobs.push(data.clone());
+        }
+        // Likewise, resolved string references must not be remembered again, and only the
+        // strings of string tables are.
+        if let (true, 0b00010000..=0b00010011, Element::String(ref val)) =
+            (self.in_string_table(), type_byte, &element)
+        {
+            self.strings.push(val.clone());
+        }
+        Ok(element)
+    }
+
+    fn read_marker(&mut self, type_byte: u8) -> Result<Element> {
+        let size = self.read_marker_size(type_byte)?;
+        let name = self.read_utf8_string(size)?;
+        self.current_name.clear();
+        self.current_name.push_str(name.as_str());
+        Ok(Element::Marker(name))
+    }
+
+    /// Read a marker whose type byte has just been read, recording its raw bytes whether
+    /// [raw bytes retention][Self::retain_raw] is enabled or not.
+    fn read_raw_marker(&mut self, type_byte: u8) -> Result<Element> {
+        if !self.records_raw() {
+            self.raw.push(type_byte);
+            self.raw_marker = true;
+        }
+        let marker = self.read_marker(type_byte);
+        self.raw_marker = false;
+        marker
+    }
+
+    fn records_raw(&self) -> bool {
+        self.retain_raw || self.decode_context || self.raw_marker
+    }
+
+    fn read_marker_size(&mut self, type_byte: u8) -> Result<usize> {
+        let marker_start = self.previous_bytes_read;
+        let mut has_more = type_byte & 0b01000000 != 0;
+        let mut size = (type_byte & 0b111111) as usize;
+        let mut shift = 6;
+        let mut bits = 1;
+        // Continuation bytes cannot be read in bulk without reading past the size, so each of
+        // them costs a read of the source: see the buffering note on [Reader].
+        while has_more {
+            let byte = self.expect::<1>()?[0];
+            has_more = byte & 0b10000000 != 0;
+            bits = (byte & 0b01111111) as usize;
+            if bits != 0 {
+                if shift >= usize::BITS || bits > usize::MAX >> shift {
+                    return Err(Syntax(marker_start, MARKER_SIZE_OVERFLOW));
+                }
+                size |= bits << shift;
+            }
+            shift = shift.saturating_add(7);
+        }
+        // A minimal encoding never ends with a continuation byte holding no bits.
+        if self.strict && bits == 0 {
+            return Err(Error::NonCanonicalSize { pos: marker_start });
+        }
+
+        if size > self.max_marker_len {
+            return Err(Error::MarkerTooLong {
+                pos: marker_start,
+                len: size,
+                limit: self.max_marker_len,
+            });
+        }
+        Ok(size)
+    }
+
+    fn read_folder(&mut self, type_byte: u8) -> Result<Element> {
+        match type_byte {
+            0 => Ok(Element::Folder),
+            1 => Ok(Element::Collection),
+            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
+        }
+    }
+
+    fn read_number(&mut self, type_byte: u8) -> Result<Element> {
+        match type_byte {
+            0b00100000 => Ok(Element::UInteger8(self.expect::<1>()?[0])),
+            0b00100001 => Ok(Element::UInteger16(LE::read_u16(
+                self.expect_ordered::<2>()?.as_slice(),
+            ))),
+            0b00100010 => Ok(Element::UInteger32(LE::read_u32(
+                self.expect_ordered::<4>()?.as_slice(),
+            ))),
+            0b00100011 => Ok(Element::UInteger64(LE::read_u64(
+                self.expect_ordered::<8>()?.as_slice(),
+            ))),
             0b00100100 => Ok(Element::Integer8(self.expect::<1>()?[0] as i8)),
             0b00100101 => Ok(Element::Integer16(LE::read_i16(
-                self.expect::<2>()?.as_slice(),
+                self.expect_ordered::<2>()?.as_slice(),
             ))),
             0b00100110 => Ok(Element::Integer32(LE::read_i32(
-                self.expect::<4>()?.as_slice(),
+                self.expect_ordered::<4>()?.as_slice(),
             ))),
             0b00100111 => Ok(Element::Integer64(LE::read_i64(
-                self.expect::<8>()?.as_slice(),
+                self.expect_ordered::<8>()?.as_slice(),
+            ))),
+            0b00101000 => Ok(Element::UInteger128(LE::read_u128(
+                self.expect_ordered::<16>()?.as_slice(),
+            ))),
+            0b00101001 => Ok(Element::Integer128(LE::read_i128(
+                self.expect_ordered::<16>()?.as_slice(),
             ))),
             0b00101110 => Ok(Element::Float32(LE::read_f32(
-                self.expect::<4>()?.as_slice(),
+                self.expect_ordered::<4>()?.as_slice(),
             ))),
             0b00101111 => Ok(Element::Float64(LE::read_f64(
-                self.expect::<8>()?.as_slice(),
+                self.expect_ordered::<8>()?.as_slice(),
             ))),
             _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
         }
     }
 
-    fn read_boolean(&mut self, type_byte: u8) -> Result<Element> {
-        match type_byte {
-            0b00110000 => Ok(Element::Boolean(false)),
-            0b00110001 => Ok(Element::Boolean(true)),
-            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
+    fn read_extension(&mut self, type_byte: u8) -> Result<Element> {
+        let previous_bytes_read = self.previous_bytes_read;
+        if type_byte & 0b1100 != 0 {
+            return Err(UnknownType(previous_bytes_read, type_byte));
+        }
+
+        let id = self.expect::<1>()?[0];
+        let size = self.read_bundled_size(type_byte)?;
+        if !self.extensions.contains_key(&id) {
+            match id {
+                FLAGGED_EXTENSION_ID => {
+                    return self.read_nested(previous_bytes_read, size, Self::read_flagged)
+                }
+                MAP_EXTENSION_ID => {
+                    return self.read_nested(previous_bytes_read, size, Self::read_map_pairs)
+                }
+                BLOB_REF_EXTENSION_ID => {
+                    let index = self.read_ordered_payload::<4>(
+                        previous_bytes_read,
+                        size,
+                        "invalid blob reference",
+                    )?;
+                    let index = u32::from_le_bytes(index);
+                    if !self.dedup_blobs {
+                        return Ok(Element::BlobRef(index));
+                    }
+                    return self
+                        .blobs
+                        .get(index as usize)
+                        .map(|blob| Element::Blob(blob.clone()))
+                        .ok_or(Syntax(previous_bytes_read, "reference to an unknown blob"));
+                }
+                STRING_REF_EXTENSION_ID => {
+                    let index = self.read_ordered_payload::<4>(
+                        previous_bytes_read,
+                        size,
+                        "invalid string reference",
+                    )?;
+                    let index = u32::from_le_bytes(index);
+                    if !self.use_string_table {
+                        return Ok(Element::StringRef(index));
+                    }
+                    return self
+                        .strings
+                        .get(index as usize)
+                        .map(|string| Element::String(string.clone()))
+                        .ok_or(Syntax(
+                            previous_bytes_read,
+                            "reference to an unknown string",
+                        ));
+                }
+                DURATION_EXTENSION_ID => {
+                    let nanos = self.read_ordered_payload::<8>(
+                        previous_bytes_read,
+                        size,
+                        "invalid duration",
+                    )?;
+                    return Ok(Element::Duration(i64::from_le_bytes(nanos)));
+                }
+                TYPED_COLLECTION_EXTENSION_ID => {
+                    if size != 5 {
+                        return Err(Syntax(previous_bytes_read, "invalid typed collection"));
+                    }
+                    let type_byte = self.expect::<1>()?[0];
+                    let count = u32::from_le_bytes(self.expect_ordered::<4>()?);
+                    return Ok(Element::TypedCollection { type_byte, count });
+                }
+                _ => (),
+            }
+        }
+        let data = self.expect_heap(size)?;
+        match self.extensions.get(&id) {
+            Some(decoder) => decoder(&data),
+            None if id == DELTA_U64_EXTENSION_ID => {
+                decode_delta_u64(&data).map(Element::DeltaU64).ok_or(Syntax(
+                    previous_bytes_read,
+                    "invalid delta-encoded integers",
+                ))
+            }
+            #[cfg(feature = "flate2")]
+            None if id == COMPRESSED_BLOB_EXTENSION_ID && self.decompress_blobs_up_to.is_some() => {
+                let limit = self.decompress_blobs_up_to.unwrap_or(usize::MAX);
+                let mut blob = Vec::new();
+                decompress_blob(previous_bytes_read, &data, limit, &mut blob)?;
+                if self.dedup_blobs {
+                    self.blobs.push(blob.clone());
+                }
+                Ok(Element::Blob(blob))
+            }
+            None => Ok(Element::Extension { id, data }),
+        }
+    }
+
+    /// Read the elements held by the payload of a flagged element or map starting at the given
+    /// position, making sure that they are not nested too deeply and that they occupy exactly
+    /// the declared size of the payload.
+    fn read_nested<R>(
+        &mut self,
+        pos: usize,
+        size: usize,
+        read: impl FnOnce(&mut Self) -> Result<R>,
+    ) -> Result<R> {
+        if self.depth >= self.max_depth {
+            return Err(Error::TooDeep {
+                pos,
+                limit: self.max_depth,
+            });
+        }
+        let start = self.bytes_read;
+        if let Some(input_len) = self.input_len {
+            if size > input_len.saturating_sub(start) {
+                return Err(Syntax(start, SIZE_EXCEEDS_INPUT));
+            }
+        }
+
+        // The nested elements cannot allocate more than the payload holds.
+        let input_len = self.input_len.replace(start + size);
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        self.input_len = input_len;
+        let result = result?;
+        if self.bytes_read - start != size {
+            return Err(Syntax(pos, "nested elements do not match the payload size"));
+        }
+        Ok(result)
+    }
+
+    fn read_flagged(&mut self) -> Result<Element> {
+        let flags = self.expect::<1>()?[0];
+        let inner_type_byte = self
+            .read_type_byte()?
+            .ok_or(Syntax(self.bytes_read, UNEXPECTED_EOF))?;
+        if inner_type_byte & 0b10000000 != 0 {
+            return Err(Syntax(
+                self.previous_bytes_read,
+                "flagged element cannot be a marker",
+            ));
+        }
+        let inner = Box::new(self.read_data_element(inner_type_byte)?);
+        Ok(Element::Flagged { flags, inner })
+    }
+
+    /// Read the payload of the extension element starting at the given position, which holds a
+    /// single number, making sure that it has the size of that number.
+    fn read_ordered_payload<const N: usize>(
+        &mut self,
+        pos: usize,
+        size: usize,
+        message: &'static str,
+    ) -> Result<[u8; N]> {
+        if size != N {
+            return Err(Syntax(pos, message));
+        }
+        self.expect_ordered::<N>()
+    }
+
+    fn read_map_pairs(&mut self) -> Result<Element> {
+        let count = u32::from_le_bytes(self.expect_ordered::<4>()?);
+        // The number of pairs is not trusted to allocate memory up front.
+        let mut pairs = Vec::new();
+        for _ in 0..count {
+            let key_start = self.bytes_read;
+            let key = match self.read_map_element()? {
+                Element::String(key) => key,
+                _ => return Err(Syntax(key_start, "invalid map key")),
+            };
+            pairs.push((key, self.read_map_element()?));
+        }
+        Ok(Element::Map(pairs))
+    }
+
+    fn read_map_element(&mut self) -> Result<Element> {
+        let type_byte = self
+            .read_type_byte()?
+            .ok_or(Syntax(self.bytes_read, UNEXPECTED_EOF))?;
+        if type_byte & 0b10000000 != 0 {
+            return Err(Syntax(
+                self.previous_bytes_read,
+                "map element cannot be a marker",
+            ));
+        }
+        self.read_data_element(type_byte)
+    }
+
+    fn read_boolean(&mut self, type_byte: u8) -> Result<Element> {
+        match type_byte {
+            0b00110000 => Ok(Element::Boolean(false)),
+            0b00110001 => Ok(Element::Boolean(true)),
+            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
+        }
+    }
+
+    fn read_string_or_blob(&mut self, type_byte: u8) -> Result<Element> {
+        // We need to store this because "read_bundled_size" ALSO reads the size bytes, so the
+        // position in the error is wrong if the sub type is invalid.
+        let previous_bytes_read = self.previous_bytes_read;
+        if type_byte == 0b00011000 {
+            return self.read_c_string();
+        }
+
+        let sub_type_byte = type_byte & 0b1100;
+        let size = self.read_bundled_size(type_byte)?;
+
+        match sub_type_byte {
+            0b0000 => self.read_utf8_string(size).map(Element::String),
+            0b0100 => self.expect_heap(size).map(Element::Blob),
+            // The content of padding is not checked, so it does not need to be kept in memory.
+            0b1100 if self.records_raw() => self.expect_heap(size).map(|_| Element::Padding(size)),
+            0b1100 => self.skip(size).map(|_| Element::Padding(size)),
+            _ => Err(UnknownType(previous_bytes_read, type_byte)),
+        }
+    }
+
+    #[inline]
+    fn read_utf8_string(&mut self, size: usize) -> Result<String> {
+        let string_bytes = self.expect_heap(size)?;
+        self.decode_utf8(string_bytes, self.previous_bytes_read)
+    }
+
+    fn read_c_string(&mut self) -> Result<Element> {
+        let string_start = self.bytes_read;
+        let mut string_bytes = Vec::new();
+        loop {
+            match self.expect::<1>()?[0] {
+                0 => break,
+                byte => string_bytes.push(byte),
+            }
+        }
+        self.decode_utf8(string_bytes, string_start)
+            .map(Element::CString)
+    }
+
+    fn decode_utf8(&mut self, string_bytes: Vec<u8>, string_start: usize) -> Result<String> {
+        #[cfg(feature = "encoding_rs")]
+        if self.string_encoding != encoding_rs::UTF_8 {
+            return self.decode_with_encoding(&string_bytes, string_start);
+        }
+
+        // Strings are only validated once fully read, so a character split across reads of the
+        // source is never mistaken for an invalid sequence.
+        match String::from_utf8(string_bytes) {
+            Ok(string) => Ok(string),
+            Err(e) if self.lossy_utf8 => {
+                self.lossy_substitutions += 1;
+                Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+            }
+            Err(e) => Err(Error::InvalidString {
+                pos: string_start + e.utf8_error().valid_up_to(),
+                source: e,
+            }),
+        }
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    fn decode_with_encoding(&mut self, string_bytes: &[u8], string_start: usize) -> Result<String> {
+        let encoding = self.string_encoding;
+        if let Some(string) =
+            encoding.decode_without_bom_handling_and_without_replacement(string_bytes)
+        {
+            return Ok(string.into_owned());
+        }
+        if self.lossy_utf8 {
+            self.lossy_substitutions += 1;
+            return Ok(encoding
+                .decode_without_bom_handling(string_bytes)
+                .0
+                .into_owned());
+        }
+        Err(Error::InvalidEncoding {
+            pos: string_start,
+            encoding: encoding.name(),
+        })
+    }
+
+    #[inline]
+    fn read_bundled_size(&mut self, type_byte: u8) -> Result<usize> {
+        let pos = self.previous_bytes_read;
+        let size = match type_byte & 0b11 {
+            0b00 => self.expect::<1>()?[0] as u64,
+            0b01 => LE::read_u16(self.expect_ordered::<2>()?.as_slice()) as u64,
+            0b10 => LE::read_u32(self.expect_ordered::<4>()?.as_slice()) as u64,
+            0b11 => LE::read_u64(self.expect_ordered::<8>()?.as_slice()),
+            _ => return Err(UnknownType(pos, type_byte)),
+        };
+        // On 32-bit platforms, truncating the size would desynchronize the reader.
+        usize::try_from(size).map_err(|_| Syntax(pos, SIZE_EXCEEDS_ADDRESS_SPACE))
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        let bytes_skipped = io::copy(
+            &mut io::Read::take(&mut self.read, count as u64),
+            &mut io::sink(),
+        )? as usize;
+        self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += bytes_skipped;
+        if bytes_skipped != count {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Like `read_exact`, but reporting how many bytes were read when the source runs out.
+        let mut bytes_read = 0;
+        while bytes_read < buf.len() {
+            match self.read.read(&mut buf[bytes_read..]) {
+                Ok(0) => break,
+                Ok(count) => bytes_read += count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        if self.records_raw() {
+            self.raw.extend_from_slice(&buf[..bytes_read]);
+        }
+        Ok(bytes_read)
+    }
+
+    fn expect<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        let bytes_read = self.read_bytes(&mut buf)?;
+        self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += bytes_read;
+        if bytes_read != N {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+        Ok(buf)
+    }
+
+    fn expect_ordered<const N: usize>(&mut self) -> Result<[u8; N]> {
+        // Numbers are always decoded as little-endian, so big-endian bytes are reversed first.
+        let mut bytes = self.expect::<N>()?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn expect_heap(&mut self, count: usize) -> Result<Vec<u8>> {
+        if count == 0 {
+            // Empty payloads do not need to touch the source at all.
+            self.previous_bytes_read = self.bytes_read;
+            return Ok(Vec::new());
+        }
+        if let Some(input_len) = self.input_len {
+            if count > input_len.saturating_sub(self.bytes_read) {
+                return Err(Syntax(self.bytes_read, SIZE_EXCEEDS_INPUT));
+            }
+        }
+
+        let mut buf = vec![0u8; count];
+        let bytes_read = self.read_bytes(&mut buf)?;
+        self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += bytes_read;
+        if bytes_read != count {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+        Ok(buf)
+    }
+}
+
+impl<T> Reader<T>
+where
+    T: io::Read,
+{
+    /// Chain another source after the source of this reader.
+    ///
+    /// The returned reader reads the remaining data of this reader, followed by the data of the
+    /// given source, as a single continuous stream: elements can span the boundary between the
+    /// two sources, and byte offsets keep counting from the start of the first source. This is
+    /// useful to read a document split across several shards.
+    ///
+    /// The configuration and state of this reader are kept, except for its
+    /// [input length][Reader::input_len], which is reset since it does not cover the new source.
+    pub fn chain<U: io::Read>(self, next: U) -> Reader<io::Chain<T, U>> {
+        let mut reader = self.map_read(|read| read.chain(next));
+        reader.input_len = None;
+        reader
+    }
+
+    fn map_read<U, F>(self, f: F) -> Reader<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        Reader {
+            read: f(self.read),
+            previous_bytes_read: self.previous_bytes_read,
+            bytes_read: self.bytes_read,
+            current_name: self.current_name,
+            retained_element: self.retained_element,
+            retained_raw: self.retained_raw,
+            retained_start: self.retained_start,
+            retain_raw: self.retain_raw,
+            raw_marker: self.raw_marker,
+            raw: self.raw,
+            last_raw: self.last_raw,
+            last_start: self.last_start,
+            max_marker_len: self.max_marker_len,
+            max_elements_per_entry: self.max_elements_per_entry,
+            max_depth: self.max_depth,
+            depth: self.depth,
+            fail_on_missing_marker: self.fail_on_missing_marker,
+            extensions: self.extensions,
+            strict: self.strict,
+            collection_type: self.collection_type,
+            typed_collection: self.typed_collection,
+            peeked_type_byte: self.peeked_type_byte,
+            input_len: self.input_len,
+            version: self.version,
+            lossy_utf8: self.lossy_utf8,
+            lossy_substitutions: self.lossy_substitutions,
+            coalesce_strings: self.coalesce_strings,
+            manifest_name: self.manifest_name,
+            dedup_blobs: self.dedup_blobs,
+            blobs: self.blobs,
+            use_string_table: self.use_string_table,
+            strings: self.strings,
+            containers_break_entries: self.containers_break_entries,
+            #[cfg(feature = "encoding_rs")]
+            string_encoding: self.string_encoding,
+            #[cfg(feature = "flate2")]
+            decompress_blobs_up_to: self.decompress_blobs_up_to,
+            decode_context: self.decode_context,
+            big_endian: self.big_endian,
         }
     }
 
-    fn read_string_or_blob(&mut self, type_byte: u8) -> Result<Element> {
-        // We need to store this because "read_bundled_size" ALSO reads the size bytes, so the
-        // position in the error is wrong if the sub type is invalid.
-        let previous_bytes_read = self.previous_bytes_read;
+    /// Create a reader over the given source, with the configuration of this reader and the
+    /// state needed to read its next element, but without its tables and extension decoders.
+    fn probe<U: io::Read>(&self, read: U) -> Reader<U> {
+        Reader {
+            read,
+            previous_bytes_read: self.previous_bytes_read,
+            bytes_read: self.bytes_read,
+            current_name: self.current_name.clone(),
+            retained_element: None,
+            retained_raw: None,
+            retained_start: 0,
+            retain_raw: self.retain_raw,
+            raw_marker: false,
+            raw: Vec::new(),
+            last_raw: None,
+            last_start: self.last_start,
+            max_marker_len: self.max_marker_len,
+            max_elements_per_entry: self.max_elements_per_entry,
+            max_depth: self.max_depth,
+            depth: self.depth,
+            fail_on_missing_marker: self.fail_on_missing_marker,
+            extensions: HashMap::new(),
+            strict: self.strict,
+            collection_type: self.collection_type,
+            typed_collection: self.typed_collection,
+            peeked_type_byte: None,
+            input_len: self.input_len,
+            version: self.version,
+            lossy_utf8: self.lossy_utf8,
+            lossy_substitutions: 0,
+            coalesce_strings: self.coalesce_strings,
+            manifest_name: self.manifest_name.clone(),
+            dedup_blobs: self.dedup_blobs,
+            blobs: Vec::new(),
+            use_string_table: self.use_string_table,
+            strings: Vec::new(),
+            containers_break_entries: self.containers_break_entries,
+            #[cfg(feature = "encoding_rs")]
+            string_encoding: self.string_encoding,
+            #[cfg(feature = "flate2")]
+            decompress_blobs_up_to: self.decompress_blobs_up_to,
+            decode_context: self.decode_context,
+            big_endian: self.big_endian,
+        }
+    }
 
-        let sub_type_byte = type_byte & 0b1100;
-        let size = self.read_bundled_size(type_byte)?;
+    /// Get the length of the next element of this reader, or `None` if the end of file has
+    /// been reached.
+    ///
+    /// The header of the element is consumed, but the payload of markers, strings, blobs,
+    /// padding and extensions is not, since it does not change their length.
+    fn next_element_len(&mut self) -> Result<Option<usize>> {
+        let element_start = self.bytes_read;
+        let type_byte = match self.read_type_byte()? {
+            Some(type_byte) => type_byte,
+            None => return Ok(None),
+        };
+        let size = match type_byte {
+            0b10000000..=0b11111111 => self.read_marker_size(type_byte)?,
+            0b00010000..=0b00010111 | 0b00011100..=0b00011111 => {
+                self.check_version(type_byte)?;
+                self.read_bundled_size(type_byte)?
+            }
+            0b01110000..=0b01110011 => {
+                self.check_version(type_byte)?;
+                self.expect::<1>()?;
+                self.read_bundled_size(type_byte)?
+            }
+            _ => {
+                self.skip_data_element(type_byte)?;
+                0
+            }
+        };
+        Ok(Some((self.bytes_read - element_start).saturating_add(size)))
+    }
+}
 
-        match sub_type_byte {
-            0b0000 => self.read_utf8_string(size).map(Element::String),
-            0b0100 => self.expect_heap(size).map(Element::Blob),
-            _ => Err(UnknownType(previous_bytes_read, type_byte)),
-        }
+impl<T> Reader<io::Take<T>>
+where
+    T: io::Read,
+{
+    /// Create a new [TPK reader][Reader] reading at most `len` bytes from the given source.
+    ///
+    /// This is useful to read TPK data embedded in a larger payload, when its length is known
+    /// from an outer framing: the reader reports the end of file once `len` bytes have been
+    /// read, without ever reading past them. The [input length][Reader::input_len] of the
+    /// reader is set accordingly.
+    pub fn take(read: T, len: u64) -> Reader<io::Take<T>> {
+        Reader::new(read.take(len)).input_len(usize::try_from(len).unwrap_or(usize::MAX))
     }
+}
 
-    #[inline]
-    fn read_utf8_string(&mut self, size: usize) -> Result<String> {
-        let string_bytes = self.expect_heap(size)?;
-        String::from_utf8(string_bytes).map_err(|e| Error::InvalidString {
-            pos: self.previous_bytes_read + e.utf8_error().valid_up_to(),
-            source: e,
+impl<T> Reader<T>
+where
+    T: io::Read + Send + 'static,
+{
+    /// Read every remaining [entry][Entry] of this reader on a background thread, sending each
+    /// of them to the given channel as soon as it is read.
+    ///
+    /// The thread stops at the end of file, after sending the first error, or once the
+    /// receiving end of the channel is dropped. The returned handle can be used to wait for it.
+    pub fn stream_entries_to(mut self, sender: Sender<Result<Entry>>) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            let result = match self.read_entry() {
+                Ok(Some(entry)) => Ok(entry),
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
         })
     }
+}
 
-    #[inline]
-    fn read_bundled_size(&mut self, type_byte: u8) -> Result<usize> {
-        match type_byte & 0b11 {
-            0b00 => Ok(self.expect::<1>()?[0] as usize),
-            0b01 => Ok(LE::read_u16(self.expect::<2>()?.as_slice()) as usize),
-            0b10 => Ok(LE::read_u32(self.expect::<4>()?.as_slice()) as usize),
-            0b11 => Ok(LE::read_u64(self.expect::<8>()?.as_slice()) as usize),
-            _ => Err(UnknownType(self.previous_bytes_read, type_byte)),
-        }
+impl<T> Reader<T>
+where
+    T: io::Read + io::Seek,
+{
+    /// Detect the total length of the input of this reader from its seekable source.
+    ///
+    /// This function behaves like [input_len][Reader::input_len], using the length of the
+    /// source as input length. The position of the source is left untouched.
+    pub fn detect_input_len(mut self) -> Result<Reader<T>> {
+        let position = self.read.stream_position()?;
+        let end = self.read.seek(io::SeekFrom::End(0))?;
+        self.read.seek(io::SeekFrom::Start(position))?;
+
+        let peeked_len = self.peeked_type_byte.map_or(0, |_| 1);
+        let remaining = usize::try_from(end.saturating_sub(position)).unwrap_or(usize::MAX);
+        self.input_len = Some((self.bytes_read + peeked_len).saturating_add(remaining));
+        Ok(self)
     }
 
-    fn expect<const N: usize>(&mut self) -> Result<[u8; N]> {
-        let mut buf = [0u8; N];
-        let bytes_read = self.read.read(&mut buf)?;
+    /// Detect the byte order of the source of this reader from its
+    /// [endianness magic][ENDIANNESS_MAGIC], if it starts with one.
+    ///
+    /// Some non-compliant producers write numbers in big-endian order. Such sources can be
+    /// recognized if they start with the 4 bytes of the endianness magic: if those are found in
+    /// little-endian order, they are skipped and the source is read normally, and if they are
+    /// found in big-endian order, they are skipped and the numbers and sizes following them are
+    /// decoded as big-endian, including the numbers held by extension elements such as
+    /// durations, references and the counts of typed collections and maps. The values of
+    /// [delta-encoded integers][Element::DeltaU64] are variable-length sequences of bytes, which
+    /// do not depend on the byte order. If the source does not start with the magic, its
+    /// position is left untouched and it is read as little-endian, as mandated by the format.
+    ///
+    /// This is meant to be called before anything else is read from the source.
+    pub fn detect_endianness(mut self) -> Result<Reader<T>> {
+        let position = self.read.stream_position()?;
+        let mut magic = [0u8; 4];
+        let bytes_read = self.read_bytes(&mut magic)?;
+        self.raw.clear();
+        if bytes_read == magic.len() && magic == ENDIANNESS_MAGIC.to_le_bytes() {
+            self.big_endian = false;
+        } else if bytes_read == magic.len() && magic == ENDIANNESS_MAGIC.to_be_bytes() {
+            self.big_endian = true;
+        } else {
+            self.read.seek(io::SeekFrom::Start(position))?;
+            return Ok(self);
+        }
         self.previous_bytes_read = self.bytes_read;
         self.bytes_read += bytes_read;
-        if bytes_read != N {
-            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        Ok(self)
+    }
+
+    /// Read the footer written at the end of the source by
+    /// [Writer::write_footer][crate::Writer::write_footer].
+    ///
+    /// This function returns the `(name, offset)` pairs of the footer, or `None` if the source
+    /// does not end with a footer. Only the footer is read, using its length stored in the last
+    /// bytes of the source, and the position of the source is left untouched. Positions reported
+    /// by errors are relative to the start of the footer.
+    pub fn read_footer(&mut self) -> Result<Option<Vec<(String, u64)>>> {
+        let position = self.read.stream_position()?;
+        let footer = self.read_footer_from_end();
+        self.read.seek(io::SeekFrom::Start(position))?;
+        footer
+    }
+
+    /// Read the entries indexed by the footer of the source, from last to first.
+    ///
+    /// This function [reads the footer][Self::read_footer], then seeks to the offset of each
+    /// indexed entry in reverse order, which is useful to show the most recent entries of a log
+    /// first. It returns an [Error::MissingFooter] if the source does not end with a footer.
+    ///
+    /// Offsets are expected to be relative to the start of the source, and the position of the
+    /// source is left after the last entry read. Note that
+    /// [blob references][Element::BlobRef] cannot be resolved when
+    /// [deduplicating blobs][Self::dedup_blobs], since the blobs they refer to are read after
+    /// them.
+    pub fn entries_reversed(&mut self) -> Result<impl Iterator<Item = Result<Entry>> + '_> {
+        let index = self.read_footer()?.ok_or(Error::MissingFooter)?;
+        Ok(index.into_iter().rev().map(move |(_, offset)| {
+            self.seek_to(offset)?;
+            self.read_entry()?
+                .ok_or(Syntax(offset as usize, "missing indexed entry"))
+        }))
+    }
+
+    fn seek_to(&mut self, offset: u64) -> Result<()> {
+        self.read.seek(io::SeekFrom::Start(offset))?;
+        self.retained_element = None;
+        self.retained_raw = None;
+        self.peeked_type_byte = None;
+        self.collection_type = None;
+        self.typed_collection = None;
+        self.previous_bytes_read = offset as usize;
+        self.bytes_read = offset as usize;
+        Ok(())
+    }
+
+    fn read_footer_from_end(&mut self) -> Result<Option<Vec<(String, u64)>>> {
+        let end = self.read.seek(io::SeekFrom::End(0))?;
+        let trailer_len = Element::UInteger32(0).encoded_len() as u64;
+        if end < trailer_len {
+            return Ok(None);
         }
-        Ok(buf)
+        let mut trailer = [0u8; 5];
+        self.read.seek(io::SeekFrom::End(-(trailer_len as i64)))?;
+        io::Read::read_exact(&mut self.read, &mut trailer)?;
+        let len = LE::read_u32(&trailer[1..]) as u64;
+        if trailer[0] != 0b00100010 || len < trailer_len || len > end {
+            return Ok(None);
+        }
+
+        self.read.seek(io::SeekFrom::Start(end - len))?;
+        let mut reader = Reader::new(io::Read::take(&mut self.read, len - trailer_len));
+        let entry = match reader.read_entry()? {
+            Some(entry) if entry.name == FOOTER_NAME => entry,
+            _ => return Ok(None),
+        };
+        let mut elements = entry.elements.into_iter();
+        let mut index = Vec::new();
+        while let Some(element) = elements.next() {
+            match (element, elements.next()) {
+                (Element::String(name), Some(Element::UInteger64(offset))) => {
+                    index.push((name, offset))
+                }
+                _ => return Err(Syntax(0, "invalid footer")),
+            }
+        }
+        Ok(Some(index))
     }
+}
 
-    fn expect_heap(&mut self, count: usize) -> Result<Vec<u8>> {
-        let mut buf = vec![0u8; count];
-        let bytes_read = self.read.read(&mut buf)?;
+/// State of a read performed by a [ResumableReader].
+#[derive(Debug, PartialEq)]
+pub enum ReadState<T> {
+    /// The read completed with the given result.
+    Ready(T),
+    /// Not enough data is available yet: the read should be retried once the source has more.
+    Pending,
+}
+
+/// A TPK reader structure for non-blocking sources.
+///
+/// Unlike [Reader], which loses its position when the source returns an
+/// [io::ErrorKind::WouldBlock] error in the middle of an element, this reader buffers the bytes
+/// of partially-received elements internally. Reads return [ReadState::Pending] until a whole
+/// element is available, and can then simply be retried, without any data being lost.
+///
+/// Elements are decoded by a single [Reader], which only reads from the buffer once it holds a
+/// whole element, so that it always stops at element boundaries. A configured reader can be
+/// turned into a resumable one using [Reader::into_resumable].
+pub struct ResumableReader<T> {
+    reader: Reader<ResumeBuffer<T>>,
+    /// The number of buffered bytes the next element needs at least, as found by the last probe.
+    needed: usize,
+}
+
+/// The source of a [ResumableReader], along with the bytes received from it, from which its
+/// [Reader] reads.
+struct ResumeBuffer<T> {
+    read: T,
+    data: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<T> io::Read for ResumeBuffer<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = io::Read::read(&mut &self.data[self.pos..], buf)?;
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+impl<T> Reader<T>
+where
+    T: io::Read,
+{
+    /// Turn this reader into a [ResumableReader] reading from the same source.
+    ///
+    /// The configuration and state of this reader are kept, and used to decode the elements of
+    /// the resumable reader.
+    pub fn into_resumable(self) -> ResumableReader<T> {
+        ResumableReader {
+            reader: self.map_read(|read| ResumeBuffer {
+                read,
+                data: Vec::new(),
+                pos: 0,
+                eof: false,
+            }),
+            needed: 0,
+        }
+    }
+}
+
+impl<T> ResumableReader<T>
+where
+    T: io::Read,
+{
+    /// Create a new [TPK resumable reader][ResumableReader].
+    pub fn new(read: T) -> ResumableReader<T> {
+        Reader::new(read).into_resumable()
+    }
+
+    /// Try to read an [element][Element] from this reader.
+    ///
+    /// This function reads every byte currently available from the source, then attempts to
+    /// decode an element from them. If the element is incomplete and the source would block,
+    /// [ReadState::Pending] is returned and the bytes read so far are kept for the next call.
+    /// Once the end of file is reached, this function behaves like [Reader::read_element].
+    pub fn read_element(&mut self) -> Result<ReadState<Option<Element>>> {
+        self.fill()?;
+        if !self.reader.read.eof && self.reader.retained_element.is_none() && !self.has_element() {
+            return Ok(ReadState::Pending);
+        }
+        self.needed = 0;
+        Ok(ReadState::Ready(self.reader.read_element()?))
+    }
+
+    /// Whether the buffer holds the whole next element, which can then be read without running
+    /// out of data.
+    ///
+    /// The length of the element is remembered once its header has been received, so that the
+    /// buffer is only probed again once it holds enough bytes.
+    fn has_element(&mut self) -> bool {
+        let buffer = &self.reader.read;
+        let peeked = self.reader.peeked_type_byte.as_slice();
+        let available = peeked.len() + buffer.data.len() - buffer.pos;
+        if available < self.needed {
+            return false;
+        }
+        let mut probe = self
+            .reader
+            .probe(io::Read::chain(peeked, &buffer.data[buffer.pos..]));
+        self.needed = match probe.next_element_len() {
+            Ok(Some(len)) => len,
+            Ok(None) | Err(Syntax(_, UNEXPECTED_EOF)) => available + 1,
+            // Other errors are left to the reader to report.
+            Err(_) => return true,
+        };
+        available >= self.needed
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        let buffer = &mut self.reader.read;
+        buffer.data.drain(..buffer.pos);
+        buffer.pos = 0;
+        let mut chunk = [0u8; 4096];
+        while !buffer.eof {
+            match buffer.read.read(&mut chunk) {
+                Ok(0) => buffer.eof = true,
+                Ok(count) => buffer.data.extend_from_slice(&chunk[..count]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Reader<io::Cursor<bytes::Bytes>> {
+    /// Read an [element][ElementBytes] sharing the buffer of this reader.
+    ///
+    /// This function behaves like [read_element][Self::read_element], except that blobs are
+    /// [slices][bytes::Bytes::slice] of the source buffer rather than copies of their data. This
+    /// makes it possible to read large blobs from a network frame without copying them.
+    pub fn read_element_bytes(&mut self) -> Result<Option<ElementBytes>> {
+        // Retained elements have already been decoded, and are returned as-is.
+        let is_blob = self.retained_element.is_none()
+            && matches!(self.peek_type_byte()?, Some(0b00010100..=0b00010111));
+        if !is_blob {
+            return Ok(self.read_element()?.map(ElementBytes::from));
+        }
+
+        self.raw.clear();
+        let element_start = self.bytes_read;
+        let type_byte = self.read_type_byte()?.ok_or(Error::Unknown)?;
+        let size = self.read_bundled_size(type_byte)?;
+        let start = self.read.position() as usize;
+        let available = size.min(self.read.get_ref().len().saturating_sub(start));
+        let blob = self.read.get_ref().slice(start..start + available);
+        self.read.set_position((start + available) as u64);
         self.previous_bytes_read = self.bytes_read;
+        self.bytes_read += available;
+        if available != size {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+
+        if self.strict {
+            self.check_collection(&Element::Blob(Vec::new()), element_start)?;
+        }
+        if self.dedup_blobs {
+            self.blobs.push(blob.to_vec());
+        }
+        if self.retain_raw {
+            self.raw.extend_from_slice(&blob);
+        }
+        self.last_raw = self.retain_raw.then(|| self.raw.as_slice().into());
+        self.last_start = element_start;
+        Ok(Some(ElementBytes::Blob(blob)))
+    }
+}
+
+/// A TPK reader structure for length-delimited documents.
+///
+/// This reader expects a stream of frames, each frame being made of a little-endian `u32`
+/// holding the length of a [document][Document], followed by the document itself, as written by
+/// [Writer::write_framed_document][crate::Writer::write_framed_document]. This makes it possible
+/// to receive several documents over a single connection, such as a socket.
+pub struct FramedReader<T> {
+    read: T,
+    bytes_read: usize,
+    max_frame_len: usize,
+}
+
+impl<T> FramedReader<T>
+where
+    T: io::Read,
+{
+    /// Create a new [TPK framed reader][FramedReader].
+    pub fn new(read: T) -> FramedReader<T> {
+        FramedReader {
+            read,
+            bytes_read: 0,
+            max_frame_len: usize::MAX,
+        }
+    }
+
+    /// Set the maximum length of the frames accepted by this reader.
+    ///
+    /// Frames whose length prefix exceeds this limit are rejected with an
+    /// [Error::FrameTooLarge] before any of their bytes is buffered. By default, frames are
+    /// only limited by their `u32` length prefix.
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> FramedReader<T> {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Read a framed [document][Document] from this reader.
+    ///
+    /// This function reads a whole frame from the source reader before parsing it, and returns
+    /// `None` if the end of file is reached before a new frame begins. Positions reported by
+    /// errors happening while parsing the document are relative to the start of its frame.
+    pub fn read_framed_document(&mut self) -> Result<Option<Document>> {
+        let mut len_buf = [0u8; 4];
+        let bytes_read = self.fill(&mut len_buf)?;
+        if bytes_read == 0 {
+            return Ok(None);
+        }
+        if bytes_read != len_buf.len() {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+
+        let len = LE::read_u32(&len_buf) as usize;
+        if len > self.max_frame_len {
+            return Err(Error::FrameTooLarge {
+                pos: self.bytes_read - len_buf.len(),
+                len,
+                limit: self.max_frame_len,
+            });
+        }
+        // Bytes are read progressively, so that a corrupt length never allocates memory up
+        // front.
+        let mut frame = Vec::new();
+        let bytes_read =
+            io::Read::read_to_end(&mut io::Read::take(&mut self.read, len as u64), &mut frame)?;
         self.bytes_read += bytes_read;
-        if bytes_read != count {
+        if bytes_read != len {
             return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
         }
-        Ok(buf)
+        Reader::new(frame.as_slice()).read_document().map(Some)
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(bytes_read) => filled += bytes_read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.bytes_read += filled;
+        Ok(filled)
+    }
+}
+
+/// A TPK reader structure for append-only logs.
+///
+/// This reader expects a sequence of records, as written by a
+/// [LogWriter][crate::LogWriter]: each record is made of the
+/// [record separator][LOG_RECORD_MAGIC], the length of an [entry][Entry] as a little-endian
+/// `u32`, and the entry itself. When a record is corrupt, the following records can still be
+/// read by [skipping to the next record][Self::skip_to_next_record].
+pub struct LogReader<T> {
+    read: T,
+    bytes_read: usize,
+    pending: Vec<u8>,
+    record: Vec<u8>,
+    max_frame_len: usize,
+}
+
+const LOG_SCAN_CHUNK_LEN: usize = 4096;
+
+impl<T> LogReader<T>
+where
+    T: io::Read,
+{
+    /// Create a new [TPK log reader][LogReader].
+    pub fn new(read: T) -> LogReader<T> {
+        LogReader {
+            read,
+            bytes_read: 0,
+            pending: Vec::new(),
+            record: Vec::new(),
+            max_frame_len: usize::MAX,
+        }
+    }
+
+    /// Set the maximum length of the entries accepted by this reader.
+    ///
+    /// Records whose length prefix exceeds this limit are rejected with an
+    /// [Error::FrameTooLarge] before any of their entry is buffered, and can then be
+    /// [skipped][Self::skip_to_next_record] like any other corrupt record. By default, entries
+    /// are only limited by their `u32` length prefix.
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> LogReader<T> {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Read the [entry][Entry] of the next record of this log.
+    ///
+    /// This function returns `None` if the end of file is reached before a new record begins.
+    /// A record that does not start with the separator, is truncated, or does not hold exactly
+    /// one valid entry is reported as an error, after which
+    /// [skip_to_next_record][Self::skip_to_next_record] can be called to resume reading.
+    /// Positions reported by errors happening while parsing the entry are relative to the start
+    /// of the entry.
+    pub fn read_record(&mut self) -> Result<Option<Entry>> {
+        self.record.clear();
+        let record_start = self.bytes_read;
+        let header_len = LOG_RECORD_MAGIC.len() + 4;
+        match self.fill(header_len)? {
+            0 => return Ok(None),
+            len if len != header_len => return Err(Syntax(self.bytes_read, UNEXPECTED_EOF)),
+            _ => {}
+        }
+        if self.record[..LOG_RECORD_MAGIC.len()] != LOG_RECORD_MAGIC {
+            return Err(Syntax(record_start, "invalid log record separator"));
+        }
+
+        let len = LE::read_u32(&self.record[LOG_RECORD_MAGIC.len()..header_len]) as usize;
+        if len > self.max_frame_len {
+            return Err(Error::FrameTooLarge {
+                pos: record_start,
+                len,
+                limit: self.max_frame_len,
+            });
+        }
+        if self.fill(len)? != len {
+            return Err(Syntax(self.bytes_read, UNEXPECTED_EOF));
+        }
+        let mut reader = Reader::new(&self.record[header_len..]);
+        let entry = reader
+            .read_entry()?
+            .ok_or(Syntax(record_start, "empty log record"))?;
+        reader.expect_eof()?;
+        Ok(Some(entry))
+    }
+
+    /// Skip to the next record of this log, after a [record][Self::read_record] failed to be
+    /// read.
+    ///
+    /// Since the length of a corrupt record cannot be trusted, the source is scanned for the
+    /// next [record separator][LOG_RECORD_MAGIC] starting right after the beginning of the
+    /// failed record. This returns `false` if the end of file is reached without finding one.
+    pub fn skip_to_next_record(&mut self) -> Result<bool> {
+        let mut start = usize::from(!self.record.is_empty());
+        loop {
+            let found = self.record[start.min(self.record.len())..]
+                .windows(LOG_RECORD_MAGIC.len())
+                .position(|window| window == LOG_RECORD_MAGIC);
+            if let Some(pos) = found {
+                // The next record is read again from the separator.
+                let rest = self.record.split_off(start + pos);
+                self.bytes_read -= rest.len();
+                self.pending.splice(..0, rest);
+                self.record.clear();
+                return Ok(true);
+            }
+
+            // A separator may be split across chunks, so the end of the chunk is kept around.
+            let kept = self.record.len().saturating_sub(LOG_RECORD_MAGIC.len() - 1);
+            self.record.drain(..kept.max(start.min(self.record.len())));
+            start = 0;
+            if self.fill(LOG_SCAN_CHUNK_LEN)? == 0 {
+                self.record.clear();
+                return Ok(false);
+            }
+        }
+    }
+
+    fn fill(&mut self, count: usize) -> Result<usize> {
+        // Bytes are read progressively, so that a corrupt length never allocates memory up
+        // front.
+        let from_pending = count.min(self.pending.len());
+        self.record.extend(self.pending.drain(..from_pending));
+        let from_read = io::Read::read_to_end(
+            &mut io::Read::take(&mut self.read, (count - from_pending) as u64),
+            &mut self.record,
+        )?;
+        self.bytes_read += from_pending + from_read;
+        Ok(from_pending + from_read)
+    }
+}
+
+/// A TPK reader structure specialized for in-memory byte slices.
+///
+/// Unlike [Reader], this reader does not copy markers, strings and blobs into owned buffers:
+/// the [elements][ElementRef] and [entries][EntryRef] it returns borrow their data directly from
+/// the source slice, which avoids an allocation per element for read-only scans.
+pub struct SliceReader<'a> {
+    inner: Reader<&'a [u8]>,
+    current_name: &'a str,
+}
+
+impl<'a> SliceReader<'a> {
+    /// Create a new [TPK slice reader][SliceReader].
+    pub fn new(data: &'a [u8]) -> SliceReader<'a> {
+        SliceReader {
+            inner: Reader::new(data),
+            current_name: "/",
+        }
+    }
+
+    /// Read a borrowed [element][ElementRef] from this reader.
+    ///
+    /// This function behaves like [Reader::read_element], except that the returned element
+    /// borrows its data from the source slice.
+    pub fn read_element_ref(&mut self) -> Result<Option<ElementRef<'a>>> {
+        let type_byte = match self.inner.read_type_byte()? {
+            Some(type_byte) => type_byte,
+            None => return Ok(None),
+        };
+        if type_byte & 0b10000000 != 0 {
+            let size = self.inner.read_marker_size(type_byte)?;
+            let name = self.borrow_utf8_string(size)?;
+            self.current_name = name;
+            return Ok(Some(ElementRef::Marker(name)));
+        }
+
+        if type_byte & 0xF8 == 0b00010000 {
+            let previous_bytes_read = self.inner.previous_bytes_read;
+            let size = self.inner.read_bundled_size(type_byte)?;
+            let element = match type_byte & 0b1100 {
+                0b0000 => self.borrow_utf8_string(size).map(ElementRef::String),
+                0b0100 => self.borrow(size).map(ElementRef::Blob),
+                _ => Err(UnknownType(previous_bytes_read, type_byte)),
+            }?;
+            return Ok(Some(element));
+        }
+
+        let element = self.inner.read_data_element(type_byte)?;
+        Ok(Some(ElementRef::Other(element)))
+    }
+
+    /// Read a [copy-on-write element][ElementCow] from this reader.
+    ///
+    /// This function behaves like [read_element_ref][Self::read_element_ref]: the returned
+    /// element borrows its data from the source slice.
+    pub fn read_element_cow(&mut self) -> Result<Option<ElementCow<'a>>> {
+        Ok(self.read_element_ref()?.map(ElementCow::from))
+    }
+
+    /// Read a borrowed [entry][EntryRef] from this reader.
+    ///
+    /// This function behaves like [Reader::read_entry], except that the returned entry borrows
+    /// its name and data from the source slice. Since the source is entirely in memory, the
+    /// marker ending an entry is never consumed: it is simply left in place for the next read.
+    pub fn read_entry_ref(&mut self) -> Result<Option<EntryRef<'a>>> {
+        let first_element = match self.read_element_ref()? {
+            Some(element) => element,
+            None => return Ok(None),
+        };
+
+        let mut elements = Vec::with_capacity(1); // Entries usually have one element.
+        let name = if let ElementRef::Marker(name) = first_element {
+            name
+        } else {
+            elements.push(first_element);
+            self.current_name
+        };
+
+        while !self.next_is_marker() {
+            match self.read_element_ref()? {
+                Some(element) => elements.push(element),
+                None => break,
+            }
+        }
+
+        Ok(Some(EntryRef { name, elements }))
+    }
+
+    #[inline]
+    fn next_is_marker(&self) -> bool {
+        matches!(self.inner.read.first(), Some(byte) if byte & 0b10000000 != 0)
+    }
+
+    fn borrow(&mut self, count: usize) -> Result<&'a [u8]> {
+        let remaining: &'a [u8] = self.inner.read;
+        let count_read = count.min(remaining.len());
+        let (bytes, rest) = remaining.split_at(count_read);
+        self.inner.read = rest;
+        self.inner.previous_bytes_read = self.inner.bytes_read;
+        self.inner.bytes_read += count_read;
+        if count_read != count {
+            return Err(Syntax(self.inner.bytes_read, UNEXPECTED_EOF));
+        }
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn borrow_utf8_string(&mut self, size: usize) -> Result<&'a str> {
+        let string_bytes = self.borrow(size)?;
+        str::from_utf8(string_bytes).map_err(|e| Error::InvalidString {
+            pos: self.inner.previous_bytes_read + e.valid_up_to(),
+            source: String::from_utf8(string_bytes.to_vec()).unwrap_err(),
+        })
     }
 }
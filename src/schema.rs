@@ -0,0 +1,117 @@
+//! Validation of the shape of TPK entries.
+//!
+//! An [EntrySchema] describes the ordered list of [kinds][ElementKind] of elements an entry is
+//! expected to hold, and is checked using [Entry::matches][crate::Entry::matches].
+
+use crate::model::{Element, ElementKind};
+use thiserror::Error;
+
+/// Representation of a schema validation error.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// An element of the entry is not of the expected kind.
+    #[error("element {index} is a {found:?}, expected a {expected:?}")]
+    Mismatch {
+        index: usize,
+        expected: ElementKind,
+        found: ElementKind,
+    },
+
+    /// The entry ended while another element was expected.
+    #[error("element {index} is missing, expected a {expected:?}")]
+    Missing { index: usize, expected: ElementKind },
+
+    /// The entry holds more elements than the schema allows.
+    #[error("unexpected {found:?} at element {index}")]
+    Unexpected { index: usize, found: ElementKind },
+}
+
+/// How many times an element of a given kind may appear in an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrence {
+    /// Exactly one element.
+    One,
+    /// Zero or one element.
+    Optional,
+    /// Zero, one or more elements.
+    Repeated,
+}
+
+/// Description of the elements an entry is expected to hold.
+///
+/// Optional and repeated elements are matched greedily, without backtracking: a schema such as
+/// `[Repeated(String), One(String)]` can never be satisfied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntrySchema {
+    items: Vec<(ElementKind, Occurrence)>,
+}
+
+impl EntrySchema {
+    /// Create a new, empty [EntrySchema], only matching entries without any element.
+    pub fn new() -> EntrySchema {
+        EntrySchema::default()
+    }
+
+    /// Expect exactly one element of the given kind.
+    pub fn one(mut self, kind: ElementKind) -> Self {
+        self.items.push((kind, Occurrence::One));
+        self
+    }
+
+    /// Expect zero or one element of the given kind.
+    pub fn optional(mut self, kind: ElementKind) -> Self {
+        self.items.push((kind, Occurrence::Optional));
+        self
+    }
+
+    /// Expect zero, one or more elements of the given kind.
+    pub fn repeated(mut self, kind: ElementKind) -> Self {
+        self.items.push((kind, Occurrence::Repeated));
+        self
+    }
+
+    /// Check that the given elements conform to this schema.
+    pub fn validate(&self, elements: &[Element]) -> Result<(), SchemaError> {
+        let mut index = 0;
+        for &(expected, occurrence) in &self.items {
+            let matches = |index: usize| {
+                elements
+                    .get(index)
+                    .is_some_and(|element| element.kind() == expected)
+            };
+            match occurrence {
+                Occurrence::One => {
+                    match elements.get(index) {
+                        None => return Err(SchemaError::Missing { index, expected }),
+                        Some(element) if element.kind() != expected => {
+                            return Err(SchemaError::Mismatch {
+                                index,
+                                expected,
+                                found: element.kind(),
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                    index += 1;
+                }
+                Occurrence::Optional => {
+                    if matches(index) {
+                        index += 1;
+                    }
+                }
+                Occurrence::Repeated => {
+                    while matches(index) {
+                        index += 1;
+                    }
+                }
+            }
+        }
+        match elements.get(index) {
+            Some(element) => Err(SchemaError::Unexpected {
+                index,
+                found: element.kind(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
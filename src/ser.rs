@@ -0,0 +1,726 @@
+//! Serialization of Rust data structures into TPK data, using serde.
+//!
+//! This is the counterpart of the [de][crate::de] module: struct fields and map keys become entry
+//! names, nested structs and maps become folders (closed by a `..` entry), and sequences become
+//! collections. Integers are written using the narrowest element that can hold their value.
+//! `None` is written as an entry without any element, so optional values that would be written
+//! the same way, such as `Some(vec![])`, are refused.
+
+use crate::model::{narrow_signed, narrow_unsigned};
+use crate::{write, Document, Element, Entry, Writer};
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::Display;
+use std::io;
+use thiserror::Error;
+
+/// Representation of a TPK serialization error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The TPK data could not be written.
+    #[error(transparent)]
+    Write(#[from] write::Error),
+
+    /// The serialized value cannot be represented as TPK data.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// Representation of a TPK serialization result.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Serialize the given value as TPK data into the given destination.
+///
+/// The value must be a struct or a map, whose fields are written as entries.
+pub fn to_writer<T, W>(value: &T, write: W) -> Result<()>
+where
+    T: Serialize + ?Sized,
+    W: io::Write,
+{
+    let document = to_document(value)?;
+    Writer::new(write).write_document(&document)?;
+    Ok(())
+}
+
+/// Serialize the given value into a [Document].
+///
+/// The value must be a struct or a map, whose fields are turned into entries.
+pub fn to_document<T>(value: &T) -> Result<Document>
+where
+    T: Serialize + ?Sized,
+{
+    let mut entries = Vec::new();
+    value.serialize(DocumentSerializer {
+        entries: &mut entries,
+    })?;
+    Ok(Document { entries })
+}
+
+const NOT_A_STRUCT: &str = "only structs and maps can be serialized as TPK documents";
+const NOT_A_SCALAR: &str = "only scalar values can be serialized as TPK elements";
+const EMPTY_SOME: &str = "optional values that are empty cannot be told apart from None";
+
+macro_rules! reject {
+    ($msg:expr => $($method:ident($($ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok> {
+                Err(Error::Custom($msg.into()))
+            }
+        )*
+    };
+}
+
+/// Serializer for the top-level value, which must be a struct or a map.
+struct DocumentSerializer<'a> {
+    entries: &'a mut Vec<Entry>,
+}
+
+impl<'a> ser::Serializer for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = StructSerializer<'a>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    reject! { NOT_A_STRUCT =>
+        serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32),
+        serialize_i64(i64), serialize_u8(u8), serialize_u16(u16), serialize_u32(u32),
+        serialize_u64(u64), serialize_f32(f32), serialize_f64(f64), serialize_char(char),
+        serialize_str(&str), serialize_bytes(&[u8]), serialize_none(), serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(StructSerializer::new(self.entries, 0))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(StructSerializer::new(self.entries, 0))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::Custom(NOT_A_STRUCT.into()))
+    }
+}
+
+/// Serializer for the fields of a struct or map, which closes its folders once done.
+struct StructSerializer<'a> {
+    entries: &'a mut Vec<Entry>,
+    key: Option<String>,
+    depth: usize,
+}
+
+impl<'a> StructSerializer<'a> {
+    fn new(entries: &'a mut Vec<Entry>, depth: usize) -> StructSerializer<'a> {
+        StructSerializer {
+            entries,
+            key: None,
+            depth,
+        }
+    }
+
+    fn serialize_entry<T: Serialize + ?Sized>(&mut self, name: String, value: &T) -> Result<()> {
+        value.serialize(EntrySerializer {
+            entries: self.entries,
+            name,
+        })
+    }
+
+    fn close(self) -> Result<()> {
+        for _ in 0..self.depth {
+            push_entry(self.entries, "..".into(), Vec::new());
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_entry(key.into(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_entry(key.into(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl ser::SerializeMap for StructSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(ElementSerializer)? {
+            Element::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::Custom("map keys must be strings".into())),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or(Error::Custom("map value serialized before its key".into()))?;
+        self.serialize_entry(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+/// Serializer for the value of a struct field or map entry, written as a whole entry.
+struct EntrySerializer<'a> {
+    entries: &'a mut Vec<Entry>,
+    name: String,
+}
+
+impl EntrySerializer<'_> {
+    fn push(self, elements: Vec<Element>) -> Result<()> {
+        push_entry(self.entries, self.name, elements);
+        Ok(())
+    }
+
+    fn push_scalar(self, element: Result<Element>) -> Result<()> {
+        let element = element?;
+        self.push(vec![element])
+    }
+}
+
+impl<'a> ser::Serializer for EntrySerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = StructSerializer<'a>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = StructSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_i8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_i16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_i32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_i64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_u8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_u16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_u32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_u64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_f32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_f64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_str(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_bytes(v))
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.push(Vec::new())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        let start = self.entries.len();
+        value.serialize(EntrySerializer {
+            entries: &mut *self.entries,
+            name: self.name,
+        })?;
+        // Empty entries and empty collections are both read back as `None`.
+        match self.entries[start..] {
+            [ref entry] if matches!(entry.elements[..], [] | [Element::Collection]) => {
+                Err(Error::Custom(EMPTY_SOME.into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.push(Vec::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.push(Vec::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.push_scalar(ElementSerializer.serialize_unit_variant(name, variant_index, variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        push_entry(self.entries, self.name, vec![Element::Folder]);
+        value.serialize(EntrySerializer {
+            entries: self.entries,
+            name: variant.into(),
+        })?;
+        push_entry(self.entries, "..".into(), Vec::new());
+        Ok(())
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SeqSerializer::new(self.entries, self.name, len, 0))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        Ok(SeqSerializer::new_tuple(self.entries, self.name, len, 0))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(SeqSerializer::new_tuple(self.entries, self.name, len, 0))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        push_entry(self.entries, self.name, vec![Element::Folder]);
+        Ok(SeqSerializer::new_tuple(
+            self.entries,
+            variant.into(),
+            len,
+            1,
+        ))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        push_entry(self.entries, self.name, vec![Element::Folder]);
+        Ok(StructSerializer::new(self.entries, 1))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        push_entry(self.entries, self.name, vec![Element::Folder]);
+        Ok(StructSerializer::new(self.entries, 1))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        push_entry(self.entries, self.name, vec![Element::Folder]);
+        push_entry(self.entries, variant.into(), vec![Element::Folder]);
+        Ok(StructSerializer::new(self.entries, 2))
+    }
+}
+
+/// Serializer for sequences, written as an entry holding a collection.
+///
+/// Tuples of several values are written as plain elements instead, since they are usually
+/// heterogeneous: they are read back as lists all the same.
+struct SeqSerializer<'a> {
+    entries: &'a mut Vec<Entry>,
+    name: String,
+    elements: Vec<Element>,
+    depth: usize,
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn new(
+        entries: &'a mut Vec<Entry>,
+        name: String,
+        len: Option<usize>,
+        depth: usize,
+    ) -> SeqSerializer<'a> {
+        let mut elements = Vec::with_capacity(1 + len.unwrap_or(0));
+        elements.push(Element::Collection);
+        SeqSerializer {
+            entries,
+            name,
+            elements,
+            depth,
+        }
+    }
+
+    fn new_tuple(
+        entries: &'a mut Vec<Entry>,
+        name: String,
+        len: usize,
+        depth: usize,
+    ) -> SeqSerializer<'a> {
+        if len < 2 {
+            return SeqSerializer::new(entries, name, Some(len), depth);
+        }
+        let elements = Vec::with_capacity(len);
+        SeqSerializer {
+            entries,
+            name,
+            elements,
+            depth,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.elements.push(value.serialize(ElementSerializer)?);
+        Ok(())
+    }
+
+    fn close(self) -> Result<()> {
+        push_entry(self.entries, self.name, self.elements);
+        for _ in 0..self.depth {
+            push_entry(self.entries, "..".into(), Vec::new());
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.close()
+    }
+}
+
+/// Serializer for scalar values, turned into single elements.
+struct ElementSerializer;
+
+impl ser::Serializer for ElementSerializer {
+    type Ok = Element;
+    type Error = Error;
+    type SerializeSeq = Impossible<Element, Error>;
+    type SerializeTuple = Impossible<Element, Error>;
+    type SerializeTupleStruct = Impossible<Element, Error>;
+    type SerializeTupleVariant = Impossible<Element, Error>;
+    type SerializeMap = Impossible<Element, Error>;
+    type SerializeStruct = Impossible<Element, Error>;
+    type SerializeStructVariant = Impossible<Element, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Element> {
+        Ok(Element::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Element> {
+        Ok(Element::Integer8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Element> {
+        Ok(narrow_signed(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Element> {
+        Ok(narrow_signed(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Element> {
+        Ok(narrow_signed(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Element> {
+        Ok(Element::UInteger8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Element> {
+        Ok(narrow_unsigned(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Element> {
+        Ok(narrow_unsigned(v as u64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Element> {
+        Ok(narrow_unsigned(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Element> {
+        Ok(Element::Float32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Element> {
+        Ok(Element::Float64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Element> {
+        Ok(Element::String(v.into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Element> {
+        Ok(Element::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Element> {
+        Ok(Element::Blob(v.into()))
+    }
+
+    reject! { NOT_A_SCALAR =>
+        serialize_none(), serialize_unit(), serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Element> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Element> {
+        Ok(Element::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Element> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Element> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::Custom(NOT_A_SCALAR.into()))
+    }
+}
+
+fn push_entry(entries: &mut Vec<Entry>, name: String, elements: Vec<Element>) {
+    entries.push(Entry { name, elements });
+}
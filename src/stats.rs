@@ -0,0 +1,47 @@
+use crate::model::ElementKind;
+use std::collections::HashMap;
+
+/// Statistics about the elements of a TPK payload, as computed by
+/// [Reader::statistics][crate::Reader::statistics].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    kinds: HashMap<ElementKind, KindStats>,
+}
+
+/// Statistics about the elements of a given [kind][ElementKind].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KindStats {
+    /// The number of elements of this kind.
+    pub count: usize,
+    /// The total number of bytes occupied by the elements of this kind.
+    pub bytes: usize,
+}
+
+impl Stats {
+    /// Get the statistics of the elements of the given [kind][ElementKind].
+    pub fn get(&self, kind: ElementKind) -> KindStats {
+        self.kinds.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// Get the statistics of every element, regardless of its kind.
+    pub fn total(&self) -> KindStats {
+        self.kinds
+            .values()
+            .fold(KindStats::default(), |total, stats| KindStats {
+                count: total.count + stats.count,
+                bytes: total.bytes + stats.bytes,
+            })
+    }
+
+    /// Iterate over the statistics of every [kind][ElementKind] of element encountered, in no
+    /// particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (ElementKind, KindStats)> + '_ {
+        self.kinds.iter().map(|(kind, stats)| (*kind, *stats))
+    }
+
+    pub(crate) fn record(&mut self, kind: ElementKind, bytes: usize) {
+        let stats = self.kinds.entry(kind).or_default();
+        stats.count += 1;
+        stats.bytes += bytes;
+    }
+}
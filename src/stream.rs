@@ -0,0 +1,66 @@
+use crate::{read, write, Element, Reader, Writer};
+use std::io;
+use thiserror::Error;
+
+/// Representation of a TPK streaming error.
+///
+/// Since streaming functions both read and write TPK data, this error can originate from either
+/// side of the stream.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An error happened while reading TPK data.
+    #[error(transparent)]
+    Read(#[from] read::Error),
+
+    /// An error happened while writing TPK data.
+    #[error(transparent)]
+    Write(#[from] write::Error),
+}
+
+/// Representation of a TPK streaming result.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Read every element of a TPK payload, transform it, and write the result.
+///
+/// The given function is called for every [element][crate::Element] read from the reader,
+/// including [markers][crate::Element::Marker]. The element it returns is written to the
+/// writer, or skipped if it returns `None`. Since elements are processed one at a time, the
+/// structure of the payload is preserved as long as the function leaves markers untouched.
+pub fn transform<R, W, F>(reader: R, writer: W, mut f: F) -> Result<()>
+where
+    R: io::Read,
+    W: io::Write,
+    F: FnMut(Element) -> Option<Element>,
+{
+    let mut reader = Reader::new(reader);
+    let mut writer = Writer::new(writer);
+    while let Some(element) = reader.read_element()? {
+        if let Some(element) = f(element) {
+            writer.write_element(&element)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read every element of a TPK payload, rename its markers, and write the result.
+///
+/// The given function is called with the name of every [marker][crate::Element::Marker] read
+/// from the reader, and the marker is written with the name it returns. Other elements are
+/// copied verbatim, using the raw bytes they were decoded from, which makes this suitable for
+/// namespacing the entries of a payload without altering their data.
+pub fn rename_markers<R, W, F>(reader: R, writer: W, mut f: F) -> Result<()>
+where
+    R: io::Read,
+    W: io::Write,
+    F: FnMut(&str) -> String,
+{
+    let mut reader = Reader::new(reader).retain_raw(true);
+    let mut writer = Writer::new(writer);
+    while let Some(element) = reader.read_raw_element()? {
+        match element.element {
+            Element::Marker(ref name) => writer.write_element(&Element::Marker(f(name)))?,
+            _ => writer.write_raw_element(&element)?,
+        }
+    }
+    Ok(())
+}
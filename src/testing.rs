@@ -0,0 +1,63 @@
+//! Helpers producing raw TPK headers, for tests and fuzzing harnesses.
+//!
+//! These helpers produce the type byte and size bytes of elements, without their data, which
+//! makes it possible to assemble arbitrary (and possibly invalid) TPK payloads by hand.
+
+use crate::model::{size_byte, ElementKind};
+use crate::write::{push_dyn_size, push_static_size};
+
+/// Get the header of a marker whose name is `name_len` bytes long.
+///
+/// The header is made of the type byte of the marker, followed by its continuation size bytes
+/// if the name is longer than 63 bytes.
+pub fn marker_header(name_len: usize) -> Vec<u8> {
+    let mut header = vec![0b10000000u8 | (name_len & 0b00111111) as u8];
+    if name_len > 63 {
+        header[0] |= 0b01000000;
+        push_dyn_size(name_len >> 6, &mut header);
+    }
+    header
+}
+
+/// Get the header of a string that is `len` bytes long.
+pub fn string_header(len: usize) -> Vec<u8> {
+    let mut header = vec![0b00010000u8 | size_byte(len)];
+    push_static_size(len, &mut header);
+    header
+}
+
+/// Get the header of a blob that is `len` bytes long.
+pub fn blob_header(len: usize) -> Vec<u8> {
+    let mut header = vec![0b00010100u8 | size_byte(len)];
+    push_static_size(len, &mut header);
+    header
+}
+
+/// Get the header of an extension with the given identifier, whose payload is `len` bytes long.
+pub fn extension_header(id: u8, len: usize) -> Vec<u8> {
+    let mut header = vec![0b01110000u8 | size_byte(len), id];
+    push_static_size(len, &mut header);
+    header
+}
+
+/// Get the type byte of the given numeric [kind][ElementKind] of element.
+///
+/// This returns `None` if the kind is not a number.
+pub fn number_type_byte(kind: ElementKind) -> Option<u8> {
+    let type_byte = match kind {
+        ElementKind::UInteger8 => 0b00100000u8,
+        ElementKind::UInteger16 => 0b00100001u8,
+        ElementKind::UInteger32 => 0b00100010u8,
+        ElementKind::UInteger64 => 0b00100011u8,
+        ElementKind::UInteger128 => 0b00101000u8,
+        ElementKind::Integer8 => 0b00100100u8,
+        ElementKind::Integer16 => 0b00100101u8,
+        ElementKind::Integer32 => 0b00100110u8,
+        ElementKind::Integer64 => 0b00100111u8,
+        ElementKind::Integer128 => 0b00101001u8,
+        ElementKind::Float32 => 0b00101110u8,
+        ElementKind::Float64 => 0b00101111u8,
+        _ => return None,
+    };
+    Some(type_byte)
+}
@@ -0,0 +1,352 @@
+//! Human-readable text representation of TPK elements.
+//!
+//! Each element is written as its type followed by its value in parentheses, and elements are
+//! separated by semicolons. For example, the timestamp entry used throughout this crate reads as
+//! `marker("name"); u32(1651906455); str("unix_time")`. This format is meant for test fixtures
+//! and documentation, which are easier to author and review as text than as bytes.
+//!
+//! The following types are recognized:
+//!
+//! - `marker("name")`, `folder()` and `collection()`;
+//! - `i8(-1)` to `i128(-1)`, `u8(1)` to `u128(1)`, `f32(1.5)` and `f64(1.5)`;
+//! - `bool(true)`, `str("text")` and `cstr("text")`, strings using Rust escape sequences;
+//! - `blob(2a2a)`, holding hexadecimal data;
+//! - `ext(200, 2a2a)`, holding an extension identifier and hexadecimal data;
+//! - `delta_u64(1, 2, 3)`, `blob_ref(0)`, `string_ref(0)`, `flagged(1, u8(42))` and
+//!   `duration(-5)`;
+//! - `padding(16)`, holding the number of reserved bytes;
+//! - `typed_collection(34, 3)`, holding the type byte and the number of the elements;
+//! - `map("key": u8(1), "other": str("value"))`, holding ordered pairs of keys and elements.
+
+use crate::Element;
+use std::fmt::Write;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Representation of a TPK text parsing error.
+///
+/// Positions are byte offsets in the parsed text.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A syntax error happened.
+    ///
+    /// This error happens when the text is not a well-formed list of elements.
+    #[error("Syntax error at position {0}: {1}")]
+    Syntax(usize, &'static str),
+
+    /// A type is unknown.
+    ///
+    /// This error happens when an element is well-formed, but its type is not one of the
+    /// [recognized types][self].
+    #[error("Unknown element type at position {pos}: {name}")]
+    UnknownType { pos: usize, name: String },
+
+    /// A value is invalid.
+    ///
+    /// This error happens when the value of an element cannot be represented by its type, for
+    /// example `u8(300)` or `blob(2a2)`.
+    #[error("Invalid value for {name} at position {pos}")]
+    InvalidValue { pos: usize, name: String },
+}
+
+/// Representation of a TPK text parsing result.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Parse the given text into a list of [elements][Element].
+///
+/// Whitespace is allowed around elements and values, and the last element may be followed by a
+/// semicolon.
+pub fn parse_text(text: &str) -> Result<Vec<Element>> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut elements = Vec::new();
+    loop {
+        parser.skip_whitespace();
+        if parser.pos == text.len() {
+            return Ok(elements);
+        }
+        elements.push(parser.parse_element()?);
+        parser.skip_whitespace();
+        if parser.pos != text.len() {
+            parser.expect(';')?;
+        }
+    }
+}
+
+/// Convert the given [elements][Element] into text, which can be [parsed][parse_text] back.
+pub fn to_text(elements: &[Element]) -> String {
+    let mut text = String::new();
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            text.push_str("; ");
+        }
+        write_element(&mut text, element);
+    }
+    text
+}
+
+fn write_element(text: &mut String, element: &Element) {
+    // Writing to a string cannot fail.
+    let _ = match *element {
+        Element::Marker(ref name) => write!(text, "marker({:?})", name),
+        Element::Folder => write!(text, "folder()"),
+        Element::Collection => write!(text, "collection()"),
+        Element::Integer8(val) => write!(text, "i8({})", val),
+        Element::Integer16(val) => write!(text, "i16({})", val),
+        Element::Integer32(val) => write!(text, "i32({})", val),
+        Element::Integer64(val) => write!(text, "i64({})", val),
+        Element::Integer128(val) => write!(text, "i128({})", val),
+        Element::UInteger8(val) => write!(text, "u8({})", val),
+        Element::UInteger16(val) => write!(text, "u16({})", val),
+        Element::UInteger32(val) => write!(text, "u32({})", val),
+        Element::UInteger64(val) => write!(text, "u64({})", val),
+        Element::UInteger128(val) => write!(text, "u128({})", val),
+        // The debug representation of floats is the shortest one parsing back to the same value.
+        Element::Float32(val) => write!(text, "f32({:?})", val),
+        Element::Float64(val) => write!(text, "f64({:?})", val),
+        Element::Boolean(val) => write!(text, "bool({})", val),
+        Element::String(ref val) => write!(text, "str({:?})", val),
+        Element::Blob(ref data) => write!(text, "blob({})", hex(data)),
+        Element::CString(ref val) => write!(text, "cstr({:?})", val),
+        Element::Extension { id, ref data } => write!(text, "ext({}, {})", id, hex(data)),
+        Element::DeltaU64(ref values) => {
+            let values: Vec<String> = values.iter().map(u64::to_string).collect();
+            write!(text, "delta_u64({})", values.join(", "))
+        }
+        Element::BlobRef(index) => write!(text, "blob_ref({})", index),
+        Element::StringRef(index) => write!(text, "string_ref({})", index),
+        Element::Flagged { flags, ref inner } => {
+            let _ = write!(text, "flagged({}, ", flags);
+            write_element(text, inner);
+            write!(text, ")")
+        }
+        Element::Duration(val) => write!(text, "duration({})", val),
+        Element::Padding(len) => write!(text, "padding({})", len),
+        Element::TypedCollection { type_byte, count } => {
+            write!(text, "typed_collection({}, {})", type_byte, count)
+        }
+        Element::Map(ref pairs) => {
+            text.push_str("map(");
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                let _ = write!(text, "{}{:?}: ", if i > 0 { ", " } else { "" }, key);
+                write_element(text, value);
+            }
+            write!(text, ")")
+        }
+    };
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_element(&mut self) -> Result<Element> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() {
+            return Err(Error::Syntax(start, "expected an element type"));
+        }
+        self.expect('(')?;
+
+        let element = match name {
+            "marker" => Element::Marker(self.parse_string()?),
+            "folder" => Element::Folder,
+            "collection" => Element::Collection,
+            "i8" => Element::Integer8(self.parse_value(name)?),
+            "i16" => Element::Integer16(self.parse_value(name)?),
+            "i32" => Element::Integer32(self.parse_value(name)?),
+            "i64" => Element::Integer64(self.parse_value(name)?),
+            "i128" => Element::Integer128(self.parse_value(name)?),
+            "u8" => Element::UInteger8(self.parse_value(name)?),
+            "u16" => Element::UInteger16(self.parse_value(name)?),
+            "u32" => Element::UInteger32(self.parse_value(name)?),
+            "u64" => Element::UInteger64(self.parse_value(name)?),
+            "u128" => Element::UInteger128(self.parse_value(name)?),
+            "f32" => Element::Float32(self.parse_value(name)?),
+            "f64" => Element::Float64(self.parse_value(name)?),
+            "bool" => Element::Boolean(self.parse_value(name)?),
+            "str" => Element::String(self.parse_string()?),
+            "blob" => Element::Blob(self.parse_hex(name)?),
+            "cstr" => Element::CString(self.parse_string()?),
+            "ext" => {
+                let id = self.parse_value(name)?;
+                self.expect(',')?;
+                Element::Extension {
+                    id,
+                    data: self.parse_hex(name)?,
+                }
+            }
+            "delta_u64" => {
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                while !self.text[self.pos..].starts_with(')') {
+                    if !values.is_empty() {
+                        self.expect(',')?;
+                    }
+                    values.push(self.parse_value(name)?);
+                    self.skip_whitespace();
+                }
+                Element::DeltaU64(values)
+            }
+            "blob_ref" => Element::BlobRef(self.parse_value(name)?),
+            "string_ref" => Element::StringRef(self.parse_value(name)?),
+            "flagged" => {
+                let flags = self.parse_value(name)?;
+                self.expect(',')?;
+                Element::Flagged {
+                    flags,
+                    inner: Box::new(self.parse_element()?),
+                }
+            }
+            "duration" => Element::Duration(self.parse_value(name)?),
+            "padding" => Element::Padding(self.parse_value(name)?),
+            "map" => {
+                let mut pairs = Vec::new();
+                self.skip_whitespace();
+                while !self.text[self.pos..].starts_with(')') {
+                    if !pairs.is_empty() {
+                        self.expect(',')?;
+                    }
+                    let key = self.parse_string()?;
+                    self.expect(':')?;
+                    pairs.push((key, self.parse_element()?));
+                    self.skip_whitespace();
+                }
+                Element::Map(pairs)
+            }
+            "typed_collection" => {
+                let type_byte = self.parse_value(name)?;
+                self.expect(',')?;
+                Element::TypedCollection {
+                    type_byte,
+                    count: self.parse_value(name)?,
+                }
+            }
+            _ => {
+                return Err(Error::UnknownType {
+                    pos: start,
+                    name: name.into(),
+                })
+            }
+        };
+
+        self.expect(')')?;
+        Ok(element)
+    }
+
+    fn parse_value<V: FromStr>(&mut self, name: &str) -> Result<V> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let token = self.take_token();
+        if token.is_empty() {
+            return Err(Error::Syntax(start, "expected a value"));
+        }
+        token.parse().map_err(|_| Error::InvalidValue {
+            pos: start,
+            name: name.into(),
+        })
+    }
+
+    fn parse_hex(&mut self, name: &str) -> Result<Vec<u8>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let token = self.take_token();
+        let invalid = || Error::InvalidValue {
+            pos: start,
+            name: name.into(),
+        };
+        if !token.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        (0..token.len())
+            .step_by(2)
+            .map(|i| {
+                token
+                    .get(i..i + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect()
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let escape_start = self.pos;
+            match self.next_char() {
+                None => return Err(Error::Syntax(self.pos, "unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.next_char() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some(c @ ('\\' | '"' | '\'')) => c,
+                    Some('u') => self
+                        .parse_unicode_escape()
+                        .ok_or(Error::Syntax(escape_start, "invalid unicode escape"))?,
+                    _ => return Err(Error::Syntax(escape_start, "invalid escape sequence")),
+                }),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Option<char> {
+        if self.next_char()? != '{' {
+            return None;
+        }
+        let digits = self.take_while(|c| c.is_ascii_hexdigit());
+        if self.next_char()? != '}' {
+            return None;
+        }
+        char::from_u32(u32::from_str_radix(digits, 16).ok()?)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        let pos = self.pos;
+        if self.next_char() != Some(expected) {
+            return Err(Error::Syntax(
+                pos,
+                match expected {
+                    '(' => "expected '('",
+                    ')' => "expected ')'",
+                    ',' => "expected ','",
+                    ';' => "expected ';'",
+                    ':' => "expected ':'",
+                    _ => "expected '\"'",
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.text[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn take_token(&mut self) -> &'a str {
+        self.take_while(|c| !c.is_whitespace() && !matches!(c, ',' | ')' | ';'))
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, pred: F) -> &'a str {
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{Document, Element, Entry};
+
+/// Representation of a dynamically-typed TPK value.
+///
+/// This is a self-describing representation of TPK data, detached from its binary encoding,
+/// which is meant to bridge TPK data with dynamically-typed environments such as scripting
+/// languages. It can be built from a single [Element], or from a whole [Document].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A signed integer, of any width.
+    Int(i64),
+    /// An unsigned integer, of any width.
+    UInt(u64),
+    /// A floating-point number, of any precision.
+    Float(f64),
+    /// A boolean.
+    Bool(bool),
+    /// A UTF-8 string.
+    Str(String),
+    /// A binary blob.
+    Bytes(Vec<u8>),
+    /// An ordered list of values.
+    List(Vec<Value>),
+    /// An ordered list of named values.
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get the value associated with the given key, if this value is a map.
+    ///
+    /// If the key appears several times in the map, the first value is returned.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Build a [Value] tree out of a [Document].
+    ///
+    /// The resulting value is always a [map][Value::Map], built according to the following
+    /// rules:
+    ///
+    /// - an entry containing a single [folder][Element::Folder] opens a nested map, in which the
+    ///   following entries are inserted;
+    /// - an entry named `..` without any element closes the current nested map, and an entry
+    ///   named `/` without any element closes all of them;
+    /// - an entry starting with a [collection][Element::Collection], typed or not, becomes a
+    ///   [list][Value::List] of the following elements of the entry;
+    /// - an entry containing a single element becomes the value of that element;
+    /// - any other entry becomes a [list][Value::List] of its elements.
+    pub fn from_document(document: &Document) -> Value {
+        let mut stack = vec![(String::new(), Vec::new())];
+        for entry in &document.entries {
+            match (entry.name.as_str(), entry.elements.as_slice()) {
+                ("..", []) => close_map(&mut stack),
+                ("/", []) => {
+                    while stack.len() > 1 {
+                        close_map(&mut stack);
+                    }
+                }
+                (_, [Element::Folder]) => stack.push((entry.name.clone(), Vec::new())),
+                _ => {
+                    let value = entry_value(entry);
+                    let (_, map) = stack.last_mut().unwrap();
+                    map.push((entry.name.clone(), value));
+                }
+            }
+        }
+
+        while stack.len() > 1 {
+            close_map(&mut stack);
+        }
+        Value::Map(stack.pop().unwrap().1)
+    }
+}
+
+impl From<Element> for Value {
+    /// Convert an [Element] into a [Value].
+    ///
+    /// 128-bit integers that do not fit in 64 bits are converted into [floats][Value::Float],
+    /// which may lose precision.
+    ///
+    /// Markers are converted into [strings][Value::Str] holding their name, while folders and
+    /// collections (typed or not), which only make sense in the context of a [Document], are
+    /// respectively converted into an empty [map][Value::Map] and an empty [list][Value::List].
+    /// Extension elements are converted into [bytes][Value::Bytes] holding their raw payload,
+    /// except for delta-encoded integers which are converted into a [list][Value::List] of their
+    /// values, unresolved blob and string references which are converted into the index of the data
+    /// they refer to, and durations which are converted into their number of nanoseconds. Flagged
+    /// elements are converted like their inner element, maps are converted into a [map][Value::Map]
+    /// of their converted values, and padding is converted into its number of bytes.
+    fn from(element: Element) -> Value {
+        match element {
+            Element::Marker(name) => Value::Str(name),
+            Element::Folder => Value::Map(Vec::new()),
+            Element::Collection => Value::List(Vec::new()),
+            Element::Integer8(val) => Value::Int(val as i64),
+            Element::Integer16(val) => Value::Int(val as i64),
+            Element::Integer32(val) => Value::Int(val as i64),
+            Element::Integer64(val) => Value::Int(val),
+            Element::Integer128(val) => {
+                i64::try_from(val).map_or(Value::Float(val as f64), Value::Int)
+            }
+            Element::UInteger8(val) => Value::UInt(val as u64),
+            Element::UInteger16(val) => Value::UInt(val as u64),
+            Element::UInteger32(val) => Value::UInt(val as u64),
+            Element::UInteger64(val) => Value::UInt(val),
+            Element::UInteger128(val) => {
+                u64::try_from(val).map_or(Value::Float(val as f64), Value::UInt)
+            }
+            Element::Float32(val) => Value::Float(val as f64),
+            Element::Float64(val) => Value::Float(val),
+            Element::Boolean(val) => Value::Bool(val),
+            Element::String(val) => Value::Str(val),
+            Element::Blob(val) => Value::Bytes(val),
+            Element::CString(val) => Value::Str(val),
+            Element::Extension { data, .. } => Value::Bytes(data),
+            Element::DeltaU64(values) => Value::List(values.into_iter().map(Value::UInt).collect()),
+            Element::BlobRef(index) | Element::StringRef(index) => Value::UInt(index as u64),
+            Element::Duration(nanos) => Value::Int(nanos),
+            Element::Padding(len) => Value::UInt(len as u64),
+            Element::TypedCollection { .. } => Value::List(Vec::new()),
+            Element::Map(pairs) => Value::Map(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+            ),
+            Element::Flagged { inner, .. } => Value::from(*inner),
+        }
+    }
+}
+
+impl From<&Document> for Value {
+    fn from(document: &Document) -> Value {
+        Value::from_document(document)
+    }
+}
+
+pub(crate) fn entry_value(entry: &Entry) -> Value {
+    match entry.elements.as_slice() {
+        [Element::Collection | Element::TypedCollection { .. }, items @ ..] => {
+            Value::List(items.iter().cloned().map(Value::from).collect())
+        }
+        [element] => element.clone().into(),
+        elements => Value::List(elements.iter().cloned().map(Value::from).collect()),
+    }
+}
+
+fn close_map(stack: &mut Vec<(String, Vec<(String, Value)>)>) {
+    if stack.len() > 1 {
+        let (name, map) = stack.pop().unwrap();
+        let (_, parent) = stack.last_mut().unwrap();
+        parent.push((name, Value::Map(map)));
+    }
+}
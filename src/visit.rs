@@ -0,0 +1,156 @@
+use crate::Element;
+
+/// A visitor of TPK [elements][Element].
+///
+/// This trait provides one method per element type, which is called by [Element::accept] with
+/// the value of the visited element. Every method does nothing by default, so implementors only
+/// need to override the methods corresponding to the element types they are interested in.
+#[allow(unused_variables)]
+pub trait Visitor {
+    /// Visit a [marker element][Element::Marker].
+    fn visit_marker(&mut self, name: &str) {}
+
+    /// Visit a [folder element][Element::Folder].
+    fn visit_folder(&mut self) {}
+
+    /// Visit a [collection element][Element::Collection].
+    fn visit_collection(&mut self) {}
+
+    /// Visit a [signed 8-bit integer element][Element::Integer8].
+    fn visit_i8(&mut self, v: i8) {}
+
+    /// Visit a [signed 16-bit integer element][Element::Integer16].
+    fn visit_i16(&mut self, v: i16) {}
+
+    /// Visit a [signed 32-bit integer element][Element::Integer32].
+    fn visit_i32(&mut self, v: i32) {}
+
+    /// Visit a [signed 64-bit integer element][Element::Integer64].
+    fn visit_i64(&mut self, v: i64) {}
+
+    /// Visit a [signed 128-bit integer element][Element::Integer128].
+    fn visit_i128(&mut self, v: i128) {}
+
+    /// Visit an [unsigned 8-bit integer element][Element::UInteger8].
+    fn visit_u8(&mut self, v: u8) {}
+
+    /// Visit an [unsigned 16-bit integer element][Element::UInteger16].
+    fn visit_u16(&mut self, v: u16) {}
+
+    /// Visit an [unsigned 32-bit integer element][Element::UInteger32].
+    fn visit_u32(&mut self, v: u32) {}
+
+    /// Visit an [unsigned 64-bit integer element][Element::UInteger64].
+    fn visit_u64(&mut self, v: u64) {}
+
+    /// Visit an [unsigned 128-bit integer element][Element::UInteger128].
+    fn visit_u128(&mut self, v: u128) {}
+
+    /// Visit a [single precision floating-point number element][Element::Float32].
+    fn visit_f32(&mut self, v: f32) {}
+
+    /// Visit a [double precision floating-point number element][Element::Float64].
+    fn visit_f64(&mut self, v: f64) {}
+
+    /// Visit a [boolean element][Element::Boolean].
+    fn visit_bool(&mut self, v: bool) {}
+
+    /// Visit a [string element][Element::String].
+    fn visit_string(&mut self, s: &str) {}
+
+    /// Visit a [blob element][Element::Blob].
+    fn visit_blob(&mut self, b: &[u8]) {}
+
+    /// Visit a [NUL-terminated string element][Element::CString].
+    ///
+    /// By default, this behaves like [visit_string][Self::visit_string].
+    fn visit_c_string(&mut self, s: &str) {
+        self.visit_string(s)
+    }
+
+    /// Visit an [extension element][Element::Extension].
+    fn visit_extension(&mut self, id: u8, data: &[u8]) {}
+
+    /// Visit a [delta-encoded integer collection element][Element::DeltaU64].
+    fn visit_delta_u64(&mut self, values: &[u64]) {}
+
+    /// Visit a [blob reference element][Element::BlobRef].
+    fn visit_blob_ref(&mut self, index: u32) {}
+
+    /// Visit a [string reference element][Element::StringRef].
+    fn visit_string_ref(&mut self, index: u32) {}
+
+    /// Visit a [duration element][Element::Duration], in nanoseconds.
+    fn visit_duration(&mut self, nanos: i64) {}
+
+    /// Visit the flags of a [flagged element][Element::Flagged].
+    ///
+    /// This is called right before the inner element of the flagged element is visited.
+    fn visit_flags(&mut self, flags: u8) {}
+
+    /// Visit a [padding element][Element::Padding], holding the given number of bytes.
+    fn visit_padding(&mut self, len: usize) {}
+
+    /// Visit a [typed collection element][Element::TypedCollection].
+    ///
+    /// By default, this behaves like [visit_collection][Self::visit_collection].
+    fn visit_typed_collection(&mut self, type_byte: u8, count: u32) {
+        self.visit_collection()
+    }
+
+    /// Visit a [map element][Element::Map] holding the given number of pairs.
+    ///
+    /// This is called right before the pairs of the map are visited: for each pair,
+    /// [visit_map_key][Self::visit_map_key] is called with its key, then its value is visited.
+    fn visit_map(&mut self, len: usize) {}
+
+    /// Visit the key of a pair of a [map element][Element::Map].
+    fn visit_map_key(&mut self, key: &str) {}
+}
+
+impl Element {
+    /// Dispatch this [Element] to the corresponding method of the given [Visitor].
+    pub fn accept<V: Visitor>(&self, v: &mut V) {
+        match *self {
+            Element::Marker(ref name) => v.visit_marker(name),
+            Element::Folder => v.visit_folder(),
+            Element::Collection => v.visit_collection(),
+            Element::Integer8(val) => v.visit_i8(val),
+            Element::Integer16(val) => v.visit_i16(val),
+            Element::Integer32(val) => v.visit_i32(val),
+            Element::Integer64(val) => v.visit_i64(val),
+            Element::Integer128(val) => v.visit_i128(val),
+            Element::UInteger8(val) => v.visit_u8(val),
+            Element::UInteger16(val) => v.visit_u16(val),
+            Element::UInteger32(val) => v.visit_u32(val),
+            Element::UInteger64(val) => v.visit_u64(val),
+            Element::UInteger128(val) => v.visit_u128(val),
+            Element::Float32(val) => v.visit_f32(val),
+            Element::Float64(val) => v.visit_f64(val),
+            Element::Boolean(val) => v.visit_bool(val),
+            Element::String(ref val) => v.visit_string(val),
+            Element::Blob(ref val) => v.visit_blob(val),
+            Element::CString(ref val) => v.visit_c_string(val),
+            Element::Extension { id, ref data } => v.visit_extension(id, data),
+            Element::DeltaU64(ref values) => v.visit_delta_u64(values),
+            Element::BlobRef(index) => v.visit_blob_ref(index),
+            Element::StringRef(index) => v.visit_string_ref(index),
+            Element::Duration(nanos) => v.visit_duration(nanos),
+            Element::Flagged { flags, ref inner } => {
+                v.visit_flags(flags);
+                inner.accept(v);
+            }
+            Element::Padding(len) => v.visit_padding(len),
+            Element::TypedCollection { type_byte, count } => {
+                v.visit_typed_collection(type_byte, count)
+            }
+            Element::Map(ref pairs) => {
+                v.visit_map(pairs.len());
+                for (key, value) in pairs {
+                    v.visit_map_key(key);
+                    value.accept(v);
+                }
+            }
+        }
+    }
+}
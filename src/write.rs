@@ -1,4 +1,11 @@
-use crate::{Element, Entry};
+use crate::model::{
+    encode_delta_u64, is_number_type_byte, size_byte, BLOB_REF_EXTENSION_ID,
+    COMPRESSED_BLOB_EXTENSION_ID, DELTA_U64_EXTENSION_ID, DURATION_EXTENSION_ID,
+    FLAGGED_EXTENSION_ID, LATEST_VERSION, MAP_EXTENSION_ID, STRING_REF_EXTENSION_ID,
+    TYPED_COLLECTION_EXTENSION_ID,
+};
+use crate::{Document, Element, Entry, RawElement};
+use std::collections::HashMap;
 use std::{io, mem};
 use thiserror::Error;
 
@@ -19,16 +26,155 @@ pub enum Error {
         #[from]
         source: io::Error,
     },
+
+    /// An element type does not exist in the format version of the writer.
+    ///
+    /// This error happens when writing an element whose type was introduced after the
+    /// [format version][Writer::version] configured for the writer. Nothing is written.
+    #[error("Element type {byte:#X} does not exist in version {version}")]
+    TypeNotInVersion { byte: u8, version: u8 },
+
+    /// An element is too large to be encoded.
+    ///
+    /// This error happens when writing a [map][Element::Map] whose number of pairs, or a
+    /// [footer][Writer::write_footer] whose length, does not fit in the `u32` holding it.
+    #[error("Element data of {len} bytes is too large to be encoded")]
+    TooLarge { len: usize },
+
+    /// A duration is too long to be encoded.
+    ///
+    /// This error happens when [converting][Element::try_from] a [std::time::Duration] longer
+    /// than `i64::MAX` nanoseconds (about 292 years) into a [duration element][Element::Duration].
+    #[error("Duration is too long to be encoded")]
+    DurationTooLong,
+
+    /// An extension identifier is reserved.
+    ///
+    /// This error happens when [creating an extension element][Element::extension] with an
+    /// identifier from 0 to 127, which are reserved for the extensions defined by this crate.
+    #[error("Extension identifier {id} is reserved")]
+    ReservedExtensionId { id: u8 },
+
+    /// A NUL-terminated string contains a NUL character.
+    ///
+    /// This error happens when writing a [NUL-terminated string][Element::CString] holding a NUL
+    /// character, which would terminate the string early. Nothing is written.
+    #[error("NUL-terminated string contains a NUL character at byte {pos}")]
+    InteriorNul { pos: usize },
+
+    /// A floating-point number is not finite.
+    ///
+    /// This error happens when writing a NaN or infinite floating-point number with a writer
+    /// [rejecting non-finite floats][Writer::reject_non_finite_floats]. Nothing is written.
+    #[error("Non-finite floating-point number {value} cannot be written")]
+    NonFiniteFloat { value: f64 },
+
+    /// A collection contains elements of different types.
+    ///
+    /// This error happens when
+    /// [writing a collection from an iterator][Writer::write_collection_from] yielding an item
+    /// that is not of the same type as the first one. The item is not written, but the items
+    /// preceding it are.
+    #[error("Heterogeneous collection item at index {index}")]
+    HeterogeneousCollection { index: usize },
+
+    /// A collection contains a marker or a container.
+    ///
+    /// This error happens when
+    /// [writing a collection from an iterator][Writer::write_collection_from] yielding a
+    /// [marker][Element::Marker], a [folder][Element::Folder], a
+    /// [collection][Element::Collection] or a [typed collection][Element::TypedCollection], which
+    /// would end the collection or start another one. The item is not written, but the items
+    /// preceding it are.
+    #[error("Collection item at index {index} is a marker or a container")]
+    InvalidCollectionItem { index: usize },
+
+    /// A typed collection does not hold what it announces.
+    ///
+    /// This error happens when writing a [typed collection][Element::TypedCollection] whose type
+    /// byte is not the one of a number element, or when the elements written after it, up to
+    /// the next marker or container, are not exactly `count` elements of that type. The element
+    /// is not written.
+    #[error("Element does not match the typed collection it belongs to")]
+    InvalidTypedCollection,
+
+    /// A string cannot be represented in the encoding of the writer.
+    ///
+    /// This error happens when a marker name or string holds a character that does not exist
+    /// in the string encoding configured for the writer, which requires the `encoding_rs`
+    /// feature. Nothing is written.
+    #[error("String cannot be encoded in {encoding}")]
+    UnmappableCharacter { encoding: &'static str },
+
+    /// A marker is nested in another element.
+    ///
+    /// This error happens when writing a [flagged element][Element::Flagged] whose inner element
+    /// is a [marker][Element::Marker], or a [map][Element::Map] holding a marker as a value,
+    /// since markers can only appear at the top level of TPK data. Nothing is written.
+    #[error("Marker cannot be nested in another element")]
+    NestedMarker,
+
+    /// The data does not start with a marker.
+    ///
+    /// This error happens when the first element written by a writer in
+    /// [strict structure mode][Writer::strict_structure] is not a [marker][Element::Marker].
+    /// Nothing is written.
+    #[error("TPK data must start with a marker")]
+    MissingLeadingMarker,
+
+    /// A frame is too large.
+    ///
+    /// This error happens when [writing a framed document][Writer::write_framed_document] or
+    /// [appending a log record][LogWriter::append] whose encoded length does not fit in the `u32`
+    /// length prefix of the frame.
+    #[error("Frame of {len} bytes exceeds the maximum frame length")]
+    FrameTooLarge { len: usize },
 }
 
 /// Representation of a TPK write result.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The name of the entry holding the [footer][Writer::write_footer] of TPK data.
+pub const FOOTER_NAME: &str = "__footer__";
+
+/// The name of the entry holding the [string table][Writer::write_string_table] of TPK data.
+pub const STRING_TABLE_NAME: &str = "__strings__";
+
+/// The separator preceding every record of a [log][LogWriter].
+pub const LOG_RECORD_MAGIC: [u8; 4] = *b"TPKR";
+
 /// A TPK writer structure.
 ///
 /// This structure holds the destination to which TPK data should be written.
+///
+/// A writer is [Send] and [Sync] as long as its destination is.
 pub struct Writer<T> {
     write: T,
+    use_scratch_buffer: bool,
+    scratch_buffer: Vec<u8>,
+    strict_structure: bool,
+    started: bool,
+    typed_collection: Option<(u8, u32)>,
+    encoder: Encoder,
+}
+
+/// The configuration and tables used by a [Writer] to encode elements.
+///
+/// This is kept apart from the destination of the writer, so that elements can be encoded either
+/// directly to the destination or to a buffer.
+struct Encoder {
+    version: u8,
+    dedup_blobs: bool,
+    blobs: HashMap<Vec<u8>, u32>,
+    use_string_table: bool,
+    strings: HashMap<String, u32>,
+    #[cfg(feature = "encoding_rs")]
+    string_encoding: &'static encoding_rs::Encoding,
+    canonicalize: bool,
+    in_collection: bool,
+    reject_non_finite_floats: bool,
+    #[cfg(feature = "flate2")]
+    compress_blobs_over: Option<usize>,
 }
 
 impl<T> Writer<T>
@@ -37,7 +183,154 @@ where
 {
     /// Create a new [TPK writer][Writer].
     pub fn new(write: T) -> Writer<T> {
-        Writer { write }
+        Writer {
+            write,
+            use_scratch_buffer: false,
+            scratch_buffer: Vec::new(),
+            strict_structure: false,
+            started: false,
+            typed_collection: None,
+            encoder: Encoder {
+                version: LATEST_VERSION,
+                dedup_blobs: false,
+                blobs: HashMap::new(),
+                use_string_table: false,
+                strings: HashMap::new(),
+                #[cfg(feature = "encoding_rs")]
+                string_encoding: encoding_rs::UTF_8,
+                canonicalize: false,
+                in_collection: false,
+                reject_non_finite_floats: false,
+                #[cfg(feature = "flate2")]
+                compress_blobs_over: None,
+            },
+        }
+    }
+
+    /// Enable or disable the use of a scratch buffer for this writer.
+    ///
+    /// When enabled, the binary representation of each element is assembled in a reusable
+    /// buffer, which is then written to the destination in a single call. This avoids
+    /// allocating memory for the size bytes of every string and blob, and makes sure that each
+    /// element is either entirely written or not written at all by a given call.
+    ///
+    /// Note that the scratch buffer grows to the size of the largest element written, and keeps
+    /// that size for the whole lifetime of the writer.
+    pub fn scratch_buffer(mut self, use_scratch_buffer: bool) -> Writer<T> {
+        self.use_scratch_buffer = use_scratch_buffer;
+        self
+    }
+
+    /// Set the TPK format version of the data written by this writer.
+    ///
+    /// Elements whose type was introduced after that version are refused with an
+    /// [Error::TypeNotInVersion], which makes sure that the data can be read by older consumers.
+    /// By default, the [latest version][LATEST_VERSION] is used.
+    pub fn version(mut self, version: u8) -> Writer<T> {
+        self.encoder.version = version;
+        self
+    }
+
+    /// Enable or disable the deduplication of blobs for this writer.
+    ///
+    /// When enabled, the writer remembers every [blob][Element::Blob] it writes, and writes
+    /// [references][Element::BlobRef] instead of blobs identical to previous ones. The data must
+    /// then be read by a [reader deduplicating blobs][crate::Reader::dedup_blobs] to get the
+    /// blobs back, and this mode should be enabled before writing anything, so that blob
+    /// indices match on both sides.
+    ///
+    /// Note that every distinct blob is kept in memory for the whole lifetime of the writer, and
+    /// that blob references are extension elements, which do not exist in version 1 of the
+    /// format.
+    pub fn dedup_blobs(mut self, dedup_blobs: bool) -> Writer<T> {
+        self.encoder.dedup_blobs = dedup_blobs;
+        self
+    }
+
+    /// Enable or disable the string table of this writer.
+    ///
+    /// When enabled, [documents][Self::write_document] written by this writer start with a
+    /// [string table][Self::write_string_table] holding the strings they repeat, and the
+    /// [strings][Element::String] found in a table are written as
+    /// [references][Element::StringRef] to it. Strings that are not longer than a reference are
+    /// left out of the tables built for documents. [Map][Element::Map] keys are always written
+    /// as strings. The data must then be read by a
+    /// [reader using a string table][crate::Reader::use_string_table] to get the strings back.
+    ///
+    /// Note that every string of the table is kept in memory for the whole lifetime of the
+    /// writer, and that string references are extension elements, which do not exist in version
+    /// 1 of the format.
+    pub fn use_string_table(mut self, use_string_table: bool) -> Writer<T> {
+        self.encoder.use_string_table = use_string_table;
+        self
+    }
+
+    /// Set the encoding of the marker names and strings written by this writer.
+    ///
+    /// By default, names and strings are written as UTF-8. Characters that do not exist in the
+    /// given encoding are refused with an [Error::UnmappableCharacter]. Note that encodings that
+    /// `encoding_rs` cannot encode into, such as UTF-16, fall back to UTF-8.
+    ///
+    /// The length of [framed documents][Self::write_framed_document] accounts for the encoded
+    /// strings, while [footers][Self::write_footer] are always written as UTF-8, so that they can
+    /// be read back without knowing the encoding.
+    #[cfg(feature = "encoding_rs")]
+    pub fn string_encoding(mut self, encoding: &'static encoding_rs::Encoding) -> Writer<T> {
+        self.encoder.string_encoding = encoding;
+        self
+    }
+
+    /// Compress the blobs larger than the given number of bytes written by this writer.
+    ///
+    /// Blobs over the threshold are compressed using DEFLATE, and written as
+    /// [extension elements][Element::Extension] with the [COMPRESSED_BLOB_EXTENSION_ID]
+    /// identifier, which [readers decompressing blobs][crate::Reader::decompress_blobs_up_to]
+    /// read back as [blobs][Element::Blob]. Blobs that do not shrink once compressed are written
+    /// as-is.
+    ///
+    /// Note that compressed blobs are extension elements, which do not exist in version 1 of the
+    /// format, and that [framed documents][Self::write_framed_document] holding blobs over the
+    /// threshold are compressed twice, once to compute their length and once to write them.
+    ///
+    /// [COMPRESSED_BLOB_EXTENSION_ID]: crate::COMPRESSED_BLOB_EXTENSION_ID
+    #[cfg(feature = "flate2")]
+    pub fn compress_blobs_over(mut self, threshold: usize) -> Writer<T> {
+        self.encoder.compress_blobs_over = Some(threshold);
+        self
+    }
+
+    /// Enable or disable the canonicalization of the elements written by this writer.
+    ///
+    /// When enabled, integers are written using the narrowest element that can hold their
+    /// value, like [Document::canonicalize] does. The items of [collections][Element::Collection]
+    /// are written as-is, since narrowing them independently would make collections
+    /// heterogeneous. Combined with
+    /// [write_document_sorted][Self::write_document_sorted], this makes documents holding the
+    /// same values be written as the exact same bytes.
+    pub fn canonicalize(mut self, canonicalize: bool) -> Writer<T> {
+        self.encoder.canonicalize = canonicalize;
+        self
+    }
+
+    /// Enable or disable the strict structure mode for this writer.
+    ///
+    /// When enabled, the first element written must be a [marker][Element::Marker], otherwise an
+    /// [Error::MissingLeadingMarker] is returned. This makes sure that the data does not start
+    /// with an implicit entry, which readers would name `/`.
+    pub fn strict_structure(mut self, strict_structure: bool) -> Writer<T> {
+        self.strict_structure = strict_structure;
+        self
+    }
+
+    /// Enable or disable the rejection of non-finite floating-point numbers by this writer.
+    ///
+    /// When enabled, writing a [Float32][Element::Float32] or [Float64][Element::Float64] element
+    /// holding NaN or an infinity returns an [Error::NonFiniteFloat]. This is useful when the
+    /// data is meant to be converted to formats that cannot represent these values, such as JSON.
+    /// By default, every floating-point number is written as-is.
+    pub fn reject_non_finite_floats(mut self, reject_non_finite_floats: bool) -> Writer<T> {
+        self.encoder.reject_non_finite_floats = reject_non_finite_floats;
+        self
     }
 
     /// Write the given [Element] to this writer.
@@ -48,62 +341,176 @@ where
     /// Note that this is a low-level function and, as such, it makes it possible to write
     /// semantically invalid TPK data, especially while writing [marker elements][Element::Marker].
     pub fn write_element(&mut self, element: &Element) -> Result<()> {
-        self.write.write_all(&[element.get_type_byte()])?;
+        self.check_structure(element)?;
+        let checkpoint = self.encoder.checkpoint();
+        let result = if self.use_scratch_buffer {
+            self.scratch_buffer.clear();
+            self.encoder
+                .encode_top_level(element, &mut self.scratch_buffer)
+                .and_then(|_| Ok(self.write.write_all(&self.scratch_buffer)?))
+        } else {
+            self.encoder.encode_top_level(element, &mut self.write)
+        };
+        if result.is_err() {
+            // Nested elements are encoded before being written, so the blobs and strings they
+            // added to the tables may not have been written.
+            self.encoder.rollback(checkpoint);
+        }
+        result
+    }
 
-        match *element {
-            Element::Marker(ref val) => {
-                let size = val.len();
-                if size > 63 {
-                    let remaining_size = size >> 6;
-                    let dynsize = dyn_size(remaining_size);
-                    self.write.write_all(dynsize.as_slice())?;
-                }
-                self.write.write_all(val.as_bytes())?;
-            }
-            Element::Integer8(val) => {
-                self.write.write_all(&[val as u8])?;
-            }
-            Element::Integer16(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
-            }
-            Element::Integer32(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
-            }
-            Element::Integer64(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
-            }
-            Element::UInteger8(val) => {
-                self.write.write_all(&[val])?;
-            }
-            Element::UInteger16(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
-            }
-            Element::UInteger32(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
-            }
-            Element::UInteger64(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
+    /// Write the given value to this writer, converting it into an [Element] first.
+    ///
+    /// This is a shorthand for [write_element][Self::write_element], which makes it possible to
+    /// write primitive values directly, such as `writer.write(42u32)` or `writer.write("hello")`.
+    pub fn write<E: Into<Element>>(&mut self, value: E) -> Result<()> {
+        self.write_element(&value.into())
+    }
+
+    /// Write a string table holding the given strings to this writer.
+    ///
+    /// The string table is an entry named [`__strings__`][STRING_TABLE_NAME], holding a
+    /// [string element][Element::String] for each given string that is not in a previous table.
+    /// The strings of every table written are numbered from zero, in the order they are written,
+    /// and a writer [using a string table][Self::use_string_table] writes the strings identical
+    /// to them as [references][Element::StringRef] to these numbers. This makes it possible to
+    /// use a string table while streaming elements, by writing the table first.
+    pub fn write_string_table<S: AsRef<str>>(&mut self, strings: &[S]) -> Result<()> {
+        self.write_element(&Element::Marker(String::from(STRING_TABLE_NAME)))?;
+        for string in strings {
+            let string = string.as_ref();
+            if !self.encoder.strings.contains_key(string) {
+                self.write_element(&Element::String(string.to_owned()))?;
+                self.encoder.add_string(string);
             }
-            Element::Float32(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn check_structure(&mut self, element: &Element) -> Result<()> {
+        if !self.started && self.strict_structure && !matches!(*element, Element::Marker(_)) {
+            return Err(Error::MissingLeadingMarker);
+        }
+        let typed_collection = match *element {
+            Element::Marker(_)
+            | Element::Folder
+            | Element::Collection
+            | Element::TypedCollection { .. }
+                if matches!(self.typed_collection, Some((_, 1..))) =>
+            {
+                // The typed collection would end before all its elements are written.
+                return Err(Error::InvalidTypedCollection);
             }
-            Element::Float64(val) => {
-                self.write.write_all(&val.to_le_bytes())?;
+            Element::Marker(_) | Element::Folder | Element::Collection => None,
+            Element::TypedCollection { type_byte, .. } if !is_number_type_byte(type_byte) => {
+                return Err(Error::InvalidTypedCollection);
             }
-            Element::String(ref val) => {
-                let bytes = val.as_bytes();
-                self.write.write_all(&static_size(bytes.len()))?;
-                self.write.write_all(bytes)?;
+            Element::TypedCollection { type_byte, count } => Some((type_byte, count)),
+            _ => match self.typed_collection {
+                Some((type_byte, remaining))
+                    if remaining > 0 && element.get_type_byte() == type_byte =>
+                {
+                    Some((type_byte, remaining - 1))
+                }
+                Some(_) => return Err(Error::InvalidTypedCollection),
+                None => None,
+            },
+        };
+        self.typed_collection = typed_collection;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Write the given items to this writer as a TPK collection.
+    ///
+    /// This function writes a [collection element][Element::Collection], followed by every item
+    /// converted into an [Element]. Since every item has the same Rust type, they are all
+    /// converted into the same kind of element, which makes the collection homogeneous by
+    /// construction.
+    pub fn write_collection<I>(&mut self, items: &[I]) -> Result<()>
+    where
+        I: Into<Element> + Copy,
+    {
+        self.write_element(&Element::Collection)?;
+        for item in items {
+            self.write_element(&(*item).into())?;
+        }
+        Ok(())
+    }
+
+    /// Write the items of the given iterator to this writer as a TPK collection.
+    ///
+    /// This function writes a [collection element][Element::Collection], followed by every item
+    /// as it is yielded, which makes it possible to stream collections whose length is not known
+    /// upfront without buffering them. Every item must be written as the same type of element as
+    /// the first one, otherwise an [Error::HeterogeneousCollection] is returned. Blobs and strings
+    /// written as references, and compressed blobs, count as the elements they stand for. Items
+    /// that are markers or containers are refused with an [Error::InvalidCollectionItem].
+    ///
+    /// Every item is encoded in the scratch buffer of this writer before being written, whether
+    /// it is [enabled][Self::scratch_buffer] or not, so that items of another type are not
+    /// written.
+    pub fn write_collection_from<I>(&mut self, items: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Element>,
+    {
+        self.write_element(&Element::Collection)?;
+        let mut collection_type = None;
+        for (index, item) in items.into_iter().enumerate() {
+            if let Element::Marker(_)
+            | Element::Folder
+            | Element::Collection
+            | Element::TypedCollection { .. } = item
+            {
+                return Err(Error::InvalidCollectionItem { index });
             }
-            Element::Blob(ref val) => {
-                self.write.write_all(&static_size(val.len()))?;
-                self.write.write_all(val.as_slice())?;
+            let checkpoint = self.encoder.checkpoint();
+            self.scratch_buffer.clear();
+            let result = self
+                .encoder
+                .encode_top_level(&item, &mut self.scratch_buffer)
+                .and_then(|_| {
+                    let written_type = encoded_type(&self.scratch_buffer);
+                    if *collection_type.get_or_insert(written_type) != written_type {
+                        return Err(Error::HeterogeneousCollection { index });
+                    }
+                    Ok(self.write.write_all(&self.scratch_buffer)?)
+                });
+            if result.is_err() {
+                self.encoder.rollback(checkpoint);
             }
-            _ => (),
-        };
+            result?;
+        }
         Ok(())
     }
 
+    /// Write the header of a [typed collection][Element::TypedCollection] to this writer.
+    ///
+    /// The header announces `count` elements with the given type byte, which must be written
+    /// right after it, for example using [write_element][Self::write_element]. Readers get the
+    /// header back using [Reader::read_typed_collection][crate::Reader::read_typed_collection].
+    ///
+    /// The type byte must be the one of a number element, and no marker or container can be
+    /// written until `count` elements of that type have been, otherwise an
+    /// [Error::InvalidTypedCollection] is returned.
+    pub fn write_typed_collection(&mut self, type_byte: u8, count: u32) -> Result<()> {
+        self.write_element(&Element::TypedCollection { type_byte, count })
+    }
+
+    /// Write the given [RawElement] to this writer.
+    ///
+    /// If the element holds raw bytes, these bytes are written verbatim. Otherwise, this function
+    /// behaves like [write_element][Self::write_element].
+    pub fn write_raw_element(&mut self, element: &RawElement) -> Result<()> {
+        match element.raw {
+            Some(ref raw) => {
+                self.check_structure(&element.element)?;
+                Ok(self.write.write_all(raw)?)
+            }
+            None => self.write_element(&element.element),
+        }
+    }
+
     /// Write the given [Entry] to this writer.
     ///
     /// This function will write the binary representation of this entry, by writing a
@@ -120,34 +527,597 @@ where
         }
         Ok(())
     }
+
+    /// Write the given [Document] to this writer.
+    ///
+    /// This function will write every entry of the document, in order, as
+    /// [write_entry][Self::write_entry] would.
+    ///
+    /// If this writer [uses a string table][Self::use_string_table], the document is preceded by
+    /// a [string table][Self::write_string_table] holding the strings it repeats, if any.
+    ///
+    /// Note that this function does not flush the underlying writer: this is left to the caller.
+    pub fn write_document(&mut self, document: &Document) -> Result<()> {
+        let strings = self.encoder.repeated_strings(&document.entries);
+        if !strings.is_empty() {
+            self.write_string_table(&strings)?;
+        }
+        for entry in &document.entries {
+            self.write_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Write the given [Document] to this writer, with its entries sorted by name.
+    ///
+    /// This function behaves like [write_document][Self::write_document], except that entries
+    /// are written in the lexicographic order of their names. Entries with the same name are
+    /// written in the order they appear in the document. This makes the output independent from
+    /// the order in which the entries were inserted, which is useful for reproducible artifacts.
+    pub fn write_document_sorted(&mut self, document: &Document) -> Result<()> {
+        let mut entries: Vec<&Entry> = document.entries.iter().collect();
+        entries.sort_by(|entry, other| entry.name.cmp(&other.name));
+        let strings = self.encoder.repeated_strings(entries.iter().copied());
+        if !strings.is_empty() {
+            self.write_string_table(&strings)?;
+        }
+        for entry in entries {
+            self.write_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Write the given [Document] to this writer as a length-delimited frame.
+    ///
+    /// This function writes the encoded length of the document as a little-endian `u32`,
+    /// followed by the document itself, as [write_document][Self::write_document] would, so that
+    /// it can be read in one go by a [FramedReader][crate::FramedReader]. The document is
+    /// encoded in the scratch buffer of this writer before being written, whether it is
+    /// [enabled][Self::scratch_buffer] or not, so that its length accounts for every rewrite.
+    ///
+    /// If the document fails to be encoded, or is larger than 4 GiB (in which case an
+    /// [Error::FrameTooLarge] is returned), nothing is written.
+    pub fn write_framed_document(&mut self, document: &Document) -> Result<()> {
+        let strings = self.encoder.repeated_strings(&document.entries);
+        let checkpoint = self.encoder.checkpoint();
+        let state = (
+            self.started,
+            self.typed_collection,
+            self.encoder.in_collection,
+        );
+        let mut frame = mem::take(&mut self.scratch_buffer);
+        frame.clear();
+        let result = self
+            .encode_document(&strings, document, &mut frame)
+            .and_then(|_| {
+                let len = frame.len();
+                let frame_len = u32::try_from(len).map_err(|_| Error::FrameTooLarge { len })?;
+                self.write.write_all(&frame_len.to_le_bytes())?;
+                Ok(self.write.write_all(&frame)?)
+            });
+        self.scratch_buffer = frame;
+        if result.is_err() {
+            // Nothing has been written, so the writer is left as it was before the call.
+            self.encoder.rollback(checkpoint);
+            (
+                self.started,
+                self.typed_collection,
+                self.encoder.in_collection,
+            ) = state;
+        }
+        result
+    }
+
+    /// Encode the given [Document] to the given buffer, preceded by a string table holding the
+    /// given strings if there are any, as [write_document][Self::write_document] would write it.
+    fn encode_document(
+        &mut self,
+        strings: &[&str],
+        document: &Document,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        if !strings.is_empty() {
+            self.encode_checked(&Element::Marker(String::from(STRING_TABLE_NAME)), out)?;
+            for string in strings {
+                self.encode_checked(&Element::String(string.to_string()), out)?;
+                self.encoder.add_string(string);
+            }
+        }
+        for entry in &document.entries {
+            self.encode_checked(&Element::Marker(entry.name.clone()), out)?;
+            for element in &entry.elements {
+                self.encode_checked(element, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode the given [Element] to the given buffer, as [write_element][Self::write_element]
+    /// would write it.
+    fn encode_checked(&mut self, element: &Element, out: &mut Vec<u8>) -> Result<()> {
+        self.check_structure(element)?;
+        self.encoder.encode_top_level(element, out)
+    }
+
+    /// Write a footer indexing the entries written to this writer.
+    ///
+    /// The footer is an entry named [`__footer__`][FOOTER_NAME], holding a
+    /// [string element][Element::String] and a [64-bit unsigned integer][Element::UInteger64]
+    /// for each name and offset of the given index, followed by a
+    /// [32-bit unsigned integer][Element::UInteger32] holding the total length of the footer
+    /// entry in bytes. Since that last element always occupies the last 5 bytes of the data, the
+    /// footer can be [read from the end][crate::Reader::read_footer] without scanning the
+    /// entries it indexes.
+    ///
+    /// The footer should be the last thing written. Note that it is a regular entry, and will be
+    /// read as such by readers unaware of it. It is always written as-is, without
+    /// canonicalization, deduplication, string table or string encoding, so that it can be read
+    /// back without knowing the configuration of this writer.
+    pub fn write_footer(&mut self, index: &[(String, u64)]) -> Result<()> {
+        let marker = Element::Marker(String::from(FOOTER_NAME));
+        self.check_structure(&marker)?;
+        let mut footer = Vec::new();
+        encode_element(&marker, &mut footer);
+        for (name, offset) in index {
+            encode_element(&Element::String(name.clone()), &mut footer);
+            encode_element(&Element::UInteger64(*offset), &mut footer);
+        }
+        let len = footer.len() + Element::UInteger32(0).encoded_len();
+        let footer_len = u32::try_from(len).map_err(|_| Error::TooLarge { len })?;
+        encode_element(&Element::UInteger32(footer_len), &mut footer);
+        self.write.write_all(&footer)?;
+        Ok(())
+    }
 }
 
-fn static_size(size: usize) -> Vec<u8> {
-    match size {
-        0..=255 => Vec::from([size as u8]),
-        256..=65535 => Vec::from((size as u16).to_le_bytes()),
-        65536..=4294967295 => Vec::from((size as u32).to_le_bytes()),
-        _ => Vec::from((size as u64).to_le_bytes()),
+impl Encoder {
+    /// Encode the given top-level [Element] to the given destination, keeping track of whether
+    /// it belongs to a collection.
+    fn encode_top_level<W: io::Write>(&mut self, element: &Element, out: &mut W) -> Result<()> {
+        match *element {
+            Element::Marker(_) | Element::Folder => self.in_collection = false,
+            Element::Collection | Element::TypedCollection { .. } => self.in_collection = true,
+            _ => (),
+        }
+        self.encode(element, out)
+    }
+
+    /// Encode the given [Element] to the given destination, rewriting it as configured.
+    fn encode<W: io::Write>(&mut self, element: &Element, out: &mut W) -> Result<()> {
+        if self.canonicalize && !self.in_collection {
+            if let Some(narrowed) = element.narrowed() {
+                return self.encode(&narrowed, out);
+            }
+        }
+        match *element {
+            Element::Blob(ref data) if self.dedup_blobs => match self.blobs.get(data) {
+                Some(&index) => self.encode_single(&Element::BlobRef(index), out),
+                None => {
+                    self.encode_single(element, out)?;
+                    // Blobs past the last referenceable index are still written, just never
+                    // deduplicated.
+                    if let Ok(index) = u32::try_from(self.blobs.len()) {
+                        self.blobs.insert(data.clone(), index);
+                    }
+                    Ok(())
+                }
+            },
+            Element::String(ref val) if self.use_string_table => match self.strings.get(val) {
+                Some(&index) => self.encode_single(&Element::StringRef(index), out),
+                None => self.encode_single(element, out),
+            },
+            _ => self.encode_single(element, out),
+        }
+    }
+
+    fn encode_single<W: io::Write>(&mut self, element: &Element, out: &mut W) -> Result<()> {
+        if element.version() > self.version {
+            return Err(Error::TypeNotInVersion {
+                byte: element.get_type_byte(),
+                version: self.version,
+            });
+        }
+        if let Element::CString(ref val) = *element {
+            if let Some(pos) = val.bytes().position(|byte| byte == 0) {
+                return Err(Error::InteriorNul { pos });
+            }
+        }
+        if self.reject_non_finite_floats {
+            let value = match *element {
+                Element::Float32(val) => val as f64,
+                Element::Float64(val) => val,
+                _ => 0.0,
+            };
+            if !value.is_finite() {
+                return Err(Error::NonFiniteFloat { value });
+            }
+        }
+        if let Element::Flagged { flags, ref inner } = *element {
+            if let Element::Marker(_) = **inner {
+                return Err(Error::NestedMarker);
+            }
+            // The inner element is encoded on its own, so that blobs are deduplicated.
+            let mut data = vec![flags];
+            self.encode(inner, &mut data)?;
+            return write_encoded(
+                &Element::Extension {
+                    id: FLAGGED_EXTENSION_ID,
+                    data,
+                },
+                out,
+            );
+        }
+        if let Element::Map(ref pairs) = *element {
+            let count =
+                u32::try_from(pairs.len()).map_err(|_| Error::TooLarge { len: pairs.len() })?;
+            let mut data = count.to_le_bytes().to_vec();
+            // Keys are never replaced by references, so that maps can be read by any reader, but
+            // values are encoded on their own, so that blobs and strings are deduplicated.
+            for (key, value) in pairs {
+                if let Element::Marker(_) = *value {
+                    return Err(Error::NestedMarker);
+                }
+                self.encode_single(&Element::String(key.clone()), &mut data)?;
+                self.encode(value, &mut data)?;
+            }
+            return write_encoded(
+                &Element::Extension {
+                    id: MAP_EXTENSION_ID,
+                    data,
+                },
+                out,
+            );
+        }
+        #[cfg(feature = "flate2")]
+        if let Element::Blob(ref data) = *element {
+            if let Some(data) = self.compress_blob(data) {
+                return self.encode_single(
+                    &Element::Extension {
+                        id: COMPRESSED_BLOB_EXTENSION_ID,
+                        data,
+                    },
+                    out,
+                );
+            }
+        }
+
+        #[cfg(feature = "encoding_rs")]
+        if self.string_encoding != encoding_rs::UTF_8 {
+            if let Some(bytes) = self.encode_string_element(element)? {
+                out.write_all(&bytes)?;
+                return Ok(());
+            }
+        }
+
+        write_encoded(element, out)
+    }
+
+    /// Encode the given marker or string element using the string encoding of this writer, or
+    /// return `None` for any other element.
+    #[cfg(feature = "encoding_rs")]
+    fn encode_string_element(&self, element: &Element) -> Result<Option<Vec<u8>>> {
+        let val = match *element {
+            Element::Marker(ref val) | Element::String(ref val) | Element::CString(ref val) => val,
+            _ => return Ok(None),
+        };
+        let (bytes, encoding, had_errors) = self.string_encoding.encode(val);
+        if had_errors {
+            return Err(Error::UnmappableCharacter {
+                encoding: encoding.name(),
+            });
+        }
+
+        let size = bytes.len();
+        let mut out = Vec::with_capacity(size + 9);
+        match *element {
+            Element::Marker(_) => {
+                let more = if size > 63 { 0b01000000u8 } else { 0u8 };
+                out.push(0b10000000u8 | more | (size & 0b00111111) as u8);
+                if size > 63 {
+                    push_dyn_size(size >> 6, &mut out);
+                }
+                out.extend_from_slice(&bytes);
+            }
+            Element::String(_) => {
+                out.push(0b00010000u8 | size_byte(size));
+                push_static_size(size, &mut out);
+                out.extend_from_slice(&bytes);
+            }
+            _ => {
+                if let Some(pos) = bytes.iter().position(|&byte| byte == 0) {
+                    return Err(Error::InteriorNul { pos });
+                }
+                out.push(0b00011000u8);
+                out.extend_from_slice(&bytes);
+                out.push(0u8);
+            }
+        }
+        Ok(Some(out))
+    }
+
+    /// Compress the given blob data, if compression is enabled, the blob is over the threshold
+    /// and it shrinks once compressed.
+    #[cfg(feature = "flate2")]
+    fn compress_blob(&self, data: &[u8]) -> Option<Vec<u8>> {
+        use std::io::Write;
+
+        if data.len() <= self.compress_blobs_over? {
+            return None;
+        }
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        // Writing to a vector cannot fail.
+        encoder.write_all(data).ok()?;
+        let compressed = encoder.finish().ok()?;
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+
+    /// Add the given string to the string table.
+    fn add_string(&mut self, string: &str) {
+        // Strings past the last referenceable index are still written, just never referenced.
+        if let Ok(index) = u32::try_from(self.strings.len()) {
+            self.strings.insert(string.to_owned(), index);
+        }
+    }
+
+    /// Get the strings repeated in the given entries that a string table should hold, in the
+    /// order they first appear.
+    ///
+    /// Only the strings that are not in the table yet, and are longer than the references to
+    /// them, are returned. Nothing is returned if the string table is disabled.
+    fn repeated_strings<'a, I>(&self, entries: I) -> Vec<&'a str>
+    where
+        I: IntoIterator<Item = &'a Entry>,
+    {
+        fn count<'a>(
+            element: &'a Element,
+            counts: &mut HashMap<&'a str, usize>,
+            order: &mut Vec<&'a str>,
+        ) {
+            match *element {
+                Element::String(ref val) => {
+                    let count = counts.entry(val.as_str()).or_insert(0);
+                    if *count == 0 {
+                        order.push(val.as_str());
+                    }
+                    *count += 1;
+                }
+                Element::Flagged { ref inner, .. } => count(inner, counts, order),
+                Element::Map(ref pairs) => {
+                    for (_, value) in pairs {
+                        count(value, counts, order);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if !self.use_string_table {
+            return Vec::new();
+        }
+        let mut counts = HashMap::new();
+        let mut order = Vec::new();
+        for entry in entries {
+            for element in &entry.elements {
+                count(element, &mut counts, &mut order);
+            }
+        }
+        let reference_len = Element::StringRef(0).encoded_len();
+        order.retain(|string| {
+            counts[string] > 1
+                && !self.strings.contains_key(*string)
+                && Element::String(string.to_string()).encoded_len() > reference_len
+        });
+        order
+    }
+
+    /// Get the current length of the tables, to [roll back][Self::rollback] to later on.
+    fn checkpoint(&self) -> (usize, usize) {
+        (self.blobs.len(), self.strings.len())
+    }
+
+    /// Forget the blobs and strings added to the tables since the given checkpoint.
+    fn rollback(&mut self, (blobs, strings): (usize, usize)) {
+        self.blobs.retain(|_, &mut index| (index as usize) < blobs);
+        self.strings
+            .retain(|_, &mut index| (index as usize) < strings);
     }
 }
 
-fn dyn_size(size: usize) -> Vec<u8> {
-    if size == 0 {
-        return Vec::from([0u8]);
+/// Get the type of the element encoded at the start of the given bytes, as read back by a
+/// [reader][crate::Reader] configured like the writer that encoded it.
+///
+/// Size classes are ignored, extensions are told apart by their identifiers, and references and
+/// compressed blobs have the type of the elements they stand for.
+fn encoded_type(encoded: &[u8]) -> (u8, Option<u8>) {
+    match encoded[0] {
+        0b00010000..=0b00010011 => (0b00010000, None),
+        0b00010100..=0b00010111 => (0b00010100, None),
+        0b00011100..=0b00011111 => (0b00011100, None),
+        0b00110000 | 0b00110001 => (0b00110000, None),
+        0b01110000..=0b01110011 => match encoded[1] {
+            BLOB_REF_EXTENSION_ID | COMPRESSED_BLOB_EXTENSION_ID => (0b00010100, None),
+            STRING_REF_EXTENSION_ID => (0b00010000, None),
+            id => (0b01110000, Some(id)),
+        },
+        type_byte => (type_byte, None),
+    }
+}
+
+/// A TPK writer structure for append-only logs.
+///
+/// Every [entry][Entry] appended to this writer is written as an independent record, made of the
+/// [record separator][LOG_RECORD_MAGIC], the encoded length of the entry as a little-endian
+/// `u32`, and the entry itself. Records do not depend on each other, which makes it possible for
+/// a [LogReader][crate::LogReader] to recover the records following a corrupt one, such as a
+/// record partially written before a crash.
+pub struct LogWriter<T> {
+    write: T,
+}
+
+impl<T> LogWriter<T>
+where
+    T: io::Write,
+{
+    /// Create a new [TPK log writer][LogWriter].
+    pub fn new(write: T) -> LogWriter<T> {
+        LogWriter { write }
+    }
+
+    /// Append the given [Entry] to this log as a new record.
+    ///
+    /// If the entry fails to be encoded, or is larger than 4 GiB (in which case an
+    /// [Error::FrameTooLarge] is returned), nothing is written.
+    pub fn append(&mut self, entry: &Entry) -> Result<()> {
+        // A fresh writer is used for every record, so that records never reference each other.
+        // The entry is encoded before anything is written, so that an entry failing to be
+        // encoded does not leave a partial record behind.
+        let mut record = Vec::new();
+        Writer::new(&mut record).write_entry(entry)?;
+        let len = record.len();
+        let record_len = u32::try_from(len).map_err(|_| Error::FrameTooLarge { len })?;
+        self.write.write_all(&LOG_RECORD_MAGIC)?;
+        self.write.write_all(&record_len.to_le_bytes())?;
+        Ok(self.write.write_all(&record)?)
     }
 
-    let fls = if size == 0 {
-        0u32
-    } else {
-        mem::size_of::<usize>() as u32 * 8u32 - size.leading_zeros()
-    };
+    /// Get the destination of this writer back.
+    pub fn into_inner(self) -> T {
+        self.write
+    }
+}
+
+/// Append the binary representation of the given [Element] to a buffer.
+///
+/// This produces exactly the same bytes as [Writer::write_element].
+pub(crate) fn encode_element(element: &Element, out: &mut Vec<u8>) {
+    let data = encode_head(element, out);
+    out.extend_from_slice(data);
+}
+
+/// Write the binary representation of the given [Element] to the given destination.
+fn write_encoded<W: io::Write>(element: &Element, out: &mut W) -> Result<()> {
+    let mut head = Vec::new();
+    let data = encode_head(element, &mut head);
+    out.write_all(&head)?;
+    out.write_all(data)?;
+    Ok(())
+}
+
+/// Append the binary representation of the given [Element] to a buffer, except for the data of
+/// strings, blobs, markers and extensions, which is returned instead.
+///
+/// This makes it possible to write large data straight from the element, without copying it.
+fn encode_head<'a>(element: &'a Element, out: &mut Vec<u8>) -> &'a [u8] {
+    out.push(element.get_type_byte());
+
+    match *element {
+        Element::Marker(ref val) => {
+            let size = val.len();
+            if size > 63 {
+                push_dyn_size(size >> 6, out);
+            }
+            return val.as_bytes();
+        }
+        Element::Folder | Element::Collection | Element::Boolean(_) => (),
+        Element::Integer8(val) => out.push(val as u8),
+        Element::Integer16(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::Integer32(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::Integer64(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::Integer128(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::UInteger8(val) => out.push(val),
+        Element::UInteger16(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::UInteger32(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::UInteger64(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::UInteger128(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::Float32(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::Float64(val) => out.extend_from_slice(&val.to_le_bytes()),
+        Element::String(ref val) => {
+            push_static_size(val.len(), out);
+            return val.as_bytes();
+        }
+        Element::Blob(ref val) => {
+            push_static_size(val.len(), out);
+            return val;
+        }
+        Element::CString(ref val) => {
+            out.extend_from_slice(val.as_bytes());
+            out.push(0u8);
+        }
+        Element::Extension { id, ref data } => {
+            out.push(id);
+            push_static_size(data.len(), out);
+            return data;
+        }
+        Element::DeltaU64(ref values) => {
+            let mut data = Vec::new();
+            encode_delta_u64(values, &mut data);
+            out.push(DELTA_U64_EXTENSION_ID);
+            push_static_size(data.len(), out);
+            out.extend_from_slice(&data);
+        }
+        Element::BlobRef(index) => {
+            out.extend_from_slice(&[BLOB_REF_EXTENSION_ID, 4u8]);
+            out.extend_from_slice(&index.to_le_bytes());
+        }
+        Element::StringRef(index) => {
+            out.extend_from_slice(&[STRING_REF_EXTENSION_ID, 4u8]);
+            out.extend_from_slice(&index.to_le_bytes());
+        }
+        Element::Duration(val) => {
+            out.extend_from_slice(&[DURATION_EXTENSION_ID, 8u8]);
+            out.extend_from_slice(&val.to_le_bytes());
+        }
+        Element::Flagged { flags, ref inner } => {
+            let mut data = vec![flags];
+            encode_element(inner, &mut data);
+            out.push(FLAGGED_EXTENSION_ID);
+            push_static_size(data.len(), out);
+            out.extend_from_slice(&data);
+        }
+        Element::Map(ref pairs) => {
+            let mut data = (pairs.len() as u32).to_le_bytes().to_vec();
+            for (key, value) in pairs {
+                data.push(0b00010000u8 | size_byte(key.len()));
+                push_static_size(key.len(), &mut data);
+                data.extend_from_slice(key.as_bytes());
+                encode_element(value, &mut data);
+            }
+            out.push(MAP_EXTENSION_ID);
+            push_static_size(data.len(), out);
+            out.extend_from_slice(&data);
+        }
+        Element::TypedCollection { type_byte, count } => {
+            out.extend_from_slice(&[TYPED_COLLECTION_EXTENSION_ID, 5u8, type_byte]);
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+        Element::Padding(len) => {
+            push_static_size(len, out);
+            out.resize(out.len() + len, 0u8);
+        }
+    }
+    &[]
+}
+
+pub(crate) fn push_static_size(size: usize, out: &mut Vec<u8>) {
+    // The width is always the one announced by the type byte, whatever the width of `usize`.
+    match size_byte(size) {
+        0b00 => out.push(size as u8),
+        0b01 => out.extend_from_slice(&(size as u16).to_le_bytes()),
+        0b10 => out.extend_from_slice(&(size as u32).to_le_bytes()),
+        _ => out.extend_from_slice(&(size as u64).to_le_bytes()),
+    }
+}
 
-    let mut ret = Vec::with_capacity((fls / 7) as usize);
+pub(crate) fn push_dyn_size(size: usize, out: &mut Vec<u8>) {
+    if size == 0 {
+        out.push(0u8);
+        return;
+    }
 
     let mut size = size;
     while size > 0 {
-        ret.push((size as u8 & 0x7F) | if size > 0x7F { 0b10000000u8 } else { 0u8 });
+        out.push((size as u8 & 0x7F) | if size > 0x7F { 0b10000000u8 } else { 0u8 });
         size >>= 7;
     }
-    ret
 }
@@ -0,0 +1,69 @@
+//! Tests asserting the memory allocation behavior of the reader.
+//!
+//! This test binary uses a global allocator recording the largest allocation made by the current
+//! thread, so that tests can check that large payloads are never allocated.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+use tpk::{Element, Entry, Reader, Writer};
+
+struct TrackingAllocator;
+
+thread_local! {
+    static LARGEST_ALLOCATION: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST_ALLOCATION.with(|largest| largest.set(largest.get().max(layout.size())));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+/// Run the given function, and return the size of the largest allocation it made.
+fn largest_allocation_during<F: FnOnce()>(f: F) -> usize {
+    LARGEST_ALLOCATION.with(|largest| largest.set(0));
+    f();
+    LARGEST_ALLOCATION.with(|largest| largest.get())
+}
+
+const LARGE_BLOB_SIZE: usize = 4 * 1024 * 1024;
+
+fn entries_with_large_blob() -> Vec<u8> {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for (name, size) in [("first", LARGE_BLOB_SIZE), ("second", 16), ("third", 16)] {
+        writer
+            .write_entry(&Entry {
+                name: name.into(),
+                elements: vec![Element::Blob(vec![42u8; size])],
+            })
+            .unwrap();
+    }
+    output
+}
+
+#[test]
+fn test_nth_entry_does_not_allocate_skipped_blobs() {
+    let input = entries_with_large_blob();
+    let mut reader = Reader::new(Cursor::new(input.as_slice()));
+
+    let mut entry = None;
+    let largest_allocation = largest_allocation_during(|| {
+        entry = reader.nth_entry(1).unwrap();
+    });
+
+    assert!(largest_allocation < LARGE_BLOB_SIZE);
+    let entry = entry.unwrap();
+    assert_eq!(entry.name, "second");
+    assert_eq!(entry.elements, vec![Element::Blob(vec![42u8; 16])]);
+    assert_eq!(reader.read_entry().unwrap().unwrap().name, "third");
+}
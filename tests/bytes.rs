@@ -0,0 +1,112 @@
+#![cfg(feature = "bytes")]
+
+use bytes::Bytes;
+use std::io::Cursor;
+use tpk::read::Error;
+use tpk::{Element, ElementBytes, Entry, Reader, Writer};
+
+fn thumbnail_frame() -> Bytes {
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_entry(&Entry {
+            name: String::from("thumbnail"),
+            elements: vec![Element::Blob(vec![42u8; 500]), Element::Boolean(true)],
+        })
+        .unwrap();
+    Bytes::from(output)
+}
+
+#[test]
+fn test_read_element_bytes_shares_buffer() {
+    let frame = thumbnail_frame();
+    let frame_range = frame.as_ptr_range();
+
+    let mut reader = Reader::new(Cursor::new(frame.clone()));
+    assert_eq!(
+        reader.read_element_bytes().unwrap(),
+        Some(ElementBytes::Other(Element::Marker(String::from(
+            "thumbnail"
+        ))))
+    );
+    let blob = match reader.read_element_bytes().unwrap() {
+        Some(ElementBytes::Blob(blob)) => blob,
+        element => panic!("unexpected element: {:?}", element),
+    };
+    assert_eq!(blob, vec![42u8; 500]);
+    assert!(frame_range.contains(&blob.as_ptr()));
+    assert_eq!(blob.as_ptr(), frame[13..].as_ptr());
+
+    assert_eq!(
+        reader.read_element_bytes().unwrap(),
+        Some(ElementBytes::Other(Element::Boolean(true)))
+    );
+    assert_eq!(reader.read_element_bytes().unwrap(), None);
+}
+
+#[test]
+fn test_read_element_bytes_with_truncated_blob() {
+    let frame = thumbnail_frame().slice(..100);
+
+    let mut reader = Reader::new(Cursor::new(frame));
+    reader.read_element_bytes().unwrap();
+    assert!(matches!(
+        reader.read_element_bytes(),
+        Err(Error::Syntax(100, _))
+    ));
+}
+
+#[test]
+fn test_element_from_bytes() {
+    let element = Element::from(Bytes::from_static(b"tpk"));
+    assert_eq!(element, Element::Blob(b"tpk".to_vec()));
+    assert_eq!(ElementBytes::from(element.clone()).into_element(), element);
+}
+
+#[test]
+fn test_element_clone_shared() {
+    let element = Element::Blob(vec![42u8; 500]);
+    let shared = element.clone_shared();
+    let other = shared.clone();
+    match (&shared, &other) {
+        (ElementBytes::Blob(blob), ElementBytes::Blob(other_blob)) => {
+            assert_eq!(blob.as_ptr(), other_blob.as_ptr());
+            assert_ne!(blob.as_ptr(), element_blob_ptr(&element));
+        }
+        elements => panic!("unexpected elements: {:?}", elements),
+    }
+    assert_eq!(other.into_element(), element);
+
+    assert_eq!(
+        Element::Boolean(true).clone_shared(),
+        ElementBytes::Other(Element::Boolean(true))
+    );
+}
+
+#[test]
+fn test_element_bytes_shared() {
+    let element = Element::Blob(vec![42u8; 500]);
+    let element_ptr = element_blob_ptr(&element);
+    let shared = ElementBytes::from(element.clone());
+    let other = shared.clone();
+    match (&shared, &other) {
+        (ElementBytes::Blob(blob), ElementBytes::Blob(other_blob)) => {
+            assert_eq!(blob.as_ptr(), other_blob.as_ptr());
+            assert_ne!(blob.as_ptr(), element_ptr);
+        }
+        elements => panic!("unexpected elements: {:?}", elements),
+    }
+    assert_eq!(other.into_element(), element);
+
+    // Converting an element moves its blob instead of copying it.
+    match ElementBytes::from(element) {
+        ElementBytes::Blob(blob) => assert_eq!(blob.as_ptr(), element_ptr),
+        element => panic!("unexpected element: {:?}", element),
+    }
+}
+
+fn element_blob_ptr(element: &Element) -> *const u8 {
+    match element {
+        Element::Blob(val) => val.as_ptr(),
+        _ => std::ptr::null(),
+    }
+}
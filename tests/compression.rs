@@ -0,0 +1,127 @@
+#![cfg(feature = "flate2")]
+
+use std::io::Cursor;
+use tpk::read::Error;
+use tpk::{Document, Element, Entry, Reader, Writer, COMPRESSED_BLOB_EXTENSION_ID};
+
+fn thumbnail_entry() -> Entry {
+    Entry {
+        name: String::from("thumbnail"),
+        elements: vec![Element::Blob(vec![42u8; 10 * 1024])],
+    }
+}
+
+#[test]
+fn test_compressed_blob_round_trip() {
+    let entry = thumbnail_entry();
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .compress_blobs_over(1024)
+        .write_entry(&entry)
+        .unwrap();
+    assert!(output.len() < entry.encoded_len() / 10);
+    assert_eq!(output[10], 0b01110000u8);
+    assert_eq!(output[11], COMPRESSED_BLOB_EXTENSION_ID);
+
+    let mut reader = Reader::new(Cursor::new(&output)).decompress_blobs_up_to(10 * 1024);
+    assert_eq!(reader.read_entry().unwrap(), Some(entry));
+    assert_eq!(reader.read_entry().unwrap(), None);
+
+    // Compressed blobs are left as-is unless decompression is enabled.
+    let mut reader = Reader::new(Cursor::new(&output));
+    assert!(matches!(
+        reader.read_entry().unwrap().unwrap().elements[..],
+        [Element::Extension {
+            id: COMPRESSED_BLOB_EXTENSION_ID,
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_compressed_blob_over_limit() {
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .compress_blobs_over(1024)
+        .write_entry(&thumbnail_entry())
+        .unwrap();
+
+    let mut reader = Reader::new(Cursor::new(&output)).decompress_blobs_up_to(10 * 1024 - 1);
+    assert!(matches!(
+        reader.read_entry(),
+        Err(Error::BlobTooLarge {
+            pos: 10,
+            limit: 10239
+        })
+    ));
+
+    let mut reader = Reader::new(Cursor::new(&output)).decompress_blobs_up_to(1024);
+    assert!(matches!(
+        reader.estimate_parsed_size(),
+        Err(Error::BlobTooLarge {
+            pos: 10,
+            limit: 1024
+        })
+    ));
+}
+
+#[test]
+fn test_compressed_blob_read_into() {
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .compress_blobs_over(1024)
+        .write_entry(&thumbnail_entry())
+        .unwrap();
+    let mut buf = vec![0u8; 10 * 1024];
+
+    let mut reader = Reader::new(Cursor::new(&output)).decompress_blobs_up_to(10 * 1024);
+    reader.read_element().unwrap();
+    assert_eq!(reader.read_blob_into(&mut buf).unwrap(), 10 * 1024);
+    assert!(buf.iter().all(|&byte| byte == 42));
+
+    let mut reader = Reader::new(Cursor::new(&output));
+    reader.read_element().unwrap();
+    assert!(matches!(
+        reader.read_blob_into(&mut buf),
+        Err(Error::UnexpectedType {
+            pos: 10,
+            byte: 0b01110000
+        })
+    ));
+}
+
+#[test]
+fn test_compressed_blob_under_threshold() {
+    let element = Element::Blob(vec![42u8; 100]);
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .compress_blobs_over(1024)
+        .write_element(&element)
+        .unwrap();
+    assert_eq!(output.len(), element.encoded_len());
+}
+
+#[test]
+fn test_compressed_blob_with_dedup_blobs() {
+    let document = Document {
+        entries: vec![thumbnail_entry(), thumbnail_entry()],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .compress_blobs_over(1024)
+        .dedup_blobs(true)
+        .write_framed_document(&document)
+        .unwrap();
+    assert_eq!(
+        u32::from_le_bytes(output[..4].try_into().unwrap()) as usize,
+        output.len() - 4
+    );
+
+    let mut reader = Reader::new(Cursor::new(&output[4..]))
+        .dedup_blobs(true)
+        .decompress_blobs_up_to(10 * 1024);
+    assert_eq!(reader.read_document().unwrap(), document);
+}
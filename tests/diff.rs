@@ -0,0 +1,88 @@
+use tpk::diff::{diff, Change};
+use tpk::{Document, Element, Entry};
+
+fn entry(name: &str, elements: Vec<Element>) -> Entry {
+    Entry {
+        name: name.into(),
+        elements,
+    }
+}
+
+fn sample_document() -> Document {
+    Document {
+        entries: vec![
+            entry("format", vec![Element::String("TPK".into())]),
+            entry("major", vec![Element::UInteger8(0)]),
+            entry("minor", vec![Element::UInteger8(1)]),
+        ],
+    }
+}
+
+#[test]
+fn test_diff_identical_documents() {
+    let old = sample_document();
+    let mut new = sample_document();
+    // Same value, different width: not a modification.
+    new.entries[2].elements[0] = Element::UInteger32(1);
+
+    assert!(diff(&old, &new).is_empty());
+}
+
+#[test]
+fn test_diff_added_entry() {
+    let old = sample_document();
+    let mut new = sample_document();
+    new.entries
+        .push(entry("patch", vec![Element::UInteger8(0)]));
+
+    assert_eq!(diff(&old, &new), vec![Change::Added("patch".into())]);
+}
+
+#[test]
+fn test_diff_removed_entry() {
+    let old = sample_document();
+    let mut new = sample_document();
+    new.entries.remove(0);
+
+    assert_eq!(diff(&old, &new), vec![Change::Removed("format".into())]);
+}
+
+#[test]
+fn test_diff_modified_entry() {
+    let old = sample_document();
+    let mut new = sample_document();
+    new.entries[1].elements[0] = Element::UInteger8(2);
+
+    assert_eq!(
+        diff(&old, &new),
+        vec![Change::Modified {
+            name: "major".into(),
+            old: vec![Element::UInteger8(0)],
+            new: vec![Element::UInteger8(2)],
+        }]
+    );
+}
+
+#[test]
+fn test_diff_duplicate_names() {
+    let mut old = sample_document();
+    old.entries
+        .push(entry("minor", vec![Element::UInteger8(2)]));
+    let mut new = sample_document();
+    new.entries
+        .push(entry("minor", vec![Element::UInteger8(3)]));
+    new.entries
+        .push(entry("minor", vec![Element::UInteger8(4)]));
+
+    assert_eq!(
+        diff(&old, &new),
+        vec![
+            Change::Modified {
+                name: "minor".into(),
+                old: vec![Element::UInteger8(2)],
+                new: vec![Element::UInteger8(3)],
+            },
+            Change::Added("minor".into()),
+        ]
+    );
+}
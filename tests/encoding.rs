@@ -0,0 +1,112 @@
+#![cfg(feature = "encoding_rs")]
+
+use encoding_rs::WINDOWS_1252;
+use std::io::Cursor;
+use tpk::read::Error;
+use tpk::{write, Document, Element, Entry, Reader, Writer};
+
+#[test]
+fn test_read_latin1_string() {
+    let input = vec![
+        0b10000001u8,
+        0xE9,
+        0b00010000u8,
+        4u8,
+        b'c',
+        b'a',
+        b'f',
+        0xE9,
+    ];
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice()));
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::InvalidString { pos: 1, .. })
+    ));
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice())).string_encoding(WINDOWS_1252);
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("é"),
+            elements: vec![Element::String(String::from("café"))],
+        })
+    );
+}
+
+#[test]
+fn test_write_latin1_string() {
+    let entry = Entry {
+        name: String::from("é"),
+        elements: vec![
+            Element::String(String::from("café")),
+            Element::CString(String::from("thé")),
+        ],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .string_encoding(WINDOWS_1252)
+        .write_entry(&entry)
+        .unwrap();
+    assert_eq!(
+        output,
+        vec![
+            0b10000001u8,
+            0xE9,
+            0b00010000u8,
+            4u8,
+            b'c',
+            b'a',
+            b'f',
+            0xE9,
+            0b00011000u8,
+            b't',
+            b'h',
+            0xE9,
+            0u8,
+        ]
+    );
+
+    let mut reader = Reader::new(Cursor::new(output)).string_encoding(WINDOWS_1252);
+    assert_eq!(reader.read_entry().unwrap(), Some(entry));
+}
+
+#[test]
+fn test_write_unmappable_character() {
+    let mut output = vec![];
+    let result = Writer::new(&mut output)
+        .string_encoding(WINDOWS_1252)
+        .write_element(&Element::String(String::from("日本")));
+    assert!(matches!(
+        result,
+        Err(write::Error::UnmappableCharacter {
+            encoding: "windows-1252"
+        })
+    ));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_write_latin1_framed_document() {
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("é"),
+            elements: vec![Element::String(String::from("café"))],
+        }],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .string_encoding(WINDOWS_1252)
+        .write_framed_document(&document)
+        .unwrap();
+    assert_eq!(
+        u32::from_le_bytes(output[..4].try_into().unwrap()) as usize,
+        output.len() - 4
+    );
+    assert_eq!(output.len() - 4, document.encoded_len() - 2);
+
+    let mut reader = Reader::new(Cursor::new(&output[4..])).string_encoding(WINDOWS_1252);
+    assert_eq!(reader.read_document().unwrap(), document);
+}
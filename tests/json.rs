@@ -0,0 +1,44 @@
+#![cfg(feature = "json")]
+
+use std::io::{self, Read};
+use tpk::json::JsonLinesReader;
+use tpk::{Element, Entry, Reader, Writer};
+
+#[test]
+fn test_json_lines_reader() {
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    writer
+        .write_entry(&Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+        .unwrap();
+    writer
+        .write_entry(&Entry {
+            name: String::from("ratio"),
+            elements: vec![Element::Float64(f64::NAN)],
+        })
+        .unwrap();
+
+    let mut output = vec![];
+    io::copy(
+        &mut JsonLinesReader::new(Reader::new(input.as_slice())),
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "{\"name\":[1651906455,\"unix_time\"]}\n{\"ratio\":null}\n"
+    );
+}
+
+#[test]
+fn test_json_lines_reader_with_invalid_data() {
+    let mut reader = JsonLinesReader::new(Reader::new([0b00101010u8].as_slice()));
+    let error = reader.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
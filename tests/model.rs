@@ -0,0 +1,319 @@
+use std::time::Duration;
+use tpk::{Document, Element, Entry, Reader, Writer, DURATION_EXTENSION_ID};
+
+#[test]
+fn test_entry_retain() {
+    let mut entry = Entry {
+        name: String::from("payload"),
+        elements: vec![
+            Element::Blob(vec![42u8; 50]),
+            Element::String(String::from("kept")),
+            Element::Blob(Vec::new()),
+            Element::UInteger8(42),
+        ],
+    };
+
+    entry.retain(|element| !matches!(element, Element::Blob(_)));
+    assert_eq!(
+        entry.elements,
+        vec![
+            Element::String(String::from("kept")),
+            Element::UInteger8(42)
+        ]
+    );
+}
+
+#[test]
+fn test_entry_blob() {
+    let entry = Entry {
+        name: String::from("payload"),
+        elements: vec![Element::Blob(vec![1, 2, 3])],
+    };
+    assert_eq!(entry.as_blob(), Some([1u8, 2, 3].as_slice()));
+    assert_eq!(entry.into_blob(), Some(vec![1, 2, 3]));
+
+    let entry = Entry {
+        name: String::from("payload"),
+        elements: vec![Element::UInteger8(1), Element::Blob(vec![1, 2, 3])],
+    };
+    assert_eq!(entry.as_blob(), None);
+    assert_eq!(entry.into_blob(), None);
+
+    let entry = Entry {
+        name: String::from("payload"),
+        elements: vec![Element::String(String::from("text"))],
+    };
+    assert_eq!(entry.as_blob(), None);
+    assert_eq!(entry.into_blob(), None);
+}
+
+#[test]
+fn test_document_retain_entries() {
+    let mut document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::Boolean(true)],
+            },
+            Entry {
+                name: String::from("b"),
+                elements: Vec::new(),
+            },
+        ],
+    };
+
+    document.retain_entries(|entry| !entry.elements.is_empty());
+    assert_eq!(document.entries.len(), 1);
+    assert_eq!(document.entries[0].name, "a");
+}
+
+#[test]
+fn test_document_map_names() {
+    let mut document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("name"),
+                elements: vec![Element::UInteger32(1651906455)],
+            },
+            Entry {
+                name: String::from("format"),
+                elements: vec![Element::String(String::from("unix_time"))],
+            },
+        ],
+    };
+
+    document.map_names(|name| format!("v2.{}", name));
+    for entry in document.entries_mut() {
+        entry.name.make_ascii_uppercase();
+    }
+
+    let mut output = vec![];
+    Writer::new(&mut output).write_document(&document).unwrap();
+    let result = Reader::new(output.as_slice()).read_document().unwrap();
+    assert_eq!(result, document);
+    assert_eq!(result.entries[0].name, "V2.NAME");
+    assert_eq!(result.entries[1].name, "V2.FORMAT");
+}
+
+#[test]
+fn test_document_with_prefix_and_concat() {
+    let network = Document {
+        entries: vec![
+            Entry {
+                name: String::from("port"),
+                elements: vec![Element::UInteger16(8080)],
+            },
+            Entry {
+                name: String::from("host"),
+                elements: vec![Element::String(String::from("localhost"))],
+            },
+        ],
+    };
+    let storage = Document {
+        entries: vec![Entry {
+            name: String::from("path"),
+            elements: vec![Element::String(String::from("/var/lib"))],
+        }],
+    };
+
+    let document = Document::concat(vec![
+        network.with_prefix("network"),
+        storage.with_prefix("storage"),
+    ]);
+    let names: Vec<&str> = document
+        .entries
+        .iter()
+        .map(|entry| entry.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["network/port", "network/host", "storage/path"]);
+    assert_eq!(
+        document.entries[0].elements,
+        vec![Element::UInteger16(8080)]
+    );
+    assert_eq!(Document::concat(vec![]).entries, vec![]);
+}
+
+#[test]
+fn test_document_compact_savings() {
+    let mut document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("counts"),
+                elements: vec![
+                    Element::UInteger64(5),
+                    Element::Integer32(-300),
+                    Element::UInteger128(70000),
+                ],
+            },
+            Entry {
+                name: String::from("other"),
+                elements: vec![
+                    Element::UInteger8(5),
+                    Element::Integer64(i64::MIN),
+                    Element::Float64(0.5),
+                    Element::String(String::from("text")),
+                ],
+            },
+        ],
+    };
+
+    let savings = document.compact_savings();
+    assert_eq!(savings, 7 + 2 + 12);
+    let encoded_len = document.encoded_len();
+    document.canonicalize();
+    assert_eq!(document.encoded_len(), encoded_len - savings);
+    assert_eq!(document.compact_savings(), 0);
+}
+
+#[test]
+#[cfg(feature = "sha2")]
+fn test_document_content_hash() {
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger64(1651906455),
+                Element::Integer32(-42),
+                Element::String(String::from("unix_time")),
+            ],
+        }],
+    };
+
+    let mut canonical = document.clone();
+    canonical.canonicalize();
+    assert_ne!(canonical, document);
+    assert_eq!(
+        canonical.content_hash().unwrap(),
+        document.content_hash().unwrap()
+    );
+    assert_ne!(
+        Document::default().content_hash().unwrap(),
+        document.content_hash().unwrap()
+    );
+
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("name"),
+            elements: vec![Element::CString(String::from("unix\0time"))],
+        }],
+    };
+    assert!(matches!(
+        document.content_hash(),
+        Err(tpk::write::Error::InteriorNul { pos: 4 })
+    ));
+}
+
+#[test]
+fn test_element_from_primitive() {
+    assert_eq!(Element::from(-42i8), Element::Integer8(-42));
+    assert_eq!(Element::from(1337u16), Element::UInteger16(1337));
+    assert_eq!(Element::from(1.5f64), Element::Float64(1.5));
+    assert_eq!(Element::from(true), Element::Boolean(true));
+    assert_eq!(Element::from("tpk"), Element::String(String::from("tpk")));
+    assert_eq!(Element::from(vec![42u8]), Element::Blob(vec![42u8]));
+}
+
+#[test]
+fn test_element_from_duration() {
+    let duration = Duration::new(3, 250);
+    let element = Element::try_from(duration).unwrap();
+    assert_eq!(element, Element::Duration(3_000_000_250));
+    assert_eq!(element.as_duration(), Some(duration));
+
+    let longest = Duration::from_nanos(i64::MAX as u64);
+    assert_eq!(
+        Element::try_from(longest).unwrap(),
+        Element::Duration(i64::MAX)
+    );
+    assert!(matches!(
+        Element::try_from(longest + Duration::from_nanos(1)),
+        Err(tpk::write::Error::DurationTooLong)
+    ));
+    assert_eq!(Element::Duration(-1).as_duration(), None);
+    assert_eq!(Element::Integer64(1).as_duration(), None);
+}
+
+#[test]
+fn test_validated_constructors() {
+    assert_eq!(
+        Element::extension(200, [1u8, 2u8]).unwrap(),
+        Element::Extension {
+            id: 200,
+            data: vec![1u8, 2u8]
+        }
+    );
+    assert!(matches!(
+        Element::extension(DURATION_EXTENSION_ID, [0u8; 8]),
+        Err(tpk::write::Error::ReservedExtensionId { id: 3 })
+    ));
+    assert_eq!(
+        Element::c_string("unix").unwrap(),
+        Element::CString(String::from("unix"))
+    );
+}
+
+#[test]
+fn test_validated_constructors_reject_invalid_data() {
+    assert!(matches!(
+        Element::c_string("unix\0time"),
+        Err(tpk::write::Error::InteriorNul { pos: 4 })
+    ));
+}
+
+#[test]
+fn test_element_value_bytes() {
+    assert_eq!(
+        Element::UInteger32(1651906455).value_bytes(),
+        Some(vec![0b10010111u8, 0b00010111u8, 0b01110110u8, 0b01100010u8])
+    );
+    assert_eq!(
+        Element::String(String::from("unix_time")).value_bytes(),
+        Some(b"unix_time".to_vec())
+    );
+    assert_eq!(Element::Integer8(-1).value_bytes(), Some(vec![0xFFu8]));
+    assert_eq!(Element::Boolean(true).value_bytes(), Some(vec![1u8]));
+    assert_eq!(
+        Element::Blob(vec![1u8, 2u8, 3u8]).value_bytes(),
+        Some(vec![1u8, 2u8, 3u8])
+    );
+    assert_eq!(Element::Marker(String::from("name")).value_bytes(), None);
+    assert_eq!(Element::Folder.value_bytes(), None);
+    assert_eq!(Element::Collection.value_bytes(), None);
+}
+
+#[test]
+fn test_element_wire_eq() {
+    let narrow = Element::UInteger8(5);
+    let wide = Element::UInteger16(5);
+    assert!(narrow.value_eq(&wide));
+    assert!(!narrow.wire_eq(&wide));
+    assert!(narrow.wire_eq(&Element::UInteger8(5)));
+
+    assert!(Element::Float64(f64::NAN).wire_eq(&Element::Float64(f64::NAN)));
+    assert_ne!(Element::Float64(f64::NAN), Element::Float64(f64::NAN));
+    assert!(!Element::Float64(0.0).wire_eq(&Element::Float64(-0.0)));
+    assert_eq!(Element::Float64(0.0), Element::Float64(-0.0));
+}
+
+#[test]
+fn test_document_truncate_to_bytes() {
+    let entry = Entry {
+        name: String::from("name"),
+        elements: vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+        ],
+    };
+    let mut document = Document {
+        entries: vec![entry.clone(), entry.clone(), entry.clone()],
+    };
+    assert_eq!(document.encoded_len(), 63);
+
+    assert_eq!(document.truncate_to_bytes(63), 0);
+    assert_eq!(document.entries.len(), 3);
+    assert_eq!(document.truncate_to_bytes(50), 1);
+    assert_eq!(document.entries, vec![entry.clone(), entry]);
+    assert_eq!(document.encoded_len(), 42);
+    assert_eq!(document.truncate_to_bytes(20), 2);
+    assert!(document.entries.is_empty());
+}
@@ -1,6 +1,14 @@
+#![allow(clippy::get_first)]
+
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::Cursor;
-use tpk::read::{Error, Result};
-use tpk::{Element, Entry, Reader};
+use std::mem;
+use tpk::read::{Error, Result, DEFAULT_MAX_DEPTH};
+use tpk::{
+    Document, Element, ElementCow, ElementRef, Entry, FramedReader, ReadState, Reader,
+    ResumableReader, SliceReader, Writer, ENDIANNESS_MAGIC,
+};
 
 macro_rules! read_element {
     ($i:ident reads to $p:pat => $e:expr) => {
@@ -195,20 +203,316 @@ fn test_read_blob_with_wrong_length() {
 
 #[test]
 fn test_read_string_blob_with_invalid_type_byte() {
-    let input = vec![0b00011000u8, 0b00000000u8];
+    let input = vec![0b00011001u8, 0b00000000u8, 0b00000000u8];
     read_element!(input fails with Error::UnknownType(pos, ..) => assert_eq!(pos, 0));
 }
 
 #[test]
-#[allow(deprecated)]
-fn test_extension_not_supported() {
-    let input = vec![0b01110000u8, 0b00000000u8];
-    read_element!(input fails with Error::UnsupportedType(pos, msg) => {
+fn test_read_extension() {
+    let input = vec![0b01110000u8, 200u8, 0b00000011u8, 1u8, 2u8, 3u8];
+    read_element!(input reads to Element::Extension { id, data } => {
+        assert_eq!(id, 200);
+        assert_eq!(data, vec![1u8, 2u8, 3u8]);
+    });
+}
+
+#[test]
+fn test_read_delta_u64() {
+    let input = vec![0b01110000u8, 0u8, 5u8, 0xE8u8, 0x07u8, 2u8, 4u8, 1u8];
+    read_element!(input reads to Element::DeltaU64(values) => {
+        assert_eq!(values, vec![1000, 1001, 1003, 1002]);
+    });
+}
+
+#[test]
+fn test_read_delta_u64_round_trip() {
+    let values = vec![u64::MAX, 0, 42, 41, u64::MAX - 1, 1 << 63];
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_element(&Element::DeltaU64(values.clone()))
+        .unwrap();
+    read_element!(output reads to Element::DeltaU64(result) => assert_eq!(result, values));
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_element(&Element::DeltaU64(vec![]))
+        .unwrap();
+    read_element!(output reads to Element::DeltaU64(result) => assert!(result.is_empty()));
+}
+
+#[test]
+fn test_read_128_bit_integer_round_trip() {
+    for val in [0, 42, u64::MAX as u128 + 1, u128::MAX] {
+        let mut output = vec![];
+        Writer::new(&mut output)
+            .write_element(&Element::UInteger128(val))
+            .unwrap();
+        read_element!(output reads to Element::UInteger128(result) => assert_eq!(result, val));
+    }
+    for val in [0, -42, i64::MIN as i128 - 1, i128::MIN, i128::MAX] {
+        let mut output = vec![];
+        Writer::new(&mut output)
+            .write_element(&Element::Integer128(val))
+            .unwrap();
+        read_element!(output reads to Element::Integer128(result) => assert_eq!(result, val));
+    }
+}
+
+#[test]
+fn test_read_reserved_number_type() {
+    let input = vec![0b00101010u8, 0u8];
+    read_element!(input fails with Error::UnknownType(pos, byte) => {
+        assert_eq!(pos, 0);
+        assert_eq!(byte, 0b00101010u8);
+    });
+
+    let input = vec![0b00101000u8];
+    let mut reader = Reader::new(Cursor::new(input)).version(2);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::TypeNotInVersion { version: 2, .. })
+    ));
+}
+
+#[test]
+fn test_read_duration_round_trip() {
+    for nanos in [0, 1_500_000_000, -42, i64::MIN, i64::MAX] {
+        let mut output = vec![];
+        Writer::new(&mut output)
+            .write_element(&Element::Duration(nanos))
+            .unwrap();
+        read_element!(output reads to Element::Duration(result) => assert_eq!(result, nanos));
+    }
+}
+
+fn sample_map() -> Element {
+    Element::Map(vec![
+        (
+            String::from("format"),
+            Element::String(String::from("unix_time")),
+        ),
+        (
+            String::from("range"),
+            Element::Map(vec![
+                (String::from("start"), Element::UInteger32(1651906455)),
+                (String::from("thumbnail"), Element::Blob(vec![42u8; 8])),
+            ]),
+        ),
+    ])
+}
+
+#[test]
+fn test_read_map_round_trip() {
+    let map = sample_map();
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_entry(&Entry {
+            name: String::from("name"),
+            elements: vec![map.clone(), Element::Boolean(true)],
+        })
+        .unwrap();
+    assert_eq!(output.len(), 5 + map.encoded_len() + 1);
+
+    let mut reader = Reader::new(Cursor::new(output));
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![map, Element::Boolean(true)],
+        })
+    );
+}
+
+#[test]
+fn test_read_map_with_dedup_blobs() {
+    let map = sample_map();
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).dedup_blobs(true);
+    writer.write_element(&Element::Blob(vec![42u8; 8])).unwrap();
+    writer.write_element(&map).unwrap();
+    let blob_len = Element::Blob(vec![42u8; 8]).encoded_len();
+    assert_eq!(
+        output.len(),
+        blob_len + map.encoded_len() - blob_len + Element::BlobRef(0).encoded_len()
+    );
+
+    let mut reader = Reader::new(Cursor::new(output)).dedup_blobs(true);
+    reader.read_element().unwrap();
+    assert_eq!(reader.read_element().unwrap(), Some(map));
+}
+
+#[test]
+fn test_read_map_with_invalid_key() {
+    let input = vec![
+        0b01110000u8,
+        6u8,
+        8u8,
+        1u8,
+        0u8,
+        0u8,
+        0u8,
+        0b00100000u8,
+        42u8,
+        0b00100000u8,
+        42u8,
+    ];
+    read_element!(input fails with Error::Syntax(pos, msg) => {
+        assert_eq!(pos, 7);
+        assert_eq!(msg, "invalid map key");
+    });
+}
+
+#[test]
+fn test_read_map_size_mismatch() {
+    let input = vec![
+        0b01110000u8,
+        6u8,
+        4u8,
+        1u8,
+        0u8,
+        0u8,
+        0u8,
+        0b00010000u8,
+        1u8,
+        b'a',
+        0b00100000u8,
+        42u8,
+    ];
+    // The key is larger than what remains of the payload.
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 9));
+}
+
+#[test]
+fn test_read_map_too_deep() {
+    let map = Element::Map(vec![(String::from("a"), Element::Map(vec![]))]);
+    let mut output = vec![];
+    Writer::new(&mut output).write_element(&map).unwrap();
+
+    let mut reader = Reader::new(output.as_slice()).max_depth(2);
+    assert_eq!(reader.read_element().unwrap(), Some(map));
+    let mut reader = Reader::new(output.as_slice()).max_depth(1);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::TooDeep { pos: 10, limit: 1 })
+    ));
+}
+
+#[test]
+fn test_skip_map_with_dedup_blobs() {
+    let blob = Element::Blob(vec![42u8; 8]);
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).dedup_blobs(true);
+    writer
+        .write_element(&Element::Marker(String::from("map")))
+        .unwrap();
+    writer
+        .write_element(&Element::Map(vec![(String::from("a"), blob.clone())]))
+        .unwrap();
+    writer
+        .write_element(&Element::Marker(String::from("blob")))
+        .unwrap();
+    writer.write_element(&blob).unwrap();
+
+    // The blob held by the skipped map must be remembered for the reference to resolve.
+    let mut reader = Reader::new(output.as_slice()).dedup_blobs(true);
+    assert_eq!(
+        reader.nth_entry(1).unwrap(),
+        Some(Entry {
+            name: String::from("blob"),
+            elements: vec![blob],
+        })
+    );
+}
+
+#[test]
+fn test_read_padding_round_trip() {
+    let elements = vec![
+        Element::UInteger32(1651906455),
+        Element::Padding(16),
+        Element::UInteger8(42),
+    ];
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+    }
+    assert_eq!(output.len(), 25);
+
+    let mut reader = Reader::new(Cursor::new(output.as_slice()));
+    for element in &elements {
+        assert_eq!(reader.read_element().unwrap().as_ref(), Some(element));
+    }
+    assert_eq!(reader.read_element().unwrap(), None);
+
+    // Patched padding is still read back as padding.
+    output[7..23].fill(0xFF);
+    let mut reader = Reader::new(Cursor::new(output.as_slice())).retain_raw(true);
+    reader.read_element().unwrap();
+    let padding = reader.read_raw_element().unwrap().unwrap();
+    assert_eq!(padding.element, Element::Padding(16));
+    assert_eq!(padding.raw.as_deref(), Some(&output[5..23]));
+    assert_eq!(reader.read_element().unwrap(), Some(Element::UInteger8(42)));
+}
+
+#[test]
+fn test_read_duration_with_invalid_size() {
+    let input = vec![0b01110000u8, 3u8, 4u8, 1u8, 2u8, 3u8, 4u8];
+    read_element!(input fails with Error::Syntax(pos, msg) => {
         assert_eq!(pos, 0);
-        assert_eq!(msg, "extension");
+        assert_eq!(msg, "invalid duration");
     });
 }
 
+#[test]
+fn test_read_delta_u64_with_truncated_varint() {
+    let input = vec![0b01110000u8, 0u8, 2u8, 0xE8u8, 0x87u8];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 0));
+}
+
+#[test]
+fn test_read_extension_with_invalid_type_byte() {
+    let input = vec![0b01110100u8, 200u8, 0b00000000u8];
+    read_element!(input fails with Error::UnknownType(pos, ..) => assert_eq!(pos, 0));
+}
+
+#[test]
+fn test_read_registered_extension() {
+    let input = vec![
+        0b01110000u8,
+        200u8,
+        0b00000100u8,
+        0b10010111u8,
+        0b00010111u8,
+        0b01110110u8,
+        0b01100010u8,
+        0b01110000u8,
+        201u8,
+        0b00000000u8,
+    ];
+
+    let mut reader = Reader::new(Cursor::new(input)).register_extension(
+        200,
+        Box::new(|data| {
+            let bytes = data
+                .try_into()
+                .map_err(|_| Error::Syntax(0, "expected 4 bytes"))?;
+            Ok(Element::UInteger32(u32::from_le_bytes(bytes)))
+        }),
+    );
+
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::UInteger32(1651906455))
+    );
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::Extension {
+            id: 201,
+            data: Vec::new()
+        })
+    );
+}
+
 // The following could be used to represent a timestamp while retaining the information about
 // what it represents: a unix timestamp. This shows the rationale behind allowing multiple
 // elements per named entry in TPK payloads.
@@ -239,6 +543,42 @@ const TIMESTAMP_ENTRY: [u8; 21] = [
     b'e',
 ];
 
+#[test]
+fn test_decode_entry() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&[0b10000100u8, b'n', b'e', b'x', b't']);
+
+    let entry = tpk::decode_entry(&input).unwrap().unwrap();
+
+    assert_eq!(entry.name, "name");
+    assert_eq!(
+        entry.elements,
+        vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+        ]
+    );
+    assert_eq!(tpk::decode_entry(&[]).unwrap(), None);
+}
+
+#[test]
+fn test_decode_entry_followed_by_invalid_data() {
+    // The next marker is truncated, but never decoded.
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.push(0b11000000u8);
+    let entry = tpk::decode_entry(&input).unwrap().unwrap();
+    assert_eq!(entry.elements.len(), 2);
+
+    // Anything else is part of the entry.
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.push(0b01111111u8);
+    assert!(matches!(
+        tpk::decode_entry(&input),
+        Err(Error::UnknownType(_, 0b01111111))
+    ));
+}
+
 #[test]
 fn test_read_entry() {
     let mut input = Vec::new();
@@ -341,3 +681,2192 @@ fn test_read_half_consumed_entry() {
         Some(Element::String(str)) if str == "unix_time"
     ));
 }
+
+#[test]
+fn test_read_entry_ref() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input[22] = b'l';
+
+    let mut reader = SliceReader::new(&input);
+    let result = reader.read_entry_ref().unwrap().unwrap();
+    let second_result = reader.read_entry_ref().unwrap().unwrap();
+
+    assert_eq!(result.name, "name");
+    assert_eq!(result.elements.len(), 2);
+    assert!(matches!(
+        result.elements.first(),
+        Some(ElementRef::Other(Element::UInteger32(1651906455)))
+    ));
+    assert!(matches!(
+        result.elements.get(1),
+        Some(ElementRef::String("unix_time"))
+    ));
+    assert_eq!(second_result.name, "lame");
+    assert_eq!(second_result.elements.len(), 2);
+    assert!(reader.read_entry_ref().unwrap().is_none());
+}
+
+#[test]
+fn test_read_entry_ref_borrows_from_source() {
+    let input = TIMESTAMP_ENTRY;
+    let source_range = input.as_ptr_range();
+
+    let mut reader = SliceReader::new(&input);
+    let result = reader.read_entry_ref().unwrap().unwrap();
+
+    assert!(source_range.contains(&result.name.as_ptr()));
+    match result.elements.get(1) {
+        Some(ElementRef::String(str)) => assert!(source_range.contains(&str.as_ptr())),
+        _ => panic!("Expected string element"),
+    }
+}
+
+#[test]
+fn test_read_element_ref_with_missing_bytes() {
+    let input = vec![0b00010100u8, 0b00000100u8, 1u8, 42u8];
+    let mut reader = SliceReader::new(&input);
+    let result = reader.read_element_ref();
+    assert!(matches!(
+        result,
+        Err(Error::Syntax(4, "expected more, got EOF"))
+    ));
+}
+
+fn collection_input() -> Vec<u8> {
+    vec![
+        // Marker - "list"
+        0b10000100u8,
+        b'l',
+        b'i',
+        b's',
+        b't',
+        // Collection
+        0b00000001u8,
+        // String - "a"
+        0b00010000u8,
+        0b00000001u8,
+        b'a',
+        // Unsigned integer - 42
+        0b00100000u8,
+        42u8,
+    ]
+}
+
+#[test]
+fn test_read_heterogeneous_collection_in_strict_mode() {
+    let input = collection_input();
+
+    let mut reader = Reader::new(Cursor::new(input)).strict(true);
+    let result = reader.read_entry();
+
+    assert!(matches!(
+        result,
+        Err(Error::HeterogeneousCollection { pos: 9 })
+    ));
+}
+
+#[test]
+fn test_read_heterogeneous_collection_in_lenient_mode() {
+    let input = collection_input();
+
+    let result = read_entry(&input).unwrap().unwrap();
+
+    assert_eq!(result.name, "list");
+    assert_eq!(result.elements.len(), 3);
+}
+
+#[test]
+fn test_read_homogeneous_collection_in_strict_mode() {
+    let mut input = collection_input();
+    input.truncate(9);
+    input.extend_from_slice(&[0b00010000u8, 0b00000001u8, b'b']);
+    // A new marker ends the collection.
+    input.extend_from_slice(&[0b10000001u8, b'n', 0b00100000u8, 42u8]);
+
+    let mut reader = Reader::new(Cursor::new(input)).strict(true);
+    let result = reader.read_entry().unwrap().unwrap();
+    let second_result = reader.read_entry().unwrap().unwrap();
+
+    assert_eq!(result.elements.len(), 3);
+    assert_eq!(second_result.name, "n");
+}
+
+#[test]
+fn test_read_non_canonical_marker_size_in_strict_mode() {
+    // The size of the marker fits in the type byte, but is followed by an empty continuation.
+    let input = [0b11000001u8, 0b00000000u8, b'a'];
+    read_element!(input reads to Element::Marker(name) => assert_eq!(name, "a"));
+    let mut reader = Reader::new(Cursor::new(input)).strict(true);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::NonCanonicalSize { pos: 0 })
+    ));
+
+    let mut input = vec![0b00100000u8, 42u8, 0b11000001u8, 0b10000001u8, 0b00000000u8];
+    input.extend(std::iter::repeat_n(b'a', 65));
+    let mut reader = Reader::new(Cursor::new(input)).strict(true);
+    reader.read_element().unwrap();
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::NonCanonicalSize { pos: 2 })
+    ));
+
+    let name = String::from_iter(std::iter::repeat_n('a', 100));
+    let mut input = vec![];
+    Writer::new(&mut input)
+        .write_element(&Element::Marker(name.clone()))
+        .unwrap();
+    let mut reader = Reader::new(Cursor::new(input)).strict(true);
+    assert_eq!(reader.read_element().unwrap(), Some(Element::Marker(name)));
+}
+
+#[test]
+fn test_copy_remaining_to() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input[22] = b'l';
+
+    let mut reader = Reader::new(Cursor::new(input.clone()));
+    reader.read_entry().unwrap().unwrap();
+    let mut output = Vec::new();
+    let bytes_written = reader.copy_remaining_to(&mut output).unwrap();
+
+    // The retained "lame" marker is written back before the untouched tail.
+    assert_eq!(bytes_written, 42);
+    assert_eq!(output, input[21..]);
+    assert!(reader.read_element().unwrap().is_none());
+}
+
+#[test]
+fn test_copy_remaining_to_keeps_marker_bytes() {
+    let input = vec![
+        0b10000001u8,
+        b'a',
+        0b00100000u8,
+        1u8,
+        0b11000001u8, // Non-minimal "b" marker
+        0u8,
+        b'b',
+        0b00100000u8,
+        2u8,
+        0b10000010u8, // Invalid UTF-8 marker
+        0xFFu8,
+        b'c',
+        0b00100000u8,
+        3u8,
+    ];
+
+    let mut reader = Reader::new(input.as_slice());
+    reader.read_entry().unwrap().unwrap();
+    let mut output = Vec::new();
+    reader.copy_remaining_to(&mut output).unwrap();
+    assert_eq!(output, input[4..]);
+
+    let mut reader = Reader::new(input.as_slice()).lossy_utf8(true);
+    reader.read_entry().unwrap().unwrap();
+    reader.read_entry().unwrap().unwrap();
+    let mut output = Vec::new();
+    reader.copy_remaining_to(&mut output).unwrap();
+    assert_eq!(output, input[9..]);
+}
+
+#[test]
+fn test_nth_entry() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input[22] = b'l';
+    input[43] = b'g';
+
+    let mut reader = Reader::new(Cursor::new(input.clone()));
+    let result = reader.nth_entry(1).unwrap().unwrap();
+    assert_eq!(result.name, "lame");
+    assert_eq!(result.elements.len(), 2);
+
+    let mut reader = Reader::new(Cursor::new(input));
+    assert!(reader.nth_entry(3).unwrap().is_none());
+}
+
+#[test]
+fn test_read_entries_named() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input[22] = b'l';
+    input[64] = b'g';
+
+    let names = HashSet::from([String::from("name")]);
+    let mut reader = Reader::new(Cursor::new(input));
+    let entries = reader
+        .read_entries_named(&names)
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    for entry in entries {
+        assert_eq!(entry.name, "name");
+        assert_eq!(entry.elements.len(), 2);
+    }
+    assert!(reader.read_element().unwrap().is_none());
+}
+
+#[test]
+fn test_read_raw_element_round_trip() {
+    // String - "abc", with a non-minimal 16-bit size.
+    let input = vec![0b00010001u8, 3u8, 0u8, b'a', b'b', b'c', 0b00110001u8];
+
+    let mut reader = Reader::new(Cursor::new(input.clone())).retain_raw(true);
+    let mut output = Vec::new();
+    let mut writer = Writer::new(&mut output);
+    while let Some(element) = reader.read_raw_element().unwrap() {
+        writer.write_raw_element(&element).unwrap();
+    }
+
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_read_raw_element_without_retention() {
+    let input = vec![0b00010001u8, 3u8, 0u8, b'a', b'b', b'c'];
+
+    let mut reader = Reader::new(Cursor::new(input));
+    let result = reader.read_raw_element().unwrap().unwrap();
+
+    assert_eq!(result.element, Element::String("abc".into()));
+    assert!(result.raw.is_none());
+}
+
+#[test]
+fn test_read_raw_element_after_entry() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+
+    let mut reader = Reader::new(Cursor::new(input)).retain_raw(true);
+    reader.read_entry().unwrap().unwrap();
+    let result = reader.read_raw_element().unwrap().unwrap();
+
+    assert_eq!(result.element, Element::Marker("name".into()));
+    assert_eq!(result.raw.as_deref(), Some(&TIMESTAMP_ENTRY[..5]));
+}
+
+#[test]
+fn test_read_element_with_decode_context() {
+    let input = vec![0b00010000u8, 5u8, b'a', b'b'];
+
+    let mut reader = Reader::new(Cursor::new(input)).decode_context(true);
+    let result = reader.read_element();
+
+    match result {
+        Err(Error::DecodeContext { bytes, source }) => {
+            assert_eq!(bytes, vec![0b00010000u8, 5u8, b'a', b'b']);
+            assert!(matches!(
+                *source,
+                Error::Syntax(4, "expected more, got EOF")
+            ));
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_read_element_without_decode_context() {
+    let input = vec![0b00010000u8, 5u8, b'a', b'b'];
+
+    let mut reader = Reader::new(Cursor::new(input));
+    let result = reader.read_element();
+
+    assert!(matches!(result, Err(Error::Syntax(4, _))));
+}
+
+#[test]
+fn test_read_marker_over_max_len() {
+    let mut input = vec![0b11000110u8, 0b11001000u8, 0b01111000u8];
+    input.extend_from_slice(&[b'a'; 16]);
+
+    let mut reader = Reader::new(Cursor::new(input)).max_marker_len(256);
+    let result = reader.read_element();
+
+    assert!(matches!(
+        result,
+        Err(Error::MarkerTooLong {
+            pos: 0,
+            len: 987654,
+            limit: 256
+        })
+    ));
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_read_marker_with_overflowing_size() {
+    let mut input = vec![0b11000000u8];
+    input.extend_from_slice(&[0xFFu8; 8]);
+    input.push(0b00000100u8);
+    read_element!(input fails with Error::Syntax(pos, msg) => {
+        assert_eq!(pos, 0);
+        assert_eq!(msg, "marker size exceeds the address space");
+    });
+
+    let mut input = vec![0b11000000u8];
+    input.extend_from_slice(&[0x80u8; 12]);
+    input.push(0b00000001u8);
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 0));
+}
+
+#[test]
+fn test_read_marker_with_long_size() {
+    // Size 5, padded with empty continuation bytes up to the maximum varint length.
+    let mut input = vec![0b11000101u8];
+    input.extend_from_slice(&[0x80u8; 8]);
+    input.push(0u8);
+    input.extend_from_slice(b"hello");
+    read_element!(input reads to Element::Marker(name) => assert_eq!(name, "hello"));
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_read_marker_with_maximum_size() {
+    let mut input = vec![0b11111111u8];
+    input.extend_from_slice(&[0xFFu8; 8]);
+    input.push(0b00000011u8);
+    let mut reader = Reader::new(Cursor::new(input)).max_marker_len(0);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::MarkerTooLong {
+            pos: 0,
+            len: usize::MAX,
+            limit: 0
+        })
+    ));
+
+    let mut input = vec![0b11000000u8];
+    input.extend_from_slice(&[0x80u8; 8]);
+    input.push(0b00000010u8);
+    let mut reader = Reader::new(Cursor::new(input)).max_marker_len(0);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::MarkerTooLong { len, .. }) if len == 1 << 63
+    ));
+}
+
+#[test]
+fn test_read_marker_under_max_len() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+
+    let mut reader = Reader::new(Cursor::new(input)).max_marker_len(4);
+    let result = reader.read_entry().unwrap().unwrap();
+
+    assert_eq!(result.name, "name");
+}
+
+#[test]
+fn test_read_entry_with_too_many_elements() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a']);
+    input.extend_from_slice(&[0b00110001u8; 3]);
+
+    let mut reader = Reader::new(Cursor::new(input.clone())).max_elements_per_entry(2);
+    assert_eq!(reader.read_entry().unwrap().unwrap().name, "name");
+    assert!(matches!(
+        reader.read_entry(),
+        Err(Error::TooManyElements { name, limit: 2 }) if name == "a"
+    ));
+
+    let mut reader = Reader::new(Cursor::new(input)).max_elements_per_entry(3);
+    assert_eq!(reader.read_document().unwrap().entries.len(), 2);
+
+    let mut reader = Reader::new(Cursor::new([0b00110001u8])).max_elements_per_entry(0);
+    assert!(matches!(
+        reader.read_entry(),
+        Err(Error::TooManyElements { limit: 0, .. })
+    ));
+}
+
+/// A reader that fails on reads into an empty buffer, which are never needed.
+struct NoEmptyReads<R>(R);
+
+impl<R: std::io::Read> std::io::Read for NoEmptyReads<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        assert!(!buf.is_empty(), "Unexpected empty read");
+        self.0.read(buf)
+    }
+}
+
+fn assert_empty_payload_reads_to(type_byte: u8, size_bytes: usize, expected: Element) {
+    let mut input = vec![type_byte];
+    input.extend(std::iter::repeat_n(0u8, size_bytes));
+    input.push(0b00110001u8);
+
+    let mut reader = Reader::new(NoEmptyReads(Cursor::new(input)));
+    assert_eq!(reader.read_element().unwrap(), Some(expected));
+    assert_eq!(reader.read_element().unwrap(), Some(Element::Boolean(true)));
+    assert_eq!(reader.read_element().unwrap(), None);
+}
+
+#[test]
+fn test_read_empty_string_with_each_size_class() {
+    for (size_class, size_bytes) in [1, 2, 4, 8].into_iter().enumerate() {
+        assert_empty_payload_reads_to(
+            0b00010000u8 | size_class as u8,
+            size_bytes,
+            Element::String(String::new()),
+        );
+    }
+}
+
+#[test]
+fn test_read_empty_blob_with_each_size_class() {
+    for (size_class, size_bytes) in [1, 2, 4, 8].into_iter().enumerate() {
+        assert_empty_payload_reads_to(
+            0b00010100u8 | size_class as u8,
+            size_bytes,
+            Element::Blob(Vec::new()),
+        );
+    }
+}
+
+fn section_input() -> Vec<u8> {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    // Marker - "footer"
+    input.extend_from_slice(&[0b10000110u8, b'f', b'o', b'o', b't', b'e', b'r']);
+    // Boolean - true
+    input.push(0b00110001u8);
+    input
+}
+
+#[test]
+fn test_read_until_marker() {
+    let mut reader = Reader::new(Cursor::new(section_input()));
+
+    let result = reader.read_until_marker("footer").unwrap();
+
+    assert_eq!(
+        result,
+        vec![
+            Element::Marker("name".into()),
+            Element::UInteger32(1651906455),
+            Element::String("unix_time".into()),
+        ]
+    );
+    let footer = reader.read_entry().unwrap().unwrap();
+    assert_eq!(footer.name, "footer");
+    assert_eq!(footer.elements, vec![Element::Boolean(true)]);
+}
+
+#[test]
+fn test_read_until_missing_marker() {
+    let mut reader = Reader::new(Cursor::new(section_input()));
+    let result = reader.read_until_marker("header").unwrap();
+    assert_eq!(result.len(), 5);
+
+    let mut reader = Reader::new(Cursor::new(section_input())).fail_on_missing_marker(true);
+    let result = reader.read_until_marker("header");
+    assert!(matches!(result, Err(Error::MarkerNotFound { name }) if name == "header"));
+}
+
+#[test]
+fn test_expect_eof() {
+    let input = TIMESTAMP_ENTRY;
+
+    let mut reader = Reader::new(Cursor::new(input));
+    reader.read_entry().unwrap().unwrap();
+
+    assert!(reader.expect_eof().is_ok());
+}
+
+#[test]
+fn test_expect_eof_with_trailing_data() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.push(0b00110001u8);
+
+    let mut reader = Reader::new(Cursor::new(input));
+    reader.read_element().unwrap(); // Read the marker
+    reader.read_element().unwrap(); // Read the integer
+    reader.read_element().unwrap(); // Read the string
+
+    assert!(matches!(
+        reader.expect_eof(),
+        Err(Error::TrailingData { pos: 21 })
+    ));
+}
+
+#[test]
+fn test_expect_eof_with_retained_marker() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+
+    let mut reader = Reader::new(Cursor::new(input));
+    reader.read_entry().unwrap().unwrap();
+
+    assert!(matches!(
+        reader.expect_eof(),
+        Err(Error::TrailingData { pos: 21 })
+    ));
+}
+
+#[test]
+fn test_read_map() {
+    let mut input = Vec::new();
+    // Marker - "format", String - "TPK"
+    input.extend_from_slice(&[0b10000110u8, b'f', b'o', b'r', b'm', b'a', b't']);
+    input.extend_from_slice(&[0b00010000u8, 0b00000011u8, b'T', b'P', b'K']);
+    // Marker - "major", Unsigned integer - 1
+    input.extend_from_slice(&[0b10000101u8, b'm', b'a', b'j', b'o', b'r']);
+    input.extend_from_slice(&[0b00100000u8, 1u8]);
+
+    let mut reader = Reader::new(Cursor::new(input));
+    let result = reader.read_map().unwrap();
+
+    assert_eq!(result.len(), 2);
+    assert_eq!(result.get("format"), Some(&Element::String("TPK".into())));
+    assert_eq!(result.get("major"), Some(&Element::UInteger8(1)));
+}
+
+#[test]
+fn test_read_map_with_multi_value_entry() {
+    let input = TIMESTAMP_ENTRY;
+
+    let mut reader = Reader::new(Cursor::new(input));
+    let result = reader.read_map();
+
+    assert!(matches!(result, Err(Error::MultiValueEntry { name }) if name == "name"));
+}
+
+#[test]
+fn test_read_truncated_frame() {
+    let input = vec![21u8, 0u8, 0u8, 0u8, 0b10000100u8, b'n', b'a', b'm', b'e'];
+    let mut reader = FramedReader::new(Cursor::new(input));
+    match reader.read_framed_document() {
+        Err(Error::Syntax(pos, _)) => assert_eq!(pos, 9),
+        _ => panic!("Expected syntax error"),
+    }
+}
+
+#[test]
+fn test_peek_type_byte() {
+    let input = TIMESTAMP_ENTRY.to_vec();
+    let mut reader = Reader::new(Cursor::new(input));
+
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b10000100u8));
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b10000100u8));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::Marker(String::from("name")))
+    );
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b00100010u8));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::UInteger32(1651906455))
+    );
+    reader.read_element().unwrap();
+    assert_eq!(reader.peek_type_byte().unwrap(), None);
+}
+
+#[test]
+fn test_peek_type_byte_of_retained_marker() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a']);
+    let mut reader = Reader::new(Cursor::new(input)).retain_raw(true);
+
+    reader.read_entry().unwrap();
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b10000001u8));
+    assert_eq!(
+        reader.read_raw_element().unwrap().unwrap().raw.as_deref(),
+        Some([0b10000001u8, b'a'].as_slice())
+    );
+}
+
+#[test]
+fn test_peek_element() {
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+
+    let marker = Element::Marker(String::from("name"));
+    assert_eq!(reader.peek_element().unwrap(), Some(&marker));
+    assert_eq!(reader.peek_element().unwrap(), Some(&marker));
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b10000100u8));
+    assert_eq!(reader.read_element().unwrap(), Some(marker));
+    assert_eq!(
+        reader.peek_element().unwrap(),
+        Some(&Element::UInteger32(1651906455))
+    );
+    assert!(matches!(
+        reader.expect_eof(),
+        Err(Error::TrailingData { pos: 5 })
+    ));
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+    assert_eq!(reader.peek_element().unwrap(), None);
+    assert!(reader.expect_eof().is_ok());
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ReadOp {
+    Peek,
+    Element,
+    Entry,
+}
+
+/// A model of the reader operations, working on already decoded elements.
+struct ReadModel<'a> {
+    elements: &'a [Element],
+    pos: usize,
+    current_name: String,
+}
+
+impl ReadModel<'_> {
+    fn next(&mut self) -> Option<Element> {
+        let element = self.elements.get(self.pos)?.clone();
+        self.pos += 1;
+        if let Element::Marker(ref name) = element {
+            self.current_name = name.clone();
+        }
+        Some(element)
+    }
+
+    fn entry(&mut self) -> Option<Entry> {
+        let mut elements = Vec::new();
+        let name = match self.next()? {
+            Element::Marker(name) => name,
+            element => {
+                elements.push(element);
+                self.current_name.clone()
+            }
+        };
+        while let Some(element) = self.elements.get(self.pos) {
+            if let Element::Marker(_) = element {
+                break;
+            }
+            elements.push(element.clone());
+            self.pos += 1;
+        }
+        Some(Entry { name, elements })
+    }
+}
+
+#[test]
+fn test_peek_and_read_interleavings() {
+    let elements = vec![
+        Element::UInteger8(1),
+        Element::Marker(String::from("a")),
+        Element::UInteger8(2),
+        Element::UInteger8(3),
+        Element::Marker(String::from("b")),
+        Element::Marker(String::from("c")),
+        Element::UInteger8(4),
+    ];
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+    }
+
+    const OPS: [ReadOp; 3] = [ReadOp::Peek, ReadOp::Element, ReadOp::Entry];
+    const LEN: u32 = 8;
+    for mut n in 0..OPS.len().pow(LEN) {
+        let mut reader = Reader::new(Cursor::new(input.as_slice())).strict(true);
+        let mut model = ReadModel {
+            elements: &elements,
+            pos: 0,
+            current_name: String::from("/"),
+        };
+        let mut ops = vec![];
+        for _ in 0..LEN {
+            let op = OPS[n % OPS.len()];
+            n /= OPS.len();
+            ops.push(op);
+            match op {
+                ReadOp::Peek => assert_eq!(
+                    reader.peek_element().unwrap(),
+                    model.elements.get(model.pos),
+                    "{ops:?}"
+                ),
+                ReadOp::Element => {
+                    assert_eq!(reader.read_element().unwrap(), model.next(), "{ops:?}")
+                }
+                ReadOp::Entry => {
+                    assert_eq!(reader.read_entry().unwrap(), model.entry(), "{ops:?}")
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_peek_element_with_raw_bytes() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a', 0b00100000u8, 42u8]);
+    let mut reader = Reader::new(Cursor::new(input)).retain_raw(true);
+
+    reader.read_element().unwrap();
+    reader.peek_element().unwrap();
+    assert_eq!(
+        reader.read_raw_element().unwrap().unwrap().raw.as_deref(),
+        Some([0b00100010u8, 0x97, 0x17, 0x76, 0x62].as_slice())
+    );
+    reader.read_entry().unwrap();
+    reader.peek_element().unwrap();
+    let mut output = vec![];
+    reader.copy_remaining_to(&mut output).unwrap();
+    assert_eq!(output, vec![0b10000001u8, b'a', 0b00100000u8, 42u8]);
+}
+
+#[test]
+fn test_read_element_cow() {
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+    let mut slice_reader = SliceReader::new(&TIMESTAMP_ENTRY);
+
+    for _ in 0..3 {
+        let owned = reader.read_element_cow().unwrap().unwrap();
+        let borrowed = slice_reader.read_element_cow().unwrap().unwrap();
+        match (&owned, &borrowed) {
+            (ElementCow::Marker(Cow::Owned(_)), ElementCow::Marker(Cow::Borrowed(_)))
+            | (ElementCow::String(Cow::Owned(_)), ElementCow::String(Cow::Borrowed(_)))
+            | (ElementCow::Other(_), ElementCow::Other(_)) => (),
+            _ => panic!("Expected owned and borrowed elements"),
+        }
+        assert_eq!(owned, borrowed);
+    }
+
+    assert_eq!(
+        slice_reader
+            .read_element_cow()
+            .unwrap()
+            .map(ElementCow::into_element),
+        None
+    );
+}
+
+#[test]
+fn test_declared_size_exceeds_input() {
+    let mut input = vec![0b00010010u8];
+    input.extend_from_slice(&1000000u32.to_le_bytes());
+    input.extend_from_slice(&[b'a'; 95]);
+
+    let mut reader = Reader::new(Cursor::new(input)).detect_input_len().unwrap();
+    match reader.read_element() {
+        Err(Error::Syntax(pos, msg)) => {
+            assert_eq!(pos, 5);
+            assert_eq!(msg, "declared size exceeds remaining input");
+        }
+        _ => panic!("Expected syntax error"),
+    }
+}
+
+#[test]
+fn test_version_rejects_newer_types() {
+    let input = vec![0b01110000u8, 200u8, 0b00000000u8];
+    let mut reader = Reader::new(Cursor::new(input.clone())).version(1);
+    match reader.read_element() {
+        Err(Error::TypeNotInVersion { pos, byte, version }) => {
+            assert_eq!(pos, 0);
+            assert_eq!(byte, 0b01110000u8);
+            assert_eq!(version, 1);
+        }
+        _ => panic!("Expected version error"),
+    }
+
+    let mut reader = Reader::new(Cursor::new(input)).version(2);
+    assert!(matches!(
+        reader.read_element(),
+        Ok(Some(Element::Extension { id: 200, .. }))
+    ));
+}
+
+#[test]
+fn test_read_lossy_marker() {
+    let input = vec![0b10000011u8, b'a', 0xFFu8, b'b', 0b00010000u8, 1u8, b'c'];
+    let mut reader = Reader::new(Cursor::new(input.clone()));
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::InvalidString { pos: 2, .. })
+    ));
+
+    let mut reader = Reader::new(Cursor::new(input)).lossy_utf8(true);
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::Marker(String::from("a\u{FFFD}b")))
+    );
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::String(String::from("c")))
+    );
+    assert_eq!(reader.lossy_substitutions(), 1);
+}
+
+#[test]
+fn test_read_lossy_string() {
+    let input = vec![
+        0b00010000u8,
+        3u8,
+        b'a',
+        0xFFu8,
+        b'b',
+        0b00011000u8,
+        0xC3u8,
+        b'c',
+        0u8,
+    ];
+    let mut reader = Reader::new(Cursor::new(input.clone()));
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::InvalidString { pos: 3, .. })
+    ));
+
+    let mut reader = Reader::new(Cursor::new(input)).lossy_utf8(true);
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::String(String::from("a\u{FFFD}b")))
+    );
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::CString(String::from("\u{FFFD}c")))
+    );
+    assert_eq!(reader.lossy_substitutions(), 2);
+}
+
+#[test]
+fn test_take_reader() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    let mut cursor = Cursor::new(input);
+
+    let mut reader = Reader::take(&mut cursor, 21);
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+    assert_eq!(reader.read_entry().unwrap(), None);
+    assert_eq!(cursor.position(), 21);
+}
+
+/// A source returning at most one byte per read call, like a slow socket would.
+struct ByteByByte<'a>(&'a [u8]);
+
+impl std::io::Read for ByteByByte<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match (self.0.split_first(), buf.first_mut()) {
+            (Some((byte, rest)), Some(slot)) => {
+                *slot = *byte;
+                self.0 = rest;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+#[test]
+fn test_read_across_chunks() {
+    let mut reader = Reader::new(ByteByByte(&TIMESTAMP_ENTRY));
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+}
+
+/// A source returning one byte per read call, and being interrupted every other call.
+struct InterruptedEveryOther<'a> {
+    data: &'a [u8],
+    interrupt: bool,
+}
+
+impl std::io::Read for InterruptedEveryOther<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        ByteByByte(self.data).read(buf).inspect(|count| {
+            self.data = &self.data[*count..];
+        })
+    }
+}
+
+#[test]
+fn test_peek_type_byte_interrupted() {
+    let mut reader = Reader::new(InterruptedEveryOther {
+        data: &TIMESTAMP_ENTRY,
+        interrupt: false,
+    });
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(TIMESTAMP_ENTRY[0]));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::Marker(String::from("name")))
+    );
+    assert_eq!(reader.peek_type_byte().unwrap(), Some(0b00100010u8));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::UInteger32(1651906455))
+    );
+}
+
+/// A source returning its data in two read calls, split at the given offset.
+struct SplitAt<'a>(&'a [u8], usize);
+
+impl std::io::Read for SplitAt<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let limit = if self.1 > 0 { self.1 } else { usize::MAX };
+        let count = buf.len().min(limit).min(self.0.len());
+        buf[..count].copy_from_slice(&self.0[..count]);
+        self.0 = &self.0[count..];
+        self.1 = self.1.saturating_sub(count);
+        Ok(count)
+    }
+}
+
+#[test]
+fn test_read_string_split_across_reads() {
+    // "€" is encoded as 0xE2 0x82 0xAC, and the source is split after its first byte.
+    let mut input = vec![0b10000011u8, b'\xE2', b'\x82', b'\xAC', 0b00010000u8, 4];
+    input.extend_from_slice("1€".as_bytes());
+    let marker_split = 2;
+    let string_split = input.len() - 2;
+
+    for split in [marker_split, string_split] {
+        let mut reader = Reader::new(SplitAt(&input, split));
+        assert_eq!(
+            reader.read_entry().unwrap(),
+            Some(Entry {
+                name: String::from("€"),
+                elements: vec![Element::String(String::from("1€"))],
+            })
+        );
+
+        let mut reader = ResumableReader::new(SplitAt(&input, split));
+        assert_eq!(
+            reader.read_element().unwrap(),
+            ReadState::Ready(Some(Element::Marker(String::from("€"))))
+        );
+    }
+
+    let input = [0b00011000u8, b'\xE2', b'\x82', b'\xAC', 0];
+    let mut reader = Reader::new(SplitAt(&input, 2));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::CString(String::from("€")))
+    );
+}
+
+#[test]
+fn test_read_invalid_string_split_across_reads() {
+    let input = [0b00010000u8, 3, b'\xE2', b'\x82', b'x'];
+    let mut reader = Reader::new(SplitAt(&input, 3));
+    match reader.read_element() {
+        Err(Error::InvalidString { pos, .. }) => assert_eq!(pos, 2),
+        _ => panic!("Expected invalid string error"),
+    }
+}
+
+#[test]
+fn test_first_marker_name() {
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+    assert_eq!(
+        reader.first_marker_name().unwrap(),
+        Some(String::from("name"))
+    );
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+
+    let mut input = vec![0b00010100u8, 3u8, 1u8, 2u8, 3u8];
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(
+        reader.first_marker_name().unwrap(),
+        Some(String::from("name"))
+    );
+
+    let input = vec![0b00100000u8, 42u8];
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(reader.first_marker_name().unwrap(), None);
+}
+
+#[test]
+fn test_marker_names() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("format"),
+                elements: vec![Element::String(String::from("TPK"))],
+            },
+            Entry {
+                name: String::from("thumbnail"),
+                elements: vec![Element::Blob(vec![42u8; 10 * 1024]), Element::Folder],
+            },
+            Entry {
+                name: String::from("name"),
+                elements: vec![Element::UInteger32(1651906455)],
+            },
+        ],
+    };
+    let mut input = vec![];
+    Writer::new(&mut input).write_document(&document).unwrap();
+
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(
+        reader.marker_names().unwrap(),
+        vec![
+            String::from("format"),
+            String::from("thumbnail"),
+            String::from("name")
+        ]
+    );
+    assert_eq!(reader.read_element().unwrap(), None);
+}
+
+fn heap_size(element: &Element) -> usize {
+    match element {
+        Element::Marker(val) | Element::String(val) | Element::CString(val) => val.capacity(),
+        Element::Blob(val) => val.capacity(),
+        Element::Extension { data, .. } => data.capacity(),
+        Element::DeltaU64(values) => values.capacity() * mem::size_of::<u64>(),
+        Element::Flagged { inner, .. } => mem::size_of::<Element>() + heap_size(inner),
+        Element::Map(pairs) => {
+            pairs.capacity() * mem::size_of::<(String, Element)>()
+                + pairs
+                    .iter()
+                    .map(|(key, value)| key.capacity() + heap_size(value))
+                    .sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_estimate_parsed_size() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("format"),
+                elements: vec![Element::String(String::from("TPK"))],
+            },
+            Entry {
+                name: String::from("thumbnail"),
+                elements: vec![
+                    Element::Blob(vec![42u8; 10 * 1024]),
+                    Element::Blob(vec![42u8; 10 * 1024]),
+                    Element::DeltaU64(vec![1, 2, 3, 4, 5]),
+                ],
+            },
+            Entry {
+                name: String::from("properties"),
+                elements: vec![
+                    Element::Flagged {
+                        flags: 1,
+                        inner: Box::new(Element::String(String::from("flagged"))),
+                    },
+                    sample_map(),
+                ],
+            },
+            Entry {
+                name: String::from("empty"),
+                elements: vec![],
+            },
+        ],
+    };
+    let mut input = vec![];
+    Writer::new(&mut input)
+        .dedup_blobs(true)
+        .write_document(&document)
+        .unwrap();
+
+    for dedup_blobs in [false, true] {
+        let read_document = || Reader::new(input.as_slice()).dedup_blobs(dedup_blobs);
+        let parsed = read_document().read_document().unwrap();
+        let actual = parsed.entries.capacity() * mem::size_of::<Entry>()
+            + parsed
+                .entries
+                .iter()
+                .map(|entry| {
+                    entry.name.capacity()
+                        + entry.elements.capacity() * mem::size_of::<Element>()
+                        + entry.elements.iter().map(heap_size).sum::<usize>()
+                })
+                .sum::<usize>();
+
+        let mut reader = read_document();
+        let estimate = reader.estimate_parsed_size().unwrap();
+        assert!(estimate >= actual, "{} < {}", estimate, actual);
+        assert!(estimate <= actual * 4, "{} > 4 * {}", estimate, actual);
+        assert_eq!(reader.read_element().unwrap(), None);
+    }
+}
+
+#[test]
+fn test_read_with_depth() {
+    let entry = |name: &str, elements: Vec<Element>| Entry {
+        name: String::from(name),
+        elements,
+    };
+    let document = Document {
+        entries: vec![
+            entry("a", vec![Element::UInteger8(1)]),
+            entry("b", vec![Element::Folder]),
+            entry("c", vec![Element::String(String::from("x"))]),
+            entry(
+                "d",
+                vec![
+                    Element::Collection,
+                    Element::UInteger8(1),
+                    Element::UInteger8(2),
+                ],
+            ),
+            entry("..", vec![]),
+            entry("e", vec![Element::Boolean(true), Element::Folder]),
+        ],
+    };
+    let mut input = vec![];
+    Writer::new(&mut input).write_document(&document).unwrap();
+
+    let marker = |name: &str| Element::Marker(String::from(name));
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(
+        reader.read_with_depth().unwrap(),
+        vec![
+            (0, marker("a")),
+            (1, Element::UInteger8(1)),
+            (0, marker("b")),
+            (1, Element::Folder),
+            (1, marker("c")),
+            (2, Element::String(String::from("x"))),
+            (1, marker("d")),
+            (2, Element::Collection),
+            (3, Element::UInteger8(1)),
+            (3, Element::UInteger8(2)),
+            (1, marker("..")),
+            (0, marker("e")),
+            (1, Element::Boolean(true)),
+            (1, Element::Folder),
+        ]
+    );
+}
+
+#[test]
+fn test_read_all_scalars() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a', 0b00000001u8, 0b00110001u8]);
+    // Padding of 2 bytes
+    input.extend_from_slice(&[0b00011100u8, 2u8, 0u8, 0u8]);
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(
+        reader.read_all_scalars().unwrap(),
+        vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+            Element::Boolean(true),
+        ]
+    );
+    assert_eq!(reader.read_element().unwrap(), None);
+}
+
+#[test]
+fn test_read_element_ranged() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a']);
+    let mut reader = Reader::new(Cursor::new(input));
+
+    assert_eq!(
+        reader.read_element_ranged().unwrap(),
+        Some((Element::Marker(String::from("name")), 0..5))
+    );
+    assert_eq!(
+        reader.read_element_ranged().unwrap(),
+        Some((Element::UInteger32(1651906455), 5..10))
+    );
+    assert_eq!(
+        reader.read_element_ranged().unwrap(),
+        Some((Element::String(String::from("unix_time")), 10..21))
+    );
+    reader.peek_element().unwrap();
+    assert_eq!(
+        reader.read_element_ranged().unwrap(),
+        Some((Element::Marker(String::from("a")), 21..23))
+    );
+    assert_eq!(reader.read_element_ranged().unwrap(), None);
+}
+
+#[test]
+fn test_read_element_ranged_non_minimal() {
+    // A string with a 16-bit size, one byte longer than its minimal encoding.
+    let input = vec![0b00010001u8, 1u8, 0u8, b'a', 0b00100000u8, 42u8];
+    let mut reader = Reader::new(Cursor::new(input));
+
+    reader.peek_element().unwrap();
+    assert!(matches!(
+        reader.expect_eof(),
+        Err(Error::TrailingData { pos: 0 })
+    ));
+    assert!(matches!(
+        reader.read_blob_into(&mut [0u8; 4]),
+        Err(Error::UnexpectedType { pos: 0, .. })
+    ));
+    assert_eq!(
+        reader.read_element_ranged().unwrap(),
+        Some((Element::String(String::from("a")), 0..4))
+    );
+    reader.peek_element().unwrap();
+    assert_eq!(reader.entry_offsets().unwrap(), vec![4]);
+}
+
+#[test]
+fn test_read_truncated_numbers() {
+    let type_bytes = [
+        (0b00100000u8, 1usize),
+        (0b00100001u8, 2),
+        (0b00100010u8, 4),
+        (0b00100011u8, 8),
+        (0b00100100u8, 1),
+        (0b00100101u8, 2),
+        (0b00100110u8, 4),
+        (0b00100111u8, 8),
+        (0b00101110u8, 4),
+        (0b00101111u8, 8),
+    ];
+
+    for (type_byte, size) in type_bytes {
+        let mut input = vec![0b10000001u8, b'a', type_byte];
+        input.extend(std::iter::repeat_n(0xFFu8, size - 1));
+        let mut reader = Reader::new(ByteByByte(&input));
+        reader.read_element().unwrap();
+        match reader.read_element() {
+            Err(Error::Syntax(pos, msg)) => {
+                assert_eq!(pos, input.len());
+                assert_eq!(msg, "expected more, got EOF");
+            }
+            _ => panic!("Expected syntax error for type byte {:#X}", type_byte),
+        }
+    }
+}
+
+#[test]
+fn test_read_c_string() {
+    let input = vec![0b00011000u8, b'u', b'n', b'i', b'x', 0u8, 0b00011000u8, 0u8];
+    let mut reader = Reader::new(Cursor::new(input));
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::CString(String::from("unix")))
+    );
+    assert_eq!(
+        reader.read_element().unwrap(),
+        Some(Element::CString(String::new()))
+    );
+}
+
+#[test]
+fn test_read_c_string_without_terminator() {
+    let input = vec![0b00011000u8, b'u', b'n'];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 3));
+}
+
+#[test]
+fn test_entry_offsets() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&[0b10000001u8, b'a']);
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+
+    let offsets = Reader::new(Cursor::new(input.clone()))
+        .entry_offsets()
+        .unwrap();
+    assert_eq!(offsets, vec![0, 21, 23]);
+
+    let mut reader = Reader::new(Cursor::new(input));
+    reader.read_entry().unwrap();
+    assert_eq!(reader.entry_offsets().unwrap(), vec![21, 23]);
+}
+
+/// A non-blocking source returning one byte per read call, and blocking every other call.
+struct WouldBlockEveryOther<'a> {
+    data: &'a [u8],
+    block: bool,
+}
+
+impl std::io::Read for WouldBlockEveryOther<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.block = !self.block;
+        if self.block {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        ByteByByte(self.data).read(buf).inspect(|count| {
+            self.data = &self.data[*count..];
+        })
+    }
+}
+
+#[test]
+fn test_resumable_reader() {
+    let source = WouldBlockEveryOther {
+        data: &TIMESTAMP_ENTRY,
+        block: false,
+    };
+    let mut reader = ResumableReader::new(source);
+
+    let mut elements = vec![];
+    let mut pending = 0;
+    loop {
+        match reader.read_element().unwrap() {
+            ReadState::Ready(Some(element)) => elements.push(element),
+            ReadState::Ready(None) => break,
+            ReadState::Pending => pending += 1,
+        }
+    }
+
+    assert!(pending > 0);
+    assert_eq!(
+        elements,
+        vec![
+            Element::Marker(String::from("name")),
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+        ]
+    );
+}
+
+#[test]
+fn test_resumable_reader_keeps_configuration() {
+    let elements = vec![
+        Element::Marker(String::from("name")),
+        Element::Blob(vec![1u8, 2u8, 3u8]),
+        Element::Blob(vec![1u8, 2u8, 3u8]),
+    ];
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input).dedup_blobs(true);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+    }
+
+    let source = WouldBlockEveryOther {
+        data: &input,
+        block: false,
+    };
+    let mut reader = Reader::new(source).dedup_blobs(true).into_resumable();
+    let mut result = vec![];
+    loop {
+        match reader.read_element().unwrap() {
+            ReadState::Ready(Some(element)) => result.push(element),
+            ReadState::Ready(None) => break,
+            ReadState::Pending => (),
+        }
+    }
+    assert_eq!(result, elements);
+}
+
+#[test]
+fn test_resumable_reader_probes_with_configuration() {
+    // Padding does not exist in version 2, which is reported before the padding is received.
+    let mut input = vec![0b00011100u8, 200u8];
+    input.extend(std::iter::repeat_n(0u8, 200));
+    let source = WouldBlockEveryOther {
+        data: &input,
+        block: false,
+    };
+    let mut reader = Reader::new(source).version(2).into_resumable();
+    let mut pending = 0;
+    let result = loop {
+        match reader.read_element() {
+            Ok(ReadState::Pending) => pending += 1,
+            result => break result,
+        }
+    };
+    assert_eq!(pending, 1);
+    assert!(matches!(
+        result,
+        Err(Error::TypeNotInVersion { pos: 0, .. })
+    ));
+
+    // A large element received in small chunks is read once whole.
+    let mut input = vec![0b00010101u8];
+    input.extend_from_slice(&5000u16.to_le_bytes());
+    input.extend(std::iter::repeat_n(42u8, 5000));
+    let source = WouldBlockEveryOther {
+        data: &input,
+        block: false,
+    };
+    let mut reader = ResumableReader::new(source);
+    let mut pending = 0;
+    let element = loop {
+        match reader.read_element().unwrap() {
+            ReadState::Ready(element) => break element,
+            ReadState::Pending => pending += 1,
+        }
+    };
+    assert_eq!(pending, input.len());
+    assert_eq!(element, Some(Element::Blob(vec![42u8; 5000])));
+}
+
+#[test]
+fn test_resumable_reader_with_truncated_element() {
+    let input = vec![0b10000100u8, b'n', b'a'];
+    let mut reader = ResumableReader::new(Cursor::new(input));
+    match reader.read_element() {
+        Err(Error::Syntax(pos, _)) => assert_eq!(pos, 3),
+        _ => panic!("Expected syntax error"),
+    }
+}
+
+#[test]
+fn test_coalesce_strings() {
+    let mut input = vec![];
+    Writer::new(&mut input)
+        .write_entry(&Entry {
+            name: String::from("text"),
+            elements: vec![
+                Element::String(String::from("unix")),
+                Element::String(String::from("_time")),
+                Element::UInteger8(42),
+                Element::String(String::from("alone")),
+            ],
+        })
+        .unwrap();
+
+    let mut reader = Reader::new(Cursor::new(input)).coalesce_strings(true);
+    assert_eq!(
+        reader.read_entry().unwrap().unwrap().elements,
+        vec![
+            Element::String(String::from("unix_time")),
+            Element::UInteger8(42),
+            Element::String(String::from("alone")),
+        ]
+    );
+}
+
+#[test]
+fn test_read_manifest() {
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    writer
+        .write_entry(&Entry {
+            name: String::from("__schema__"),
+            elements: vec![Element::String(String::from("name:u32,str"))],
+        })
+        .unwrap();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice()));
+    assert_eq!(
+        reader.read_manifest().unwrap(),
+        Some(Entry {
+            name: String::from("__schema__"),
+            elements: vec![Element::String(String::from("name:u32,str"))],
+        })
+    );
+    let entry = reader.read_entry().unwrap().unwrap();
+    assert_eq!(entry.name, "name");
+    assert_eq!(reader.read_entry().unwrap(), None);
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice())).manifest_name("manifest");
+    assert_eq!(reader.read_manifest().unwrap(), None);
+    let entry = reader.read_entry().unwrap().unwrap();
+    assert_eq!(entry.name, "__schema__");
+}
+
+#[test]
+fn test_read_manifest_without_manifest() {
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+    assert_eq!(reader.read_manifest().unwrap(), None);
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+
+    let mut reader = Reader::new(Cursor::new(vec![]));
+    assert_eq!(reader.read_manifest().unwrap(), None);
+}
+
+#[test]
+fn test_read_chained_sources() {
+    let (first, second) = TIMESTAMP_ENTRY.split_at(8);
+    let mut reader = Reader::new(Cursor::new(first)).chain(Cursor::new(second));
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+    assert_eq!(reader.read_entry().unwrap(), None);
+
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    let (first, second) = input.split_at(30);
+    let mut reader = Reader::new(Cursor::new(first)).chain(Cursor::new(second));
+    assert_eq!(reader.entry_offsets().unwrap(), vec![0, 21]);
+
+    // Offsets of errors happening in the second source are relative to the first one.
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY)).chain(Cursor::new([0x7Cu8]));
+    match reader.read_entry() {
+        Err(Error::UnknownType(pos, _)) => assert_eq!(pos, 21),
+        _ => panic!("Expected unknown type error"),
+    }
+}
+
+#[test]
+fn test_read_unknown_blob_ref() {
+    let input = vec![0b01110000u8, 1u8, 4u8, 0u8, 0u8, 0u8, 0u8];
+    let mut reader = Reader::new(Cursor::new(input)).dedup_blobs(true);
+    match reader.read_element() {
+        Err(Error::Syntax(pos, _)) => assert_eq!(pos, 0),
+        _ => panic!("Expected syntax error"),
+    }
+}
+
+#[test]
+fn test_read_flagged_round_trip() {
+    let entry = Entry {
+        name: String::from("password"),
+        elements: vec![
+            Element::Flagged {
+                flags: 0b101,
+                inner: Box::new(Element::String(String::from("secret"))),
+            },
+            Element::UInteger8(42),
+        ],
+    };
+    let mut output = vec![];
+    Writer::new(&mut output).write_entry(&entry).unwrap();
+    assert_eq!(read_entry(&output).unwrap(), Some(entry));
+}
+
+#[test]
+fn test_read_flagged_marker() {
+    let input = vec![0b01110000u8, 2u8, 3u8, 0u8, 0b10000001u8, b'a'];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 4));
+
+    let input = vec![0b01110000u8, 2u8, 1u8, 0u8];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 4));
+}
+
+#[test]
+fn test_read_flagged_size_mismatch() {
+    let input = vec![0b01110000u8, 2u8, 1u8, 0u8, 0b00100000u8, 42u8];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 0));
+
+    // The inner blob cannot be larger than the payload of the flagged element.
+    let input = vec![0b01110000u8, 2u8, 3u8, 0u8, 0b00010100u8, 200u8];
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 6));
+}
+
+#[test]
+fn test_read_flagged_too_deep() {
+    // Payloads too small to hold the next header are rejected before going any deeper.
+    let input = [0b01110000u8, 2u8, 1u8, 0u8].repeat(1_000_000);
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 7));
+
+    // Valid payloads are rejected once the nesting limit is reached.
+    let mut headers = vec![];
+    let mut len = 2; // The innermost element is a UInteger8.
+    for _ in 0..100_000 {
+        let payload_len = 1 + len;
+        let mut header = match payload_len {
+            0..=0xFF => vec![0b01110000u8, 2u8, payload_len as u8],
+            0x100..=0xFFFF => [0b01110001u8, 2u8]
+                .into_iter()
+                .chain((payload_len as u16).to_le_bytes())
+                .collect(),
+            _ => [0b01110010u8, 2u8]
+                .into_iter()
+                .chain((payload_len as u32).to_le_bytes())
+                .collect(),
+        };
+        header.push(0u8);
+        len += header.len();
+        headers.push(header);
+    }
+    let mut input: Vec<u8> = headers.into_iter().rev().flatten().collect();
+    input.extend_from_slice(&[0b00100000u8, 42u8]);
+    read_element!(input fails with Error::TooDeep { limit, .. } => {
+        assert_eq!(limit, DEFAULT_MAX_DEPTH)
+    });
+}
+
+#[test]
+fn test_read_flagged_max_depth() {
+    let mut element = Element::UInteger8(42);
+    for flags in 0..3 {
+        element = Element::Flagged {
+            flags,
+            inner: Box::new(element),
+        };
+    }
+    let mut output = vec![];
+    Writer::new(&mut output).write_element(&element).unwrap();
+
+    let mut reader = Reader::new(output.as_slice()).max_depth(3);
+    assert_eq!(reader.read_element().unwrap(), Some(element));
+    let mut reader = Reader::new(output.as_slice()).max_depth(2);
+    assert!(matches!(
+        reader.read_element(),
+        Err(Error::TooDeep { pos: 8, limit: 2 })
+    ));
+}
+
+#[test]
+fn test_skip_flagged() {
+    let entries = [
+        Entry {
+            name: String::from("first"),
+            elements: vec![Element::Flagged {
+                flags: 1,
+                inner: Box::new(Element::Blob(vec![42u8; 300])),
+            }],
+        },
+        Entry {
+            name: String::from("second"),
+            elements: vec![Element::UInteger8(42)],
+        },
+    ];
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for entry in &entries {
+        writer.write_entry(entry).unwrap();
+    }
+
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.entry_offsets().unwrap(), vec![0, 314]);
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.nth_entry(1).unwrap(), Some(entries[1].clone()));
+}
+
+#[test]
+fn test_read_document_with_progress() {
+    let mut input = TIMESTAMP_ENTRY.to_vec();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    let mut reader = Reader::new(Cursor::new(input));
+
+    let mut progress = vec![];
+    let document = reader
+        .read_document_with_progress(|bytes_read| progress.push(bytes_read))
+        .unwrap();
+    assert_eq!(progress, vec![26, 42]);
+
+    let entry = Entry {
+        name: String::from("name"),
+        elements: vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+        ],
+    };
+    assert_eq!(document.entries, vec![entry.clone(), entry]);
+}
+
+#[test]
+#[cfg(target_pointer_width = "32")]
+fn test_read_blob_with_size_exceeding_address_space() {
+    let mut input = vec![0b00010111u8];
+    input.extend_from_slice(&(1u64 << 32).to_le_bytes());
+    input.extend_from_slice(&[42u8; 16]);
+    read_element!(input fails with Error::Syntax(pos, ..) => assert_eq!(pos, 0));
+
+    // The largest size representable on 32 bits still uses the 32-bit prefix.
+    let mut input = vec![0b00010110u8];
+    input.extend_from_slice(&u32::MAX.to_le_bytes());
+    let mut reader = Reader::new(Cursor::new(input)).input_len(5);
+    match reader.read_element() {
+        Err(Error::Syntax(pos, _)) => assert_eq!(pos, 5),
+        _ => panic!("Expected syntax error"),
+    }
+}
+
+#[test]
+fn test_read_if() {
+    let is_string = |element: &Element| matches!(element, Element::String(_));
+    let mut input = vec![0b00010000u8, 2u8, b'h', b'i'];
+    input.extend_from_slice(&[0b00100000u8, 42u8]);
+    let mut reader = Reader::new(Cursor::new(input));
+
+    assert_eq!(
+        reader.read_if(is_string).unwrap(),
+        Some(Element::String(String::from("hi")))
+    );
+    assert_eq!(reader.read_if(is_string).unwrap(), None);
+    assert_eq!(reader.read_element().unwrap(), Some(Element::UInteger8(42)));
+    assert_eq!(reader.read_if(is_string).unwrap(), None);
+}
+
+#[test]
+fn test_read_blob_into() {
+    let input = vec![
+        0b00010100u8,
+        3u8,
+        1u8,
+        2u8,
+        3u8,
+        0b00010100u8,
+        5u8,
+        1u8,
+        2u8,
+        3u8,
+        4u8,
+        5u8,
+        0b00100000u8,
+        42u8,
+    ];
+    let mut reader = Reader::new(Cursor::new(input));
+    let mut buf = [0u8; 4];
+
+    assert_eq!(reader.read_blob_into(&mut buf).unwrap(), 3);
+    assert_eq!(&buf[..3], [1u8, 2u8, 3u8].as_slice());
+    match reader.read_blob_into(&mut buf) {
+        Err(Error::BufferTooSmall { needed, have }) => {
+            assert_eq!(needed, 5);
+            assert_eq!(have, 4);
+        }
+        _ => panic!("Expected buffer too small error"),
+    }
+    match reader.read_blob_into(&mut buf) {
+        Err(Error::UnexpectedType { pos, byte }) => {
+            assert_eq!(pos, 12);
+            assert_eq!(byte, 0b00100000u8);
+        }
+        _ => panic!("Expected unexpected type error"),
+    }
+    assert_eq!(reader.read_element().unwrap(), Some(Element::UInteger8(42)));
+    assert!(matches!(reader.read_blob_into(&mut buf), Err(Error::Eof)));
+}
+
+#[test]
+fn test_read_blob_into_after_peek() {
+    let input = vec![0b00010100u8, 2u8, 1u8, 2u8, 0b00100000u8, 42u8];
+    let mut reader = Reader::new(Cursor::new(input));
+    let mut buf = [0u8; 2];
+
+    reader.peek_element().unwrap();
+    assert_eq!(reader.read_blob_into(&mut buf).unwrap(), 2);
+    assert_eq!(buf, [1u8, 2u8]);
+    reader.peek_element().unwrap();
+    match reader.read_blob_into(&mut buf) {
+        Err(Error::UnexpectedType { pos, .. }) => assert_eq!(pos, 4),
+        _ => panic!("Expected unexpected type error"),
+    }
+    assert_eq!(reader.read_element().unwrap(), Some(Element::UInteger8(42)));
+}
+
+#[test]
+fn test_read_blob_into_with_dedup_blobs() {
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input).dedup_blobs(true);
+    for _ in 0..3 {
+        writer.write_element(&Element::Blob(vec![1, 2, 3])).unwrap();
+    }
+    writer.write_element(&Element::UInteger8(42)).unwrap();
+    let mut reader = Reader::new(Cursor::new(input)).dedup_blobs(true);
+    let mut buf = [0u8; 2];
+
+    // The blob is skipped, but still remembered for the references following it.
+    assert!(matches!(
+        reader.read_blob_into(&mut buf),
+        Err(Error::BufferTooSmall { needed: 3, have: 2 })
+    ));
+    let mut buf = [0u8; 3];
+    assert_eq!(reader.read_blob_into(&mut buf).unwrap(), 3);
+    assert_eq!(buf, [1u8, 2u8, 3u8]);
+    reader.peek_element().unwrap();
+    assert_eq!(reader.read_blob_into(&mut buf).unwrap(), 3);
+    assert_eq!(buf, [1u8, 2u8, 3u8]);
+    assert!(matches!(
+        reader.read_blob_into(&mut buf),
+        Err(Error::UnexpectedType {
+            byte: 0b00100000,
+            ..
+        })
+    ));
+    assert_eq!(reader.read_element().unwrap(), Some(Element::UInteger8(42)));
+}
+
+#[test]
+fn test_read_blob_into_unresolved_ref() {
+    let input = vec![0b01110000u8, 1u8, 4u8, 0u8, 0u8, 0u8, 0u8];
+    let mut reader = Reader::new(Cursor::new(input));
+    let mut buf = [0u8; 4];
+
+    assert!(matches!(
+        reader.read_blob_into(&mut buf),
+        Err(Error::UnexpectedType {
+            pos: 0,
+            byte: 0b01110000
+        })
+    ));
+    assert_eq!(reader.read_element().unwrap(), Some(Element::BlobRef(0)));
+}
+
+#[test]
+fn test_read_scalar_array() {
+    let mut input = vec![];
+    Writer::new(&mut input)
+        .write_entry(&Entry {
+            name: String::from("points"),
+            elements: vec![
+                Element::Collection,
+                Element::UInteger32(1),
+                Element::UInteger32(2),
+                Element::UInteger32(1651906455),
+                Element::UInteger8(42),
+            ],
+        })
+        .unwrap();
+    let mut reader = Reader::new(Cursor::new(input));
+
+    reader.read_element().unwrap();
+    reader.read_element().unwrap();
+    assert_eq!(
+        reader.read_scalar_array::<u32>(3).unwrap(),
+        vec![1u32, 2, 1651906455]
+    );
+    match reader.read_scalar_array::<u32>(1) {
+        Err(Error::UnexpectedType { pos, byte }) => {
+            assert_eq!(pos, 23);
+            assert_eq!(byte, 0b00100000u8);
+        }
+        _ => panic!("Expected unexpected type error"),
+    }
+    reader.peek_element().unwrap();
+    assert_eq!(reader.read_scalar_array::<u8>(1).unwrap(), vec![42u8]);
+    assert!(matches!(reader.read_scalar_array::<u8>(1), Err(Error::Eof)));
+    assert_eq!(
+        reader.read_scalar_array::<f64>(0).unwrap(),
+        Vec::<f64>::new()
+    );
+}
+
+#[test]
+fn test_read_scalar_array_with_huge_count() {
+    let mut input = vec![0b00101000u8];
+    input.extend_from_slice(&u128::MAX.to_le_bytes());
+    let mut reader = Reader::new(Cursor::new(input));
+
+    assert!(matches!(
+        reader.read_scalar_array::<u128>(usize::MAX),
+        Err(Error::Eof)
+    ));
+}
+
+#[test]
+fn test_read_typed_collection() {
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    writer
+        .write_element(&Element::Marker(String::from("points")))
+        .unwrap();
+    writer.write_typed_collection(0b00100010u8, 3).unwrap();
+    for value in [1u32, 2, 1651906455] {
+        writer.write_element(&value.into()).unwrap();
+    }
+    let mut reader = Reader::new(Cursor::new(input));
+
+    match reader.read_typed_collection() {
+        Err(Error::UnexpectedType { pos, byte }) => {
+            assert_eq!(pos, 0);
+            assert_eq!(byte, 0b10000110u8);
+        }
+        _ => panic!("Expected unexpected type error"),
+    }
+    reader.read_element().unwrap();
+    let (type_byte, count) = reader.read_typed_collection().unwrap();
+    assert_eq!(type_byte, 0b00100010u8);
+    assert_eq!(
+        reader.read_scalar_array::<u32>(count as usize).unwrap(),
+        vec![1u32, 2, 1651906455]
+    );
+    assert!(matches!(reader.read_typed_collection(), Err(Error::Eof)));
+}
+
+#[test]
+fn test_read_invalid_typed_collection() {
+    let header = |type_byte: u8, count: u32| {
+        let mut header = vec![0b01110000u8, 5u8, 5u8, type_byte];
+        header.extend_from_slice(&count.to_le_bytes());
+        header
+    };
+    let cases = [
+        (header(0b00010100, 0), 0),
+        ([header(0b00100000, 2), vec![0b00100000, 1]].concat(), 10),
+        ([header(0b00100000, 1), vec![0b00100001, 1, 0]].concat(), 8),
+        ([header(0b00100000, 1), vec![0b10000001, b'a']].concat(), 8),
+        ([header(0b00100000, 0), vec![0b00100000, 1]].concat(), 8),
+    ];
+
+    for (input, expected_pos) in cases {
+        // Lenient readers let the elements through as they are.
+        let mut reader = Reader::new(Cursor::new(&input));
+        while reader.read_element().unwrap().is_some() {}
+
+        let mut reader = Reader::new(Cursor::new(&input)).strict(true);
+        let result = std::iter::from_fn(|| reader.read_element().transpose()).find(Result::is_err);
+        match result {
+            Some(Err(Error::InvalidTypedCollection { pos })) => assert_eq!(pos, expected_pos),
+            _ => panic!("Expected invalid typed collection error"),
+        }
+    }
+}
+
+#[test]
+fn test_read_entry_with_containers_breaking_entries() {
+    let input = vec![
+        0b10000001u8,
+        b'a',
+        0b00100000u8,
+        42u8,
+        0b00000000u8,
+        0b10000001u8,
+        b'b',
+        0b00000001u8,
+        0b00100000u8,
+        1u8,
+    ];
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice()));
+    let entry = reader.read_entry().unwrap().unwrap();
+    assert_eq!(
+        entry.elements,
+        vec![Element::UInteger8(42), Element::Folder]
+    );
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice())).containers_break_entries(true);
+    assert_eq!(
+        reader.read_document().unwrap().entries,
+        vec![
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::UInteger8(42)],
+            },
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::Folder],
+            },
+            Entry {
+                name: String::from("b"),
+                elements: vec![Element::Collection, Element::UInteger8(1)],
+            },
+        ]
+    );
+
+    let mut reader = Reader::new(Cursor::new(input.as_slice())).containers_break_entries(true);
+    assert_eq!(
+        reader.nth_entry(1).unwrap(),
+        Some(Entry {
+            name: String::from("a"),
+            elements: vec![Element::Folder],
+        })
+    );
+}
+
+#[test]
+fn test_detect_endianness() {
+    let mut input = ENDIANNESS_MAGIC.to_be_bytes().to_vec();
+    // Marker - "name"
+    input.extend_from_slice(&[0b10000100u8, b'n', b'a', b'm', b'e']);
+    // UInteger32, big-endian
+    input.push(0b00100010u8);
+    input.extend_from_slice(&1651906455u32.to_be_bytes());
+    // Integer16, big-endian
+    input.push(0b00100101u8);
+    input.extend_from_slice(&(-300i16).to_be_bytes());
+    // String with a big-endian 16-bit size
+    input.push(0b00010001u8);
+    input.extend_from_slice(&9u16.to_be_bytes());
+    input.extend_from_slice(b"unix_time");
+
+    let mut reader = Reader::new(Cursor::new(input)).detect_endianness().unwrap();
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::Integer16(-300),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+    reader.expect_eof().unwrap();
+
+    let mut input = ENDIANNESS_MAGIC.to_le_bytes().to_vec();
+    input.extend_from_slice(&TIMESTAMP_ENTRY);
+    let mut reader = Reader::new(Cursor::new(input)).detect_endianness().unwrap();
+    assert_eq!(
+        reader.read_entry().unwrap().unwrap().elements[0],
+        Element::UInteger32(1651906455)
+    );
+
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY))
+        .detect_endianness()
+        .unwrap();
+    assert_eq!(
+        reader.read_entry().unwrap().unwrap().elements[0],
+        Element::UInteger32(1651906455)
+    );
+}
+
+#[test]
+fn test_detect_endianness_with_extensions() {
+    let mut input = ENDIANNESS_MAGIC.to_be_bytes().to_vec();
+    input.extend_from_slice(&[0b01110000u8, 3u8, 8u8]);
+    input.extend_from_slice(&3_000_000_250i64.to_be_bytes());
+    input.extend_from_slice(&[0b01110000u8, 1u8, 4u8]);
+    input.extend_from_slice(&1u32.to_be_bytes());
+    input.extend_from_slice(&[0b01110000u8, 7u8, 4u8]);
+    input.extend_from_slice(&2u32.to_be_bytes());
+    input.extend_from_slice(&[0b01110000u8, 6u8, 9u8]);
+    input.extend_from_slice(&1u32.to_be_bytes());
+    input.extend_from_slice(&[0b00010000u8, 1u8, b'k', 0b00100000u8, 7u8]);
+    input.extend_from_slice(&[0b01110000u8, 5u8, 5u8, 0b00100000u8]);
+    input.extend_from_slice(&1u32.to_be_bytes());
+    input.extend_from_slice(&[0b00100000u8, 42u8]);
+
+    let mut reader = Reader::new(Cursor::new(input))
+        .strict(true)
+        .detect_endianness()
+        .unwrap();
+    let mut elements = vec![];
+    while let Some(element) = reader.read_element().unwrap() {
+        elements.push(element);
+    }
+    assert_eq!(
+        elements,
+        vec![
+            Element::Duration(3_000_000_250),
+            Element::BlobRef(1),
+            Element::StringRef(2),
+            Element::Map(vec![(String::from("k"), Element::UInteger8(7))]),
+            Element::TypedCollection {
+                type_byte: 0b00100000,
+                count: 1
+            },
+            Element::UInteger8(42),
+        ]
+    );
+}
+
+#[test]
+fn test_read_footer() {
+    let entries = vec![
+        Entry {
+            name: String::from("first"),
+            elements: vec![Element::Blob(vec![42u8; 300])],
+        },
+        Entry {
+            name: String::from("second"),
+            elements: vec![Element::String(String::from("found"))],
+        },
+    ];
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    let mut index = vec![];
+    let mut offset = 0;
+    for entry in &entries {
+        index.push((entry.name.clone(), offset as u64));
+        writer.write_entry(entry).unwrap();
+        offset += entry.encoded_len();
+    }
+    writer.write_footer(&index).unwrap();
+
+    let mut cursor = Cursor::new(output.as_slice());
+    let mut reader = Reader::new(&mut cursor);
+    let footer = reader.read_footer().unwrap().unwrap();
+    assert_eq!(footer, index);
+    assert_eq!(reader.read_entry().unwrap().as_ref(), Some(&entries[0]));
+
+    let (_, offset) = footer.iter().find(|(name, _)| name == "second").unwrap();
+    cursor.set_position(*offset);
+    let mut reader = Reader::new(&mut cursor);
+    assert_eq!(reader.read_entry().unwrap().as_ref(), Some(&entries[1]));
+    assert_eq!(reader.read_entry().unwrap().unwrap().name, "__footer__");
+}
+
+#[test]
+fn test_entries_reversed() {
+    let entries = vec![
+        Entry {
+            name: String::from("first"),
+            elements: vec![Element::Blob(vec![42u8; 300])],
+        },
+        Entry {
+            name: String::from("second"),
+            elements: vec![Element::String(String::from("found"))],
+        },
+        Entry {
+            name: String::from("third"),
+            elements: vec![Element::UInteger8(3), Element::Boolean(true)],
+        },
+    ];
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    let mut index = vec![];
+    let mut offset = 0;
+    for entry in &entries {
+        index.push((entry.name.clone(), offset as u64));
+        writer.write_entry(entry).unwrap();
+        offset += entry.encoded_len();
+    }
+    writer.write_footer(&index).unwrap();
+
+    let mut reader = Reader::new(Cursor::new(output));
+    let reversed = reader
+        .entries_reversed()
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(reversed, entries.into_iter().rev().collect::<Vec<_>>());
+
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+    assert!(matches!(
+        reader.entries_reversed().err(),
+        Some(Error::MissingFooter)
+    ));
+}
+
+#[test]
+fn test_read_footer_without_footer() {
+    let mut reader = Reader::new(Cursor::new(TIMESTAMP_ENTRY));
+    assert_eq!(reader.read_footer().unwrap(), None);
+    assert_eq!(reader.read_entry().unwrap().unwrap().name, "name");
+
+    let mut reader = Reader::new(Cursor::new(vec![0b00100000u8]));
+    assert_eq!(reader.read_footer().unwrap(), None);
+}
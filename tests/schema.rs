@@ -0,0 +1,69 @@
+use tpk::schema::{EntrySchema, SchemaError};
+use tpk::{Element, ElementKind, Entry};
+
+#[test]
+fn test_entry_matches() {
+    let schema = EntrySchema::new()
+        .one(ElementKind::UInteger32)
+        .one(ElementKind::String);
+    let entry = Entry {
+        name: String::from("timestamp"),
+        elements: vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+        ],
+    };
+    assert_eq!(entry.matches(&schema), Ok(()));
+
+    let reordered = Entry {
+        name: String::from("timestamp"),
+        elements: vec![
+            Element::String(String::from("unix_time")),
+            Element::UInteger32(1651906455),
+        ],
+    };
+    assert_eq!(
+        reordered.matches(&schema),
+        Err(SchemaError::Mismatch {
+            index: 0,
+            expected: ElementKind::UInteger32,
+            found: ElementKind::String,
+        })
+    );
+}
+
+#[test]
+fn test_entry_matches_optional_and_repeated() {
+    let schema = EntrySchema::new()
+        .one(ElementKind::Collection)
+        .repeated(ElementKind::String)
+        .optional(ElementKind::Boolean);
+    let mut entry = Entry {
+        name: String::from("tags"),
+        elements: vec![Element::Collection],
+    };
+    assert_eq!(entry.matches(&schema), Ok(()));
+
+    entry.elements.push(Element::String(String::from("alpha")));
+    entry.elements.push(Element::String(String::from("beta")));
+    entry.elements.push(Element::Boolean(true));
+    assert_eq!(entry.matches(&schema), Ok(()));
+
+    entry.elements.push(Element::UInteger8(42));
+    assert_eq!(
+        entry.matches(&schema),
+        Err(SchemaError::Unexpected {
+            index: 4,
+            found: ElementKind::UInteger8,
+        })
+    );
+
+    entry.elements.clear();
+    assert_eq!(
+        entry.matches(&schema),
+        Err(SchemaError::Missing {
+            index: 0,
+            expected: ElementKind::Collection,
+        })
+    );
+}
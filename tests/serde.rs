@@ -0,0 +1,287 @@
+#![cfg(feature = "serde")]
+
+use serde::{Deserialize, Serialize};
+use tpk::{Element, Entry, Reader, Writer};
+
+const TIMESTAMP_ENTRY: [u8; 21] = [
+    0b10000100u8,
+    b'n',
+    b'a',
+    b'm',
+    b'e',
+    0b00100010u8,
+    0b10010111u8,
+    0b00010111u8,
+    0b01110110u8,
+    0b01100010u8,
+    0b00010000u8,
+    0b00001001u8,
+    b'u',
+    b'n',
+    b'i',
+    b'x',
+    b'_',
+    b't',
+    b'i',
+    b'm',
+    b'e',
+];
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Timestamp {
+    name: (u32, String),
+}
+
+#[test]
+fn test_deserialize_timestamp_entry() {
+    let timestamp: Timestamp = tpk::de::from_reader(TIMESTAMP_ENTRY.as_slice()).unwrap();
+    assert_eq!(
+        timestamp,
+        Timestamp {
+            name: (1651906455, String::from("unix_time"))
+        }
+    );
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Version {
+    major: u8,
+    minor: u16,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Package {
+    format: String,
+    version: Version,
+    tags: Vec<String>,
+    checksum: Vec<u8>,
+    stable: bool,
+    license: Option<String>,
+}
+
+#[test]
+fn test_deserialize_nested_struct() {
+    let entries = vec![
+        Entry {
+            name: String::from("format"),
+            elements: vec![Element::String(String::from("TPK"))],
+        },
+        Entry {
+            name: String::from("version"),
+            elements: vec![Element::Folder],
+        },
+        Entry {
+            name: String::from("major"),
+            elements: vec![Element::UInteger8(0)],
+        },
+        Entry {
+            name: String::from("minor"),
+            elements: vec![Element::UInteger8(1)],
+        },
+        Entry {
+            name: String::from(".."),
+            elements: vec![],
+        },
+        Entry {
+            name: String::from("tags"),
+            elements: vec![
+                Element::Collection,
+                Element::String(String::from("alpha")),
+                Element::String(String::from("unstable")),
+            ],
+        },
+        Entry {
+            name: String::from("checksum"),
+            elements: vec![Element::Blob(vec![1u8, 2u8, 3u8])],
+        },
+        Entry {
+            name: String::from("stable"),
+            elements: vec![Element::Boolean(false)],
+        },
+    ];
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    for entry in &entries {
+        writer.write_entry(entry).unwrap();
+    }
+
+    let package: Package = tpk::de::from_reader(input.as_slice()).unwrap();
+    assert_eq!(
+        package,
+        Package {
+            format: String::from("TPK"),
+            version: Version { major: 0, minor: 1 },
+            tags: vec![String::from("alpha"), String::from("unstable")],
+            checksum: vec![1u8, 2u8, 3u8],
+            stable: false,
+            license: None,
+        }
+    );
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Channel {
+    Stable,
+    Nightly(u32),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Release {
+    name: String,
+    version: Version,
+    timestamp: (u32, String),
+    tags: Vec<String>,
+    downloads: u64,
+    offset: i32,
+    ratio: f64,
+    stable: bool,
+    license: Option<String>,
+    homepage: Option<String>,
+    channel: Channel,
+    previous: Channel,
+}
+
+#[test]
+fn test_serialize_round_trip() {
+    let release = Release {
+        name: String::from("First Development Release"),
+        version: Version { major: 0, minor: 1 },
+        timestamp: (1651906455, String::from("unix_time")),
+        tags: vec![String::from("alpha"), String::from("unstable")],
+        downloads: 42,
+        offset: -300,
+        ratio: 1.5,
+        stable: false,
+        license: Some(String::from("MIT")),
+        homepage: None,
+        channel: Channel::Nightly(1337),
+        previous: Channel::Stable,
+    };
+
+    let mut output = vec![];
+    tpk::ser::to_writer(&release, &mut output).unwrap();
+    let document = Reader::new(output.as_slice()).read_document().unwrap();
+    assert_eq!(
+        document.entries[7],
+        Entry {
+            name: String::from("downloads"),
+            elements: vec![Element::UInteger8(42)],
+        }
+    );
+
+    let result: Release = tpk::de::from_reader(output.as_slice()).unwrap();
+    assert_eq!(result, release);
+}
+
+#[test]
+fn test_serialize_non_struct() {
+    let mut output = vec![];
+    assert!(tpk::ser::to_writer(&42u32, &mut output).is_err());
+    assert!(output.is_empty());
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Optional {
+    tags: Option<Vec<String>>,
+}
+
+#[test]
+fn test_serialize_option_round_trip() {
+    for optional in [
+        Optional { tags: None },
+        Optional {
+            tags: Some(vec![String::from("alpha")]),
+        },
+    ] {
+        let mut output = vec![];
+        tpk::ser::to_writer(&optional, &mut output).unwrap();
+        let result: Optional = tpk::de::from_reader(output.as_slice()).unwrap();
+        assert_eq!(result, optional);
+    }
+
+    // An empty collection would be read back as `None`.
+    let mut output = vec![];
+    let optional = Optional { tags: Some(vec![]) };
+    assert!(matches!(
+        tpk::ser::to_writer(&optional, &mut output),
+        Err(tpk::ser::Error::Custom(_))
+    ));
+    assert!(output.is_empty());
+}
+
+fn write_entries(entries: &[Entry]) -> Vec<u8> {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for entry in entries {
+        writer.write_entry(entry).unwrap();
+    }
+    output
+}
+
+fn entry(name: &str, elements: Vec<Element>) -> Entry {
+    Entry {
+        name: String::from(name),
+        elements,
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Nested {
+    version: Version,
+    name: String,
+}
+
+#[test]
+fn test_deserialize_streamed_folders() {
+    // Unknown entries are skipped along with their folders, and `/` closes every folder.
+    let input = write_entries(&[
+        entry("version", vec![Element::Folder]),
+        entry("major", vec![Element::UInteger8(0)]),
+        entry("extra", vec![Element::Folder]),
+        entry("minor", vec![Element::UInteger8(2)]),
+        entry("..", vec![]),
+        entry("minor", vec![Element::UInteger16(1)]),
+        entry("/", vec![]),
+        entry("..", vec![]),
+        entry("name", vec![Element::String(String::from("TPK"))]),
+    ]);
+    let nested: Nested = tpk::de::from_reader(input.as_slice()).unwrap();
+    assert_eq!(
+        nested,
+        Nested {
+            version: Version { major: 0, minor: 1 },
+            name: String::from("TPK"),
+        }
+    );
+}
+
+#[test]
+fn test_deserialize_too_deep() {
+    let mut entries = vec![entry("version", vec![Element::Folder])];
+    for _ in 0..1000 {
+        entries.push(entry("extra", vec![Element::Folder]));
+    }
+    let input = write_entries(&entries);
+    let result: tpk::de::Result<Nested> = tpk::de::from_reader(input.as_slice());
+    assert!(matches!(
+        result,
+        Err(tpk::de::Error::Read(tpk::read::Error::TooDeep {
+            limit: tpk::read::DEFAULT_MAX_DEPTH,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_deserialize_trailing_elements() {
+    let input = write_entries(&[entry(
+        "name",
+        vec![
+            Element::UInteger32(1651906455),
+            Element::String(String::from("unix_time")),
+            Element::Boolean(true),
+        ],
+    )]);
+    let result: tpk::de::Result<Timestamp> = tpk::de::from_reader(input.as_slice());
+    assert!(matches!(result, Err(tpk::de::Error::Custom(_))));
+}
@@ -0,0 +1,61 @@
+use tpk::stats::KindStats;
+use tpk::{Document, Element, ElementKind, Entry, Reader, Writer};
+
+#[test]
+fn test_statistics() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("name"),
+                elements: vec![
+                    Element::UInteger32(1651906455),
+                    Element::String(String::from("unix_time")),
+                ],
+            },
+            Entry {
+                name: String::from("data"),
+                elements: vec![
+                    Element::Collection,
+                    Element::Blob(vec![42u8; 500]),
+                    Element::Blob(vec![42u8; 10]),
+                ],
+            },
+        ],
+    };
+    let mut input = vec![];
+    Writer::new(&mut input).write_document(&document).unwrap();
+
+    let stats = Reader::new(input.as_slice()).statistics().unwrap();
+    assert_eq!(
+        stats.get(ElementKind::Marker),
+        KindStats {
+            count: 2,
+            bytes: 10
+        }
+    );
+    assert_eq!(
+        stats.get(ElementKind::UInteger32),
+        KindStats { count: 1, bytes: 5 }
+    );
+    assert_eq!(
+        stats.get(ElementKind::String),
+        KindStats {
+            count: 1,
+            bytes: 11
+        }
+    );
+    assert_eq!(
+        stats.get(ElementKind::Collection),
+        KindStats { count: 1, bytes: 1 }
+    );
+    assert_eq!(
+        stats.get(ElementKind::Blob),
+        KindStats {
+            count: 2,
+            bytes: 515
+        }
+    );
+    assert_eq!(stats.get(ElementKind::Boolean), KindStats::default());
+    assert_eq!(stats.total().bytes, input.len());
+    assert_eq!(stats.total().count, 7);
+}
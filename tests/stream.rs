@@ -0,0 +1,121 @@
+use std::io::Cursor;
+use tpk::stream::{rename_markers, transform};
+use tpk::{Element, Entry, Reader, Writer};
+
+fn write_entries(entries: &[Entry]) -> Vec<u8> {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for entry in entries {
+        writer.write_entry(entry).unwrap();
+    }
+    output
+}
+
+fn read_entries(input: &[u8]) -> Vec<Entry> {
+    let mut reader = Reader::new(Cursor::new(input));
+    let mut entries = vec![];
+    while let Some(entry) = reader.read_entry().unwrap() {
+        entries.push(entry);
+    }
+    entries
+}
+
+#[test]
+fn test_transform_redacts_strings() {
+    let input = write_entries(&[
+        Entry {
+            name: "user".into(),
+            elements: vec![Element::String("alice".into()), Element::UInteger8(42)],
+        },
+        Entry {
+            name: "password".into(),
+            elements: vec![Element::String("hunter2".into())],
+        },
+    ]);
+
+    let mut output = vec![];
+    transform(Cursor::new(input), &mut output, |element| match element {
+        Element::String(_) => Some(Element::String("***".into())),
+        _ => Some(element),
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_entries(&output),
+        vec![
+            Entry {
+                name: "user".into(),
+                elements: vec![Element::String("***".into()), Element::UInteger8(42)],
+            },
+            Entry {
+                name: "password".into(),
+                elements: vec![Element::String("***".into())],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_transform_drops_elements() {
+    let input = write_entries(&[Entry {
+        name: "data".into(),
+        elements: vec![Element::Blob(vec![1, 2, 3]), Element::Boolean(true)],
+    }]);
+
+    let mut output = vec![];
+    transform(Cursor::new(input), &mut output, |element| match element {
+        Element::Blob(_) => None,
+        _ => Some(element),
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_entries(&output),
+        vec![Entry {
+            name: "data".into(),
+            elements: vec![Element::Boolean(true)],
+        }]
+    );
+}
+
+#[test]
+fn test_rename_markers() {
+    let mut input = write_entries(&[
+        Entry {
+            name: "user".into(),
+            elements: vec![Element::String("alice".into()), Element::UInteger8(42)],
+        },
+        Entry {
+            name: "password".into(),
+            elements: vec![Element::String("hunter2".into())],
+        },
+    ]);
+    // A non-minimal encoding of 42, which must be copied as is.
+    input.extend_from_slice(&[0b10000001u8, b'n', 0b00100011u8, 42, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut output = vec![];
+    rename_markers(Cursor::new(input.as_slice()), &mut output, |name| {
+        format!("app/{name}")
+    })
+    .unwrap();
+
+    assert_eq!(output.len(), input.len() + 3 * 4);
+    assert_eq!(&output[output.len() - 9..], &input[input.len() - 9..]);
+    assert_eq!(
+        read_entries(&output),
+        vec![
+            Entry {
+                name: "app/user".into(),
+                elements: vec![Element::String("alice".into()), Element::UInteger8(42)],
+            },
+            Entry {
+                name: "app/password".into(),
+                elements: vec![Element::String("hunter2".into())],
+            },
+            Entry {
+                name: "app/n".into(),
+                elements: vec![Element::UInteger64(42)],
+            },
+        ]
+    );
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "testing")]
+
+use tpk::testing::{blob_header, extension_header, marker_header, number_type_byte, string_header};
+use tpk::{Element, ElementKind, Writer};
+
+fn encode(element: &Element) -> Vec<u8> {
+    let mut output = vec![];
+    Writer::new(&mut output).write_element(element).unwrap();
+    output
+}
+
+#[test]
+fn test_headers_match_written_elements() {
+    for len in [0usize, 4, 63, 64, 987654] {
+        let element = Element::Marker(String::from_iter(std::iter::repeat_n('a', len)));
+        let header = marker_header(len);
+        assert_eq!(header[0], element.get_type_byte());
+        assert_eq!(encode(&element)[..header.len()], header);
+    }
+
+    for len in [0usize, 255, 256, 70000] {
+        let element = Element::String(String::from_iter(std::iter::repeat_n('a', len)));
+        assert_eq!(
+            encode(&element)[..],
+            [string_header(len), vec![b'a'; len]].concat()
+        );
+
+        let element = Element::Blob(vec![42u8; len]);
+        assert_eq!(
+            encode(&element)[..],
+            [blob_header(len), vec![42u8; len]].concat()
+        );
+
+        let element = Element::Extension {
+            id: 200,
+            data: vec![42u8; len],
+        };
+        assert_eq!(
+            encode(&element)[..],
+            [extension_header(200, len), vec![42u8; len]].concat()
+        );
+    }
+}
+
+#[test]
+fn test_number_type_bytes() {
+    let elements = vec![
+        Element::UInteger8(0),
+        Element::UInteger16(0),
+        Element::UInteger32(0),
+        Element::UInteger64(0),
+        Element::Integer8(0),
+        Element::Integer16(0),
+        Element::Integer32(0),
+        Element::Integer64(0),
+        Element::Float32(0.0),
+        Element::Float64(0.0),
+    ];
+    for element in elements {
+        assert_eq!(
+            number_type_byte(element.kind()),
+            Some(element.get_type_byte())
+        );
+    }
+    assert_eq!(number_type_byte(ElementKind::String), None);
+}
@@ -0,0 +1,130 @@
+use std::io::Cursor;
+use tpk::text::{parse_text, to_text, Error};
+use tpk::{Element, Entry, Reader, Writer};
+
+const TIMESTAMP_TEXT: &str = r#"marker("name"); u32(1651906455); str("unix_time")"#;
+
+#[test]
+fn test_text_timestamp_round_trip() {
+    let elements = parse_text(TIMESTAMP_TEXT).unwrap();
+    assert_eq!(to_text(&elements), TIMESTAMP_TEXT);
+
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+    }
+    assert_eq!(
+        Reader::new(Cursor::new(output)).read_entry().unwrap(),
+        Some(Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::UInteger32(1651906455),
+                Element::String(String::from("unix_time")),
+            ],
+        })
+    );
+}
+
+#[test]
+fn test_text_all_types_round_trip() {
+    let elements = vec![
+        Element::Marker(String::from("quoted \"name\"\n")),
+        Element::Folder,
+        Element::Collection,
+        Element::Integer8(-8),
+        Element::Integer16(-16),
+        Element::Integer32(-32),
+        Element::Integer64(i64::MIN),
+        Element::Integer128(i128::MIN),
+        Element::UInteger8(8),
+        Element::UInteger16(16),
+        Element::UInteger32(32),
+        Element::UInteger64(u64::MAX),
+        Element::UInteger128(u128::MAX),
+        Element::Float32(0.1),
+        Element::Float64(-1e-300),
+        Element::Float64(f64::INFINITY),
+        Element::Boolean(true),
+        Element::String(String::from("tab\t€ \u{1b}")),
+        Element::Blob(vec![0x00, 0x2a, 0xff]),
+        Element::Blob(vec![]),
+        Element::CString(String::from("unix")),
+        Element::Extension {
+            id: 200,
+            data: vec![1, 2],
+        },
+        Element::DeltaU64(vec![1, 2, 3]),
+        Element::DeltaU64(vec![]),
+        Element::BlobRef(0),
+        Element::StringRef(3),
+        Element::Flagged {
+            flags: 1,
+            inner: Box::new(Element::UInteger8(42)),
+        },
+        Element::Duration(-5),
+        Element::Padding(16),
+        Element::TypedCollection {
+            type_byte: 0b00100010u8,
+            count: 3,
+        },
+        Element::Map(vec![
+            (String::from("a"), Element::UInteger8(1)),
+            (String::from("b"), Element::Map(vec![])),
+        ]),
+    ];
+
+    assert_eq!(parse_text(&to_text(&elements)).unwrap(), elements);
+}
+
+#[test]
+fn test_parse_text_whitespace() {
+    let text = "\n  marker( \"a\" ) ;\n  ext( 1 , 2a ) ;\n  delta_u64( 1 ,2 );\n";
+    assert_eq!(
+        parse_text(text).unwrap(),
+        vec![
+            Element::Marker(String::from("a")),
+            Element::Extension {
+                id: 1,
+                data: vec![0x2a],
+            },
+            Element::DeltaU64(vec![1, 2]),
+        ]
+    );
+    assert_eq!(parse_text("  ").unwrap(), vec![]);
+}
+
+#[test]
+fn test_parse_text_errors() {
+    assert_eq!(
+        parse_text("u8(300)"),
+        Err(Error::InvalidValue {
+            pos: 3,
+            name: String::from("u8"),
+        })
+    );
+    assert_eq!(
+        parse_text("blob(2a2)"),
+        Err(Error::InvalidValue {
+            pos: 5,
+            name: String::from("blob"),
+        })
+    );
+    assert_eq!(
+        parse_text("u8(1); u65(1)"),
+        Err(Error::UnknownType {
+            pos: 7,
+            name: String::from("u65"),
+        })
+    );
+    assert!(matches!(
+        parse_text("u8(1) u8(2)"),
+        Err(Error::Syntax(6, _))
+    ));
+    assert!(matches!(parse_text("str(\"abc"), Err(Error::Syntax(8, _))));
+    assert!(matches!(
+        parse_text("str(\"\\q\")"),
+        Err(Error::Syntax(5, _))
+    ));
+    assert!(matches!(parse_text("u8()"), Err(Error::Syntax(3, _))));
+}
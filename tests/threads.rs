@@ -0,0 +1,106 @@
+use std::io::Cursor;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use tpk::{Document, Element, Entry, FramedReader, Reader, SliceReader, Writer};
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn test_types_are_send_and_sync() {
+    assert_send::<Reader<Cursor<Vec<u8>>>>();
+    assert_sync::<Reader<Cursor<Vec<u8>>>>();
+    assert_send::<FramedReader<Cursor<Vec<u8>>>>();
+    assert_send::<SliceReader<'static>>();
+    assert_send::<Writer<Vec<u8>>>();
+    assert_sync::<Writer<Vec<u8>>>();
+    assert_send::<Element>();
+    assert_sync::<Element>();
+    assert_send::<Entry>();
+    assert_sync::<Entry>();
+    assert_send::<Document>();
+    assert_sync::<Document>();
+}
+
+#[test]
+fn test_share_reader_between_threads() {
+    let mut input = vec![];
+    let mut writer = Writer::new(&mut input);
+    for i in 0..8u8 {
+        writer
+            .write_entry(&Entry {
+                name: String::from("value"),
+                elements: vec![Element::UInteger8(i)],
+            })
+            .unwrap();
+    }
+
+    let reader = Reader::new(Cursor::new(input))
+        .register_extension(200, Box::new(|data| Ok(Element::Blob(data.to_vec()))));
+    let reader = Arc::new(Mutex::new(reader));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let reader = Arc::clone(&reader);
+            thread::spawn(move || {
+                let mut entries = 0;
+                while reader.lock().unwrap().read_entry().unwrap().is_some() {
+                    entries += 1;
+                }
+                entries
+            })
+        })
+        .collect();
+
+    let entries: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(entries, 8);
+}
+
+#[test]
+fn test_stream_entries_to_channel() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("name"),
+                elements: vec![Element::UInteger32(1651906455)],
+            },
+            Entry {
+                name: String::from("format"),
+                elements: vec![Element::String(String::from("unix_time"))],
+            },
+        ],
+    };
+    let mut input = vec![];
+    Writer::new(&mut input).write_document(&document).unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let handle = Reader::new(Cursor::new(input)).stream_entries_to(sender);
+    let entries = receiver
+        .iter()
+        .collect::<tpk::read::Result<Vec<_>>>()
+        .unwrap();
+    handle.join().unwrap();
+    assert_eq!(entries, document.entries);
+}
+
+#[test]
+fn test_stream_entries_to_channel_with_error() {
+    let input = vec![
+        0b10000100u8,
+        b'n',
+        b'a',
+        b'm',
+        b'e',
+        0b00010000u8,
+        4u8,
+        b't',
+    ];
+
+    let (sender, receiver) = mpsc::channel();
+    Reader::new(Cursor::new(input))
+        .stream_entries_to(sender)
+        .join()
+        .unwrap();
+    let results: Vec<_> = receiver.iter().collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
@@ -0,0 +1,105 @@
+use tpk::value::Value;
+use tpk::{Document, Element, Entry};
+
+fn entry(name: &str, elements: Vec<Element>) -> Entry {
+    Entry {
+        name: name.into(),
+        elements,
+    }
+}
+
+#[test]
+fn test_element_to_value() {
+    assert_eq!(Value::from(Element::Integer16(-42)), Value::Int(-42));
+    assert_eq!(Value::from(Element::UInteger32(42)), Value::UInt(42));
+    assert_eq!(Value::from(Element::Float32(1.5)), Value::Float(1.5));
+    assert_eq!(Value::from(Element::Boolean(true)), Value::Bool(true));
+    assert_eq!(
+        Value::from(Element::String("TPK".into())),
+        Value::Str("TPK".into())
+    );
+    assert_eq!(
+        Value::from(Element::Blob(vec![1, 2, 3])),
+        Value::Bytes(vec![1, 2, 3])
+    );
+}
+
+#[test]
+fn test_document_to_value() {
+    let document = Document {
+        entries: vec![
+            entry("format", vec![Element::String("TPK".into())]),
+            entry("version", vec![Element::Folder]),
+            entry(
+                "name",
+                vec![Element::String("First Development Release".into())],
+            ),
+            entry("numbers", vec![Element::Folder]),
+            entry("major", vec![Element::UInteger8(0)]),
+            entry("minor", vec![Element::UInteger8(1)]),
+            entry("..", vec![]),
+            entry(
+                "tags",
+                vec![
+                    Element::Collection,
+                    Element::String("alpha".into()),
+                    Element::String("unstable".into()),
+                ],
+            ),
+            entry("/", vec![]),
+            entry(
+                "timestamp",
+                vec![
+                    Element::UInteger32(1651906455),
+                    Element::String("unix_time".into()),
+                ],
+            ),
+        ],
+    };
+
+    let value = Value::from(&document);
+
+    assert_eq!(
+        value,
+        Value::Map(vec![
+            ("format".into(), Value::Str("TPK".into())),
+            (
+                "version".into(),
+                Value::Map(vec![
+                    (
+                        "name".into(),
+                        Value::Str("First Development Release".into())
+                    ),
+                    (
+                        "numbers".into(),
+                        Value::Map(vec![
+                            ("major".into(), Value::UInt(0)),
+                            ("minor".into(), Value::UInt(1)),
+                        ])
+                    ),
+                    (
+                        "tags".into(),
+                        Value::List(vec![
+                            Value::Str("alpha".into()),
+                            Value::Str("unstable".into()),
+                        ])
+                    ),
+                ])
+            ),
+            (
+                "timestamp".into(),
+                Value::List(vec![
+                    Value::UInt(1651906455),
+                    Value::Str("unix_time".into())
+                ])
+            ),
+        ])
+    );
+    assert_eq!(
+        value.get("version").and_then(|v| v.get("numbers")),
+        Some(&Value::Map(vec![
+            ("major".into(), Value::UInt(0)),
+            ("minor".into(), Value::UInt(1)),
+        ]))
+    );
+}
@@ -0,0 +1,53 @@
+use tpk::visit::Visitor;
+use tpk::{Element, Entry};
+
+#[derive(Default)]
+struct IntegerSum {
+    sum: i128,
+    strings: usize,
+}
+
+impl Visitor for IntegerSum {
+    fn visit_i8(&mut self, v: i8) {
+        self.sum += v as i128;
+    }
+
+    fn visit_i32(&mut self, v: i32) {
+        self.sum += v as i128;
+    }
+
+    fn visit_u16(&mut self, v: u16) {
+        self.sum += v as i128;
+    }
+
+    fn visit_u64(&mut self, v: u64) {
+        self.sum += v as i128;
+    }
+
+    fn visit_string(&mut self, _: &str) {
+        self.strings += 1;
+    }
+}
+
+#[test]
+fn test_visitor_sums_integers() {
+    let entry = Entry {
+        name: "values".into(),
+        elements: vec![
+            Element::Integer8(-2),
+            Element::UInteger16(300),
+            Element::String("not a number".into()),
+            Element::Integer32(-8),
+            Element::Float64(1000.0),
+            Element::UInteger64(10),
+        ],
+    };
+
+    let mut visitor = IntegerSum::default();
+    for element in &entry.elements {
+        element.accept(&mut visitor);
+    }
+
+    assert_eq!(visitor.sum, 300);
+    assert_eq!(visitor.strings, 1);
+}
@@ -1,5 +1,11 @@
+#![allow(clippy::manual_repeat_n)]
+
+use std::io::Cursor;
 use std::iter::repeat;
-use tpk::{Element, Entry, Writer};
+use tpk::{
+    Document, Element, ElementRef, Entry, FramedReader, LogReader, LogWriter, Reader, SliceReader,
+    Writer,
+};
 
 fn assert_element_write(element: Element, expected_size: usize) -> Vec<u8> {
     let mut output = vec![];
@@ -160,6 +166,24 @@ fn test_write_float64() {
     );
 }
 
+#[test]
+fn test_write_uint128() {
+    let output = assert_element_write(Element::UInteger128(u64::MAX as u128 + 1), 17);
+    assert_eq!(output[0], 0b00101000u8);
+    let mut expected = vec![0u8; 16];
+    expected[8] = 1u8;
+    assert_eq!(output[1..], expected);
+}
+
+#[test]
+fn test_write_int128() {
+    let output = assert_element_write(Element::Integer128(-2), 17);
+    assert_eq!(output[0], 0b00101001u8);
+    let mut expected = vec![0xFFu8; 16];
+    expected[0] = 0xFEu8;
+    assert_eq!(output[1..], expected);
+}
+
 #[test]
 fn test_write_boolean_false() {
     let output = assert_element_write(Element::Boolean(false), 1);
@@ -181,6 +205,35 @@ fn test_write_small_string() {
     assert_eq!(&output[2..], string.as_bytes());
 }
 
+#[test]
+fn test_write_empty_blob_round_trip() {
+    let element = Element::Blob(Vec::new());
+    let output = assert_element_write(element.clone(), 2);
+    assert_eq!(output, vec![0b00010100u8, 0u8]);
+
+    let mut scratch_output = vec![];
+    Writer::new(&mut scratch_output)
+        .scratch_buffer(true)
+        .write_element(&element)
+        .unwrap();
+    assert_eq!(scratch_output, output);
+
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.read_element().unwrap(), Some(element));
+    assert_eq!(reader.read_element().unwrap(), None);
+
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.read_blob_into(&mut []).unwrap(), 0);
+    assert_eq!(reader.read_element().unwrap(), None);
+
+    let mut reader = SliceReader::new(&output);
+    assert!(matches!(
+        reader.read_element_ref().unwrap(),
+        Some(ElementRef::Blob(&[]))
+    ));
+    assert!(reader.read_element_ref().unwrap().is_none());
+}
+
 #[test]
 fn test_write_medium_string() {
     let output = assert_element_write(Element::String(String::from_iter(vec!['a'; 500])), 503);
@@ -255,3 +308,1219 @@ fn test_write_entry_with_no_elements() {
     let output = assert_entry_write(entry, 5);
     assert_eq!(output, vec![0b10000100u8, b'n', b'a', b'm', b'e']);
 }
+
+#[test]
+fn test_element_encoded_len() {
+    let elements = vec![
+        Element::Marker(String::from("test")),
+        Element::Marker(String::from_iter(std::iter::repeat_n('a', 987654))),
+        Element::Folder,
+        Element::Boolean(true),
+        Element::Integer16(-42),
+        Element::Float64(1.5),
+        Element::String(String::from("unix_time")),
+        Element::Blob(vec![42u8; 500]),
+        Element::Duration(-42),
+        Element::Padding(300),
+        Element::TypedCollection {
+            type_byte: 0b00101111u8,
+            count: 3,
+        },
+        Element::Map(vec![
+            (String::from("a"), Element::UInteger8(1)),
+            (String::from("b"), Element::Map(vec![])),
+        ]),
+    ];
+
+    for element in elements {
+        let mut output = vec![];
+        Writer::new(&mut output).write_element(&element).unwrap();
+        assert_eq!(element.encoded_len(), output.len());
+    }
+}
+
+#[test]
+fn test_document_encoded_len() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("name"),
+                elements: vec![
+                    Element::UInteger32(1651906455),
+                    Element::String(String::from("unix_time")),
+                ],
+            },
+            Entry {
+                name: String::from_iter(std::iter::repeat_n('a', 100)),
+                elements: vec![Element::Blob(vec![42u8; 500])],
+            },
+        ],
+    };
+
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for entry in &document.entries {
+        writer.write_entry(entry).unwrap();
+    }
+
+    assert_eq!(document.encoded_len(), output.len());
+}
+
+#[test]
+fn test_write_document_round_trip() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("format"),
+                elements: vec![Element::String(String::from("TPK"))],
+            },
+            Entry {
+                name: String::from("version"),
+                elements: vec![Element::Folder],
+            },
+            Entry {
+                name: String::from("major"),
+                elements: vec![Element::UInteger8(0)],
+            },
+            Entry {
+                name: String::from("minor"),
+                elements: vec![Element::UInteger8(1)],
+            },
+        ],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output).write_document(&document).unwrap();
+    let result = Reader::new(output.as_slice()).read_document().unwrap();
+
+    assert_eq!(output.len(), document.encoded_len());
+    assert_eq!(result, document);
+}
+
+#[test]
+fn test_write_with_scratch_buffer() {
+    let elements = vec![
+        Element::Marker(String::from("test")),
+        Element::Marker(String::from_iter(std::iter::repeat_n('a', 987654))),
+        Element::Folder,
+        Element::Collection,
+        Element::Boolean(false),
+        Element::Integer8(-42),
+        Element::Integer64(-1337),
+        Element::UInteger16(1337),
+        Element::UInteger32(1651906455),
+        Element::Float32(1.5),
+        Element::String(String::from("unix_time")),
+        Element::String(String::from_iter(std::iter::repeat_n('a', 70000))),
+        Element::Blob(vec![42u8; 500]),
+        Element::Blob(Vec::new()),
+    ];
+
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    let mut scratch_output = vec![];
+    let mut scratch_writer = Writer::new(&mut scratch_output).scratch_buffer(true);
+    for element in &elements {
+        writer.write_element(element).unwrap();
+        scratch_writer.write_element(element).unwrap();
+    }
+
+    assert_eq!(scratch_output, output);
+}
+
+#[test]
+fn test_write_extension() {
+    let output = assert_element_write(
+        Element::Extension {
+            id: 200,
+            data: vec![1u8, 2u8, 3u8],
+        },
+        6,
+    );
+    assert_eq!(
+        output,
+        vec![0b01110000u8, 200u8, 0b00000011u8, 1u8, 2u8, 3u8]
+    );
+}
+
+#[test]
+fn test_write_delta_u64() {
+    let output = assert_element_write(Element::DeltaU64(vec![1000, 1001, 1003, 1002]), 8);
+    assert_eq!(
+        output,
+        vec![0b01110000u8, 0u8, 5u8, 0xE8u8, 0x07u8, 2u8, 4u8, 1u8]
+    );
+}
+
+#[test]
+fn test_write_duration() {
+    let output = assert_element_write(Element::Duration(-2), 11);
+    assert_eq!(
+        output,
+        vec![
+            0b01110000u8,
+            3u8,
+            8u8,
+            0xFEu8,
+            0xFFu8,
+            0xFFu8,
+            0xFFu8,
+            0xFFu8,
+            0xFFu8,
+            0xFFu8,
+            0xFFu8
+        ]
+    );
+}
+
+#[test]
+fn test_write_reject_non_finite_floats() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).reject_non_finite_floats(true);
+    writer.write_element(&Element::Float64(1.5)).unwrap();
+    assert!(matches!(
+        writer.write_element(&Element::Float64(f64::NAN)),
+        Err(tpk::write::Error::NonFiniteFloat { value }) if value.is_nan()
+    ));
+    assert!(matches!(
+        writer.write_element(&Element::Float32(f32::NEG_INFINITY)),
+        Err(tpk::write::Error::NonFiniteFloat { value }) if value == f64::NEG_INFINITY
+    ));
+    assert_eq!(output.len(), Element::Float64(1.5).encoded_len());
+
+    // Non-finite floats are written by default.
+    assert_element_write(Element::Float64(f64::NAN), 9);
+}
+
+#[test]
+fn test_write_padding() {
+    let output = assert_element_write(Element::Padding(16), 18);
+    assert_eq!(output[..2], [0b00011100u8, 16u8]);
+    assert!(output[2..].iter().all(|&byte| byte == 0));
+
+    let mut output = vec![];
+    let result = Writer::new(&mut output)
+        .version(2)
+        .write_element(&Element::Padding(16));
+    assert!(matches!(
+        result,
+        Err(tpk::write::Error::TypeNotInVersion {
+            byte: 0b00011100u8,
+            version: 2
+        })
+    ));
+}
+
+#[test]
+fn test_write_delta_u64_size() {
+    let timestamps: Vec<u64> = (0..1000).map(|i| 1651906455000 + i * 250).collect();
+    let delta = Element::DeltaU64(timestamps.clone());
+
+    let mut output = vec![];
+    Writer::new(&mut output).write_element(&delta).unwrap();
+    assert_eq!(output.len(), delta.encoded_len());
+
+    let mut collection_output = vec![];
+    Writer::new(&mut collection_output)
+        .write_collection(&timestamps)
+        .unwrap();
+    assert_eq!(collection_output.len(), 1 + 1000 * 9);
+    assert!(output.len() * 4 < collection_output.len());
+}
+
+#[test]
+fn test_write_framed_document() {
+    let documents = vec![
+        Document {
+            entries: vec![Entry {
+                name: String::from("name"),
+                elements: vec![
+                    Element::UInteger32(1651906455),
+                    Element::String(String::from("unix_time")),
+                ],
+            }],
+        },
+        Document::default(),
+        Document {
+            entries: vec![Entry {
+                name: String::from("flag"),
+                elements: vec![Element::Boolean(true)],
+            }],
+        },
+    ];
+
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    for document in &documents {
+        writer.write_framed_document(document).unwrap();
+    }
+    assert_eq!(output[..4], 21u32.to_le_bytes());
+
+    let mut reader = FramedReader::new(output.as_slice());
+    for document in &documents {
+        assert_eq!(
+            reader.read_framed_document().unwrap().as_ref(),
+            Some(document)
+        );
+    }
+    assert_eq!(reader.read_framed_document().unwrap(), None);
+}
+
+#[test]
+fn test_read_framed_document_too_large() {
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("flag"),
+            elements: vec![Element::Boolean(true)],
+        }],
+    };
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    writer.write_framed_document(&Document::default()).unwrap();
+    writer.write_framed_document(&document).unwrap();
+
+    let mut reader = FramedReader::new(output.as_slice()).max_frame_len(5);
+    assert_eq!(
+        reader.read_framed_document().unwrap(),
+        Some(Document::default())
+    );
+    assert!(matches!(
+        reader.read_framed_document(),
+        Err(tpk::read::Error::FrameTooLarge {
+            pos: 4,
+            len: 6,
+            limit: 5
+        })
+    ));
+
+    // The length is not trusted to allocate the frame.
+    let mut input = u32::MAX.to_le_bytes().to_vec();
+    input.push(0b00110001u8);
+    let mut reader = FramedReader::new(input.as_slice());
+    assert!(matches!(
+        reader.read_framed_document(),
+        Err(tpk::read::Error::Syntax(5, _))
+    ));
+}
+
+#[test]
+fn test_write_framed_document_failing() {
+    let invalid = Document {
+        entries: vec![
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::UInteger8(1)],
+            },
+            Entry {
+                name: String::from("b"),
+                elements: vec![Element::CString(String::from("x\0y"))],
+            },
+        ],
+    };
+    let valid = Document {
+        entries: vec![Entry {
+            name: String::from("flag"),
+            elements: vec![Element::Boolean(true)],
+        }],
+    };
+
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    assert!(matches!(
+        writer.write_framed_document(&invalid),
+        Err(tpk::write::Error::InteriorNul { pos: 1 })
+    ));
+    writer.write_framed_document(&valid).unwrap();
+    assert_eq!(output[..4], 6u32.to_le_bytes());
+
+    let mut reader = FramedReader::new(output.as_slice());
+    assert_eq!(reader.read_framed_document().unwrap(), Some(valid));
+    assert_eq!(reader.read_framed_document().unwrap(), None);
+}
+
+fn log_entries() -> Vec<Entry> {
+    vec![
+        Entry {
+            name: String::from("first"),
+            elements: vec![Element::UInteger32(1)],
+        },
+        Entry {
+            name: String::from("second"),
+            elements: vec![Element::String(String::from("lost"))],
+        },
+        Entry {
+            name: String::from("third"),
+            elements: vec![Element::Blob(vec![42u8; 10]), Element::Boolean(true)],
+        },
+    ]
+}
+
+#[test]
+fn test_log_round_trip() {
+    let entries = log_entries();
+    let mut writer = LogWriter::new(vec![]);
+    for entry in &entries {
+        writer.append(entry).unwrap();
+    }
+    let output = writer.into_inner();
+    assert_eq!(output[..4], *b"TPKR");
+
+    let mut reader = LogReader::new(output.as_slice());
+    for entry in &entries {
+        assert_eq!(reader.read_record().unwrap().as_ref(), Some(entry));
+    }
+    assert_eq!(reader.read_record().unwrap(), None);
+}
+
+#[test]
+fn test_log_append_failing() {
+    let entries = log_entries();
+    let mut writer = LogWriter::new(vec![]);
+    writer.append(&entries[0]).unwrap();
+    let invalid = Entry {
+        name: String::from("invalid"),
+        elements: vec![
+            Element::UInteger8(1),
+            Element::CString(String::from("x\0y")),
+        ],
+    };
+    assert!(matches!(
+        writer.append(&invalid),
+        Err(tpk::write::Error::InteriorNul { pos: 1 })
+    ));
+    writer.append(&entries[1]).unwrap();
+    let output = writer.into_inner();
+
+    let mut reader = LogReader::new(output.as_slice());
+    assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[0]));
+    assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[1]));
+    assert_eq!(reader.read_record().unwrap(), None);
+}
+
+#[test]
+fn test_log_recovers_from_corrupt_record() {
+    let entries = log_entries();
+    let mut writer = LogWriter::new(vec![]);
+    for entry in &entries {
+        writer.append(entry).unwrap();
+    }
+    let output = writer.into_inner();
+    let second_start = 8 + entries[0].encoded_len();
+
+    // The length of the second record swallows the third one, then its element is invalid.
+    let mut corrupt_len = output.clone();
+    corrupt_len[second_start + 4..second_start + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut corrupt_element = output.clone();
+    corrupt_element[second_start + 8 + 7] = 0x7F;
+
+    for input in [corrupt_len, corrupt_element] {
+        let mut reader = LogReader::new(input.as_slice());
+        assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[0]));
+        assert!(reader.read_record().is_err());
+        assert!(reader.skip_to_next_record().unwrap());
+        assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[2]));
+        assert_eq!(reader.read_record().unwrap(), None);
+        assert!(!reader.skip_to_next_record().unwrap());
+    }
+}
+
+#[test]
+fn test_log_record_too_large() {
+    let entries = log_entries();
+    let mut writer = LogWriter::new(vec![]);
+    for entry in &entries {
+        writer.append(entry).unwrap();
+    }
+    let output = writer.into_inner();
+    let third_start = 16 + entries[0].encoded_len() + entries[1].encoded_len();
+
+    let limit = entries[0].encoded_len().max(entries[1].encoded_len());
+    let mut reader = LogReader::new(output.as_slice()).max_frame_len(limit);
+    assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[0]));
+    assert_eq!(reader.read_record().unwrap().as_ref(), Some(&entries[1]));
+    match reader.read_record() {
+        Err(tpk::read::Error::FrameTooLarge { pos, len, .. }) => {
+            assert_eq!(pos, third_start);
+            assert_eq!(len, entries[2].encoded_len());
+        }
+        _ => panic!("Expected frame too large error"),
+    }
+    assert!(!reader.skip_to_next_record().unwrap());
+}
+
+#[test]
+fn test_canonicalize_document() {
+    let input = vec![
+        0b10000001u8,
+        b'a',
+        0b00100011u8, // UInteger64(42)
+        42u8,
+        0u8,
+        0u8,
+        0u8,
+        0u8,
+        0u8,
+        0u8,
+        0u8,
+        0b00100110u8, // Integer32(-300)
+        0b11010100u8,
+        0b11111110u8,
+        0b11111111u8,
+        0b11111111u8,
+        0b00010001u8, // String with a 16-bit size
+        2u8,
+        0u8,
+        b'o',
+        b'k',
+    ];
+
+    let mut document = Reader::new(input.as_slice()).read_document().unwrap();
+    document.canonicalize();
+    let mut output = vec![];
+    Writer::new(&mut output).write_document(&document).unwrap();
+    assert_eq!(
+        output,
+        vec![
+            0b10000001u8,
+            b'a',
+            0b00100000u8,
+            42u8,
+            0b00100101u8,
+            0b11010100u8,
+            0b11111110u8,
+            0b00010000u8,
+            2u8,
+            b'o',
+            b'k',
+        ]
+    );
+
+    let mut canonical = document.clone();
+    canonical.canonicalize();
+    assert_eq!(canonical, document);
+}
+
+#[test]
+fn test_version_refuses_newer_types() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).version(1);
+    writer.write_element(&Element::UInteger8(42)).unwrap();
+    let result = writer.write_element(&Element::Extension {
+        id: 200,
+        data: Vec::new(),
+    });
+
+    assert!(matches!(
+        result,
+        Err(tpk::write::Error::TypeNotInVersion {
+            byte: 0b01110000u8,
+            version: 1
+        })
+    ));
+    assert_eq!(output, vec![0b00100000u8, 42u8]);
+}
+
+#[test]
+fn test_write_collection_from_iterator() {
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_collection_from((0..1000).map(|i| Element::UInteger8((i % 256) as u8)))
+        .unwrap();
+    assert_eq!(output.len(), 1 + 1000 * 2);
+
+    let mut reader = Reader::new(output.as_slice()).strict(true);
+    assert_eq!(reader.read_element().unwrap(), Some(Element::Collection));
+    for i in 0..1000 {
+        assert_eq!(
+            reader.read_element().unwrap(),
+            Some(Element::UInteger8((i % 256) as u8))
+        );
+    }
+    assert_eq!(reader.read_element().unwrap(), None);
+
+    let mut output = vec![];
+    let result = Writer::new(&mut output).write_collection_from([
+        Element::UInteger8(1),
+        Element::UInteger8(2),
+        Element::Boolean(true),
+    ]);
+    assert!(matches!(
+        result,
+        Err(tpk::write::Error::HeterogeneousCollection { index: 2 })
+    ));
+    assert_eq!(output, vec![1u8, 0b00100000, 1, 0b00100000, 2]);
+}
+
+#[test]
+fn test_write_collection_from_rewritten_items() {
+    let items = vec![
+        Element::Blob(vec![1u8; 300]),
+        Element::Blob(vec![1u8; 300]),
+        Element::Blob(vec![2u8]),
+    ];
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .dedup_blobs(true)
+        .canonicalize(true)
+        .write_collection_from(items.clone())
+        .unwrap();
+
+    let mut reader = Reader::new(output.as_slice())
+        .strict(true)
+        .dedup_blobs(true);
+    assert_eq!(reader.read_element().unwrap(), Some(Element::Collection));
+    for item in items {
+        assert_eq!(reader.read_element().unwrap(), Some(item));
+    }
+    assert_eq!(reader.read_element().unwrap(), None);
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .canonicalize(true)
+        .write_collection_from([Element::UInteger16(300), Element::UInteger16(1)])
+        .unwrap();
+    assert_eq!(output, vec![1u8, 0b00100001, 44, 1, 0b00100001, 1, 0]);
+}
+
+#[test]
+fn test_write_collection_from_invalid_item() {
+    for item in [
+        Element::Marker(String::from("name")),
+        Element::Folder,
+        Element::Collection,
+        Element::TypedCollection {
+            type_byte: 0b00100000,
+            count: 0,
+        },
+    ] {
+        let mut output = vec![];
+        let result = Writer::new(&mut output).write_collection_from([Element::UInteger8(1), item]);
+        assert!(matches!(
+            result,
+            Err(tpk::write::Error::InvalidCollectionItem { index: 1 })
+        ));
+        assert_eq!(output, vec![1u8, 0b00100000, 1]);
+    }
+}
+
+#[test]
+fn test_write_invalid_typed_collection() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    assert!(matches!(
+        writer.write_typed_collection(0b00010100, 1),
+        Err(tpk::write::Error::InvalidTypedCollection)
+    ));
+
+    writer.write_typed_collection(0b00100000, 2).unwrap();
+    writer.write_element(&Element::UInteger8(1)).unwrap();
+    for element in [
+        Element::UInteger16(2),
+        Element::Marker(String::from("name")),
+        Element::Collection,
+    ] {
+        assert!(matches!(
+            writer.write_element(&element),
+            Err(tpk::write::Error::InvalidTypedCollection)
+        ));
+    }
+    writer.write_element(&Element::UInteger8(2)).unwrap();
+    assert!(matches!(
+        writer.write_element(&Element::UInteger8(3)),
+        Err(tpk::write::Error::InvalidTypedCollection)
+    ));
+    writer
+        .write_element(&Element::Marker(String::from("name")))
+        .unwrap();
+    assert_eq!(output.len(), 8 + 2 + 2 + 5);
+}
+
+#[test]
+fn test_write_collection_from_slice() {
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_collection(&[1u32, 1337u32, 1651906455u32])
+        .unwrap();
+
+    let mut reader = Reader::new(output.as_slice()).strict(true);
+    let mut elements = vec![];
+    while let Some(element) = reader.read_element().unwrap() {
+        elements.push(element);
+    }
+    assert_eq!(
+        elements,
+        vec![
+            Element::Collection,
+            Element::UInteger32(1),
+            Element::UInteger32(1337),
+            Element::UInteger32(1651906455),
+        ]
+    );
+}
+
+#[test]
+fn test_write_c_string_round_trip() {
+    let element = Element::CString(String::from("unix_time"));
+    let output = assert_element_write(element.clone(), 11);
+    assert_eq!(output[0], 0b00011000u8);
+    assert_eq!(&output[1..10], b"unix_time".as_slice());
+    assert_eq!(output[10], 0u8);
+
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.read_element().unwrap(), Some(element));
+    assert_eq!(reader.read_element().unwrap(), None);
+}
+
+#[test]
+fn test_write_c_string_with_interior_nul() {
+    let mut output = vec![];
+    let result =
+        Writer::new(&mut output).write_element(&Element::CString(String::from("unix\0time")));
+    assert!(matches!(
+        result,
+        Err(tpk::write::Error::InteriorNul { pos: 4 })
+    ));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_write_strict_structure() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).strict_structure(true);
+    let result = writer.write_element(&Element::UInteger8(42));
+    assert!(matches!(
+        result,
+        Err(tpk::write::Error::MissingLeadingMarker)
+    ));
+    writer.write_element(&Element::Marker("a".into())).unwrap();
+    writer.write_element(&Element::UInteger8(42)).unwrap();
+    assert_eq!(output, vec![0b10000001u8, b'a', 0b00100000u8, 42u8]);
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_element(&Element::UInteger8(42))
+        .unwrap();
+    assert_eq!(output, vec![0b00100000u8, 42u8]);
+}
+
+#[test]
+fn test_write_document_sorted() {
+    let name = Entry {
+        name: String::from("name"),
+        elements: vec![Element::UInteger32(1651906455)],
+    };
+    let format = Entry {
+        name: String::from("format"),
+        elements: vec![Element::String(String::from("unix_time"))],
+    };
+    let other_format = Entry {
+        name: String::from("format"),
+        elements: vec![Element::UInteger8(42)],
+    };
+    let document = Document {
+        entries: vec![name.clone(), format.clone(), other_format.clone()],
+    };
+    let shuffled = Document {
+        entries: vec![format.clone(), other_format.clone(), name.clone()],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .write_document_sorted(&document)
+        .unwrap();
+    let mut shuffled_output = vec![];
+    Writer::new(&mut shuffled_output)
+        .write_document_sorted(&shuffled)
+        .unwrap();
+    assert_eq!(output, shuffled_output);
+
+    let result = Reader::new(output.as_slice()).read_document().unwrap();
+    assert_eq!(result.entries, vec![format, other_format, name]);
+}
+
+#[test]
+fn test_write_into_element() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output);
+    writer
+        .write(Element::Marker(String::from("metrics")))
+        .unwrap();
+    writer.write(42u32).unwrap();
+    writer.write(-1i8).unwrap();
+    writer.write(0.5f64).unwrap();
+    writer.write(true).unwrap();
+    writer.write("hello").unwrap();
+    writer.write(vec![1u8, 2, 3]).unwrap();
+
+    let result = Reader::new(output.as_slice())
+        .read_entry()
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.name, "metrics");
+    assert_eq!(
+        result.elements,
+        vec![
+            Element::UInteger32(42),
+            Element::Integer8(-1),
+            Element::Float64(0.5),
+            Element::Boolean(true),
+            Element::String(String::from("hello")),
+            Element::Blob(vec![1, 2, 3]),
+        ]
+    );
+}
+
+#[test]
+fn test_canonicalize_narrowing_boundaries() {
+    let cases = [
+        (
+            Element::UInteger64(u8::MAX as u64),
+            Element::UInteger8(u8::MAX),
+        ),
+        (Element::UInteger64(300), Element::UInteger16(300)),
+        (
+            Element::UInteger64(u16::MAX as u64),
+            Element::UInteger16(u16::MAX),
+        ),
+        (
+            Element::UInteger64(u16::MAX as u64 + 1),
+            Element::UInteger32(u16::MAX as u32 + 1),
+        ),
+        (
+            Element::UInteger64(u32::MAX as u64),
+            Element::UInteger32(u32::MAX),
+        ),
+        (
+            Element::UInteger64(u32::MAX as u64 + 1),
+            Element::UInteger64(u32::MAX as u64 + 1),
+        ),
+        (
+            Element::UInteger128(u64::MAX as u128),
+            Element::UInteger64(u64::MAX),
+        ),
+        (
+            Element::UInteger128(u64::MAX as u128 + 1),
+            Element::UInteger128(u64::MAX as u128 + 1),
+        ),
+        (
+            Element::Integer64(i8::MIN as i64),
+            Element::Integer8(i8::MIN),
+        ),
+        (
+            Element::Integer64(i8::MAX as i64),
+            Element::Integer8(i8::MAX),
+        ),
+        (
+            Element::Integer64(i8::MIN as i64 - 1),
+            Element::Integer16(i8::MIN as i16 - 1),
+        ),
+        (
+            Element::Integer64(i8::MAX as i64 + 1),
+            Element::Integer16(i8::MAX as i16 + 1),
+        ),
+        (
+            Element::Integer64(i16::MIN as i64),
+            Element::Integer16(i16::MIN),
+        ),
+        (
+            Element::Integer64(i16::MAX as i64 + 1),
+            Element::Integer32(i16::MAX as i32 + 1),
+        ),
+        (
+            Element::Integer64(i32::MIN as i64),
+            Element::Integer32(i32::MIN),
+        ),
+        (
+            Element::Integer64(i32::MIN as i64 - 1),
+            Element::Integer64(i32::MIN as i64 - 1),
+        ),
+        (
+            Element::Integer128(i64::MIN as i128),
+            Element::Integer64(i64::MIN),
+        ),
+        (
+            Element::Integer128(i64::MAX as i128 + 1),
+            Element::Integer128(i64::MAX as i128 + 1),
+        ),
+    ];
+
+    for (element, expected) in cases {
+        let mut output = vec![];
+        Writer::new(&mut output)
+            .canonicalize(true)
+            .write_element(&element)
+            .unwrap();
+        let result = Reader::new(output.as_slice())
+            .read_element()
+            .unwrap()
+            .unwrap();
+        assert_eq!(result, expected);
+        assert!(result.value_eq(&element), "{:?} != {:?}", result, element);
+    }
+}
+
+#[test]
+fn test_write_document_sorted_canonicalized() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("b"),
+                elements: vec![Element::UInteger64(5), Element::Integer32(-300)],
+            },
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::Integer128(1)],
+            },
+        ],
+    };
+    let other = Document {
+        entries: vec![
+            Entry {
+                name: String::from("a"),
+                elements: vec![Element::Integer8(1)],
+            },
+            Entry {
+                name: String::from("b"),
+                elements: vec![Element::UInteger8(5), Element::Integer16(-300)],
+            },
+        ],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .canonicalize(true)
+        .write_document_sorted(&document)
+        .unwrap();
+    let mut other_output = vec![];
+    Writer::new(&mut other_output)
+        .canonicalize(true)
+        .write_document_sorted(&other)
+        .unwrap();
+    assert_eq!(output, other_output);
+    assert_eq!(output.len(), other.encoded_len());
+
+    let mut framed_output = vec![];
+    Writer::new(&mut framed_output)
+        .canonicalize(true)
+        .write_framed_document(&document)
+        .unwrap();
+    assert_eq!(
+        u32::from_le_bytes(framed_output[..4].try_into().unwrap()) as usize,
+        framed_output.len() - 4
+    );
+}
+
+#[test]
+fn test_write_collection_canonicalized() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).canonicalize(true);
+    writer.write_collection(&[5u16, 300u16]).unwrap();
+    writer.write_element(&Element::UInteger16(5)).unwrap();
+    writer.write_element(&Element::Folder).unwrap();
+    writer.write_element(&Element::UInteger16(5)).unwrap();
+
+    let mut reader = Reader::new(output.as_slice()).strict(true);
+    let mut elements = vec![];
+    while let Some(element) = reader.read_element().unwrap() {
+        elements.push(element);
+    }
+    assert_eq!(
+        elements,
+        vec![
+            Element::Collection,
+            Element::UInteger16(5),
+            Element::UInteger16(300),
+            Element::UInteger16(5),
+            Element::Folder,
+            Element::UInteger8(5),
+        ]
+    );
+
+    let mut document = Document {
+        entries: vec![Entry {
+            name: String::from("name"),
+            elements: elements.clone(),
+        }],
+    };
+    document.canonicalize();
+    assert_eq!(document.entries[0].elements, elements);
+}
+
+#[test]
+fn test_write_footer_canonicalized() {
+    let index = vec![(String::from("name"), 300u64), (String::from("name"), 0u64)];
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .canonicalize(true)
+        .write_footer(&index)
+        .unwrap();
+
+    let mut reader = Reader::new(Cursor::new(output.as_slice()));
+    assert_eq!(reader.read_footer().unwrap(), Some(index));
+}
+
+#[test]
+fn test_write_dedup_blobs() {
+    let thumbnail = Element::Blob(vec![42u8; 100]);
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("first"),
+                elements: vec![thumbnail.clone(), Element::Blob(vec![1u8, 2u8, 3u8])],
+            },
+            Entry {
+                name: String::from("second"),
+                elements: vec![thumbnail.clone()],
+            },
+        ],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .dedup_blobs(true)
+        .write_document(&document)
+        .unwrap();
+    assert_eq!(output.len(), document.encoded_len() - 102 + 7);
+    assert_eq!(
+        &output[output.len() - 7..],
+        [0b01110000u8, 1u8, 4u8, 0u8, 0u8, 0u8, 0u8].as_slice()
+    );
+
+    let mut reader = Reader::new(output.as_slice());
+    let entries = reader.read_document().unwrap().entries;
+    assert_eq!(entries[1].elements, vec![Element::BlobRef(0)]);
+
+    let mut reader = Reader::new(output.as_slice()).dedup_blobs(true);
+    assert_eq!(reader.read_document().unwrap(), document);
+
+    let mut reader = Reader::new(output.as_slice()).dedup_blobs(true);
+    assert_eq!(
+        reader.nth_entry(1).unwrap(),
+        Some(document.entries[1].clone())
+    );
+}
+
+#[test]
+fn test_write_framed_document_with_dedup_blobs() {
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("thumbnails"),
+            elements: vec![
+                Element::Blob(vec![42u8; 100]),
+                Element::Blob(vec![42u8; 100]),
+                Element::Map(vec![(
+                    String::from("small"),
+                    Element::Blob(vec![42u8; 100]),
+                )]),
+            ],
+        }],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .dedup_blobs(true)
+        .write_framed_document(&document)
+        .unwrap();
+    assert_eq!(output.len(), 4 + document.encoded_len() - 2 * (102 - 7));
+    assert_eq!(
+        u32::from_le_bytes(output[..4].try_into().unwrap()) as usize,
+        output.len() - 4
+    );
+}
+
+#[test]
+fn test_write_string_table() {
+    let document = Document {
+        entries: vec![
+            Entry {
+                name: String::from("first"),
+                elements: vec![
+                    Element::String(String::from("unix_time")),
+                    Element::Map(vec![(
+                        String::from("unix_time"),
+                        Element::String(String::from("unix_time")),
+                    )]),
+                ],
+            },
+            Entry {
+                name: String::from("second"),
+                elements: vec![
+                    Element::String(String::from("a")),
+                    Element::String(String::from("a")),
+                ],
+            },
+        ],
+    };
+    let table = Entry {
+        name: String::from(tpk::write::STRING_TABLE_NAME),
+        elements: vec![Element::String(String::from("unix_time"))],
+    };
+
+    let mut output = vec![];
+    Writer::new(&mut output)
+        .use_string_table(true)
+        .write_document(&document)
+        .unwrap();
+    assert_eq!(
+        output.len(),
+        table.encoded_len() + document.encoded_len() - 2 * (11 - 7)
+    );
+
+    // The table is a regular entry, and map keys are never references.
+    let mut reader = Reader::new(output.as_slice());
+    assert_eq!(reader.read_entry().unwrap(), Some(table.clone()));
+    assert_eq!(
+        reader.read_entry().unwrap().unwrap().elements,
+        vec![
+            Element::StringRef(0),
+            Element::Map(vec![(String::from("unix_time"), Element::StringRef(0))]),
+        ]
+    );
+    assert_eq!(
+        reader.read_entry().unwrap().as_ref(),
+        Some(&document.entries[1])
+    );
+
+    let mut reader = Reader::new(output.as_slice()).use_string_table(true);
+    assert_eq!(reader.read_document().unwrap(), document);
+
+    let mut reader = Reader::new(output.as_slice()).use_string_table(true);
+    assert_eq!(
+        reader.nth_entry(1).unwrap(),
+        Some(document.entries[1].clone())
+    );
+
+    let mut framed = vec![];
+    Writer::new(&mut framed)
+        .use_string_table(true)
+        .write_framed_document(&document)
+        .unwrap();
+    assert_eq!(framed[4..], output);
+    assert_eq!(
+        u32::from_le_bytes(framed[..4].try_into().unwrap()) as usize,
+        output.len()
+    );
+}
+
+#[test]
+fn test_write_string_table_explicitly() {
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).use_string_table(true);
+    writer
+        .write_string_table(&["unix_time", "unix_time"])
+        .unwrap();
+    writer
+        .write(Element::Marker(String::from("first")))
+        .unwrap();
+    writer.write("unix_time").unwrap();
+    writer.write_string_table(&["unix_time", "name"]).unwrap();
+    writer
+        .write(Element::Marker(String::from("second")))
+        .unwrap();
+    writer.write("name").unwrap();
+    assert_eq!(
+        &output[output.len() - 7..],
+        [0b01110000u8, 7u8, 4u8, 1u8, 0u8, 0u8, 0u8].as_slice()
+    );
+
+    let mut reader = Reader::new(output.as_slice()).use_string_table(true);
+    assert_eq!(
+        reader.read_document().unwrap().entries,
+        vec![
+            Entry {
+                name: String::from("first"),
+                elements: vec![Element::String(String::from("unix_time"))],
+            },
+            Entry {
+                name: String::from("second"),
+                elements: vec![Element::String(String::from("name"))],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_write_footer_with_string_table() {
+    let document = Document {
+        entries: vec![Entry {
+            name: String::from("name"),
+            elements: vec![
+                Element::String(String::from("name_name")),
+                Element::String(String::from("name_name")),
+            ],
+        }],
+    };
+    let index = vec![(String::from("name_name"), 0u64)];
+    let mut output = vec![];
+    let mut writer = Writer::new(&mut output).use_string_table(true);
+    writer.write_document(&document).unwrap();
+    writer.write_footer(&index).unwrap();
+
+    let mut reader = Reader::new(Cursor::new(output.as_slice())).use_string_table(true);
+    assert_eq!(reader.read_footer().unwrap(), Some(index));
+    assert_eq!(
+        reader.read_entry().unwrap().as_ref(),
+        Some(&document.entries[0])
+    );
+}
+
+#[test]
+fn test_write_flagged() {
+    let output = assert_element_write(
+        Element::Flagged {
+            flags: 0b101,
+            inner: Box::new(Element::String(String::from("secret"))),
+        },
+        12,
+    );
+    let mut expected = vec![0b01110000u8, 2u8, 9u8, 0b101u8, 0b00010000u8, 6u8];
+    expected.extend_from_slice(b"secret");
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_write_map_marker() {
+    let mut output = vec![];
+    let result = Writer::new(&mut output).write_element(&Element::Map(vec![(
+        String::from("a"),
+        Element::Marker(String::from("name")),
+    )]));
+    assert!(matches!(result, Err(tpk::write::Error::NestedMarker)));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_write_flagged_marker() {
+    let mut output = vec![];
+    let result = Writer::new(&mut output).write_element(&Element::Flagged {
+        flags: 0,
+        inner: Box::new(Element::Marker(String::from("name"))),
+    });
+    assert!(matches!(result, Err(tpk::write::Error::NestedMarker)));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_write_blob_size_boundaries() {
+    for (len, size_bits, size_len) in [
+        (255usize, 0b00u8, 1usize),
+        (256, 0b01, 2),
+        (65535, 0b01, 2),
+        (65536, 0b10, 4),
+    ] {
+        let blob = Element::Blob(vec![42u8; len]);
+        let output = assert_element_write(blob.clone(), 1 + size_len + len);
+        assert_eq!(output[0], 0b00010100u8 | size_bits);
+        assert_eq!(
+            output[1..1 + size_len]
+                .iter()
+                .rev()
+                .fold(0usize, |size, &byte| size << 8 | byte as usize),
+            len
+        );
+        assert_eq!(
+            Reader::new(output.as_slice()).read_element().unwrap(),
+            Some(blob)
+        );
+    }
+}